@@ -33,7 +33,7 @@ macro_rules! num_pack {
                 let bytes = unpacker.read_bytes(PackPointer {
                     offset,
                     len: Self::PACK_BYTES,
-                });
+                })?;
 
                 let bytes = bytes.try_into().unwrap();
 
@@ -55,6 +55,27 @@ num_pack!(i32);
 num_pack!(i64);
 num_pack!(i128);
 
+macro_rules! float_pack {
+    ($ty:ty, $bits:ty) => {
+        impl Pack for $ty {
+            const PACK_BYTES: u32 = <$bits>::PACK_BYTES;
+
+            fn pack(&self, offset: u32, packer: &mut BytePacker) {
+                self.to_bits().pack(offset, packer);
+            }
+        }
+
+        impl<'b> Unpack<'b> for $ty {
+            fn unpack(offset: u32, unpacker: &ByteUnpacker<'b>) -> Option<Self> {
+                Some(Self::from_bits(<$bits>::unpack(offset, unpacker)?))
+            }
+        }
+    };
+}
+
+float_pack!(f32, u32);
+float_pack!(f64, u64);
+
 impl Pack for bool {
     const PACK_BYTES: u32 = 1;
 
@@ -70,7 +91,7 @@ impl<'b> Unpack<'b> for bool {
         let bytes = unpacker.read_bytes(PackPointer {
             offset,
             len: Self::PACK_BYTES,
-        });
+        })?;
 
         let bytes: [u8; 1] = bytes.try_into().unwrap();
 
@@ -336,6 +357,22 @@ impl<'b> Unpack<'b> for chrono::DateTime<Utc> {
     }
 }
 
+impl Pack for chrono::TimeDelta {
+    const PACK_BYTES: u32 = i64::PACK_BYTES;
+
+    fn pack(&self, offset: u32, packer: &mut BytePacker) {
+        self.num_seconds().pack(offset, packer);
+    }
+}
+
+impl<'b> Unpack<'b> for chrono::TimeDelta {
+    fn unpack(offset: u32, unpacker: &ByteUnpacker<'b>) -> Option<Self> {
+        let seconds = i64::unpack(offset, unpacker)?;
+
+        Self::try_seconds(seconds)
+    }
+}
+
 impl Pack for chrono::NaiveDate {
     const PACK_BYTES: u32 = i32::PACK_BYTES;
 
@@ -43,7 +43,7 @@ impl<'b> Unpack<'b> for PackPointer {
         let bytes = unpacker.read_bytes(PackPointer {
             offset,
             len: Self::PACK_BYTES,
-        });
+        })?;
 
         let ptr = <[u8; 8]>::try_from(bytes).unwrap();
         let [offset_bytes, len_bytes] = unsafe { transmute::<_, [[u8; 4]; 2]>(ptr) };
@@ -9,13 +9,14 @@ impl<'b> ByteUnpacker<'b> {
         Self { bytes }
     }
 
-    pub fn read_bytes(&self, ptr: PackPointer) -> &'b [u8] {
+    /// Returns `None` if `ptr` points outside of the bytes.
+    pub fn read_bytes(&self, ptr: PackPointer) -> Option<&'b [u8]> {
         read_fixed_value(self.bytes.as_ref(), ptr.offset, ptr.len)
     }
 
     pub fn read_indirect(&self, offset: u32) -> Option<&'b [u8]> {
         let ptr = PackPointer::unpack(offset, &self)?;
-        Some(self.read_bytes(ptr))
+        self.read_bytes(ptr)
     }
 
     pub fn fields<'f, F>(&self, format: &'f F, offset: u32) -> FieldUnpacker<'b, 'f, F> {
@@ -27,8 +28,11 @@ impl<'b> ByteUnpacker<'b> {
     }
 }
 
-fn read_fixed_value<'a>(bytes: &'a [u8], offset: u32, len: u32) -> &'a [u8] {
-    &bytes[(offset as usize)..(offset as usize + len as usize)]
+fn read_fixed_value<'a>(bytes: &'a [u8], offset: u32, len: u32) -> Option<&'a [u8]> {
+    let start = offset as usize;
+    let end = start.checked_add(len as usize)?;
+
+    bytes.get(start..end)
 }
 
 pub struct FieldUnpacker<'b, 'f, F> {
@@ -0,0 +1,381 @@
+use std::sync::Arc;
+
+use bytepack::{BytePacker, PackFormat};
+use db_core::{
+    defs::table::{AlterTableOp, TableData, TableDef, TableFieldDef},
+    named::Named,
+    record::RecordBytes,
+    value::FieldValue,
+};
+use redb::ReadableTable;
+use ulid::Ulid;
+
+use crate::{
+    Db, DbTransaction,
+    db::{
+        TableWithIdDef,
+        blob_ext::{delete_chunks, external_blob_ids},
+        index_key::IndexTableDef,
+        table_ext::{TABLE_DEF_TABLE, check_table_def},
+    },
+    error::DbError,
+};
+
+impl Db {
+    /// Changes the schema of the table `table_name`.
+    ///
+    /// All stored records are rewritten to the new layout and the indices of the table are rebuilt.
+    pub fn alter_table(
+        &self,
+        table_name: &str,
+        ops: impl IntoIterator<Item = AlterTableOp>,
+    ) -> Result<(), DbError> {
+        self.write(|tx| tx.alter_table(table_name, ops))
+    }
+}
+
+impl DbTransaction<'_> {
+    pub fn alter_table(
+        &self,
+        table_name: &str,
+        ops: impl IntoIterator<Item = AlterTableOp>,
+    ) -> Result<(), DbError> {
+        let table_name: Arc<str> = table_name.into();
+
+        let old_def = {
+            let table_defs = self.tx.open_table(TABLE_DEF_TABLE)?;
+
+            let Some(bytes) = table_defs.get(table_name.as_ref())? else {
+                return Err(DbError::TableDoesNotExist { table: table_name });
+            };
+
+            let Some(table_def) = TableDef::from_stored(bytes.value()) else {
+                return Err(DbError::CorruptTableDef { table: table_name });
+            };
+
+            table_def
+        };
+
+        let plan = AlterPlan::new(&table_name, &old_def, ops)?;
+
+        let new_def = plan.table_def();
+
+        check_table_def(&table_name, &new_def)?;
+
+        let old_data = TableData::from(old_def);
+        let new_data = TableData::from(new_def.clone());
+
+        let records = {
+            let table = self.tx.open_table(TableWithIdDef::new(&table_name))?;
+
+            let mut records = Vec::new();
+
+            for entry in table.iter()? {
+                let (id, value) = entry?;
+
+                records.push(RecordBytes::new(Ulid(id.value()), value.value().to_owned()));
+            }
+
+            records
+        };
+
+        let mut rewritten = Vec::with_capacity(records.len());
+
+        for record in records {
+            let bytes = plan.rewrite(&table_name, &record, &old_data, &new_data)?;
+            let new_record = RecordBytes::new(record.id(), bytes);
+
+            self.check_new_references(&plan, &table_name, &new_data, &new_record)?;
+
+            rewritten.push((record, new_record));
+        }
+
+        {
+            let mut table = self.tx.open_table(TableWithIdDef::new(&table_name))?;
+
+            for (record, new_record) in rewritten {
+                // Contents of removed blob fields are not used anymore
+                let used = external_blob_ids(&new_data, &new_record);
+                let unused = external_blob_ids(&old_data, &record)
+                    .into_iter()
+                    .filter(|id| !used.contains(id));
+
+                delete_chunks(&self.tx, &table_name, record.id(), unused)?;
+
+                table.insert(record.id().0, new_record.bytes())?;
+            }
+        }
+
+        for index in old_data.indices(&table_name) {
+            self.tx
+                .delete_multimap_table(IndexTableDef::new(&index.index_name))?;
+        }
+
+        {
+            let mut table_defs = self.tx.open_table(TABLE_DEF_TABLE)?;
+
+            let bytes = new_def.to_stored();
+
+            table_defs.insert(table_name.as_ref(), &*bytes)?;
+        }
+
+        self.schema_changed.set(true);
+
+        {
+            let mut tables = self.db.inner.tables.write().unwrap();
+
+            tables.remove_table(&table_name);
+            tables.register_tables([Named::new(table_name.clone(), new_def)]);
+        }
+
+        for index in new_data.indices(&table_name) {
+            self.db.index_rebuild(&self.tx, &index.index_name)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl DbTransaction<'_> {
+    /// Added and retyped fields get new values, their references must point to existing records.
+    ///
+    /// Indices are rebuilt without this check, see `Db::index_rebuild`.
+    fn check_new_references(
+        &self,
+        plan: &AlterPlan,
+        table_name: &Arc<str>,
+        new_data: &TableData,
+        record: &RecordBytes,
+    ) -> Result<(), DbError> {
+        for field in plan.fields.iter().filter(|field| field.default.is_some()) {
+            let Some(field_data) = new_data.field(&field.name) else {
+                continue;
+            };
+
+            let value = record
+                .get_field(field_data)
+                .ok_or_else(|| DbError::CorruptRecord {
+                    table: table_name.clone(),
+                    record: record.id(),
+                })?;
+
+            let values = match value {
+                FieldValue::List { values, .. } => values,
+                value => vec![value],
+            };
+
+            for value in values {
+                if let FieldValue::RecordId { id, table_name } = value
+                    && !self
+                        .db
+                        .record_exists(self.tx.open_table(TableWithIdDef::new(&table_name))?, id)?
+                {
+                    return Err(DbError::RecordDoesNotExist {
+                        table: table_name,
+                        record: id,
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct AlterPlan {
+    fields: Vec<FieldPlan>,
+    main_display_field: Option<Arc<str>>,
+    unique: Vec<Vec<Arc<str>>>,
+    indices: Vec<Vec<Arc<str>>>,
+}
+
+struct FieldPlan {
+    name: Arc<str>,
+    field: TableFieldDef,
+    /// The name of the field in the old table layout
+    source: Option<Arc<str>>,
+    /// Used if there is no source value or the source value can not be cast to the new type
+    default: Option<FieldValue>,
+}
+
+impl AlterPlan {
+    fn new(
+        table_name: &Arc<str>,
+        table: &TableDef,
+        ops: impl IntoIterator<Item = AlterTableOp>,
+    ) -> Result<Self, DbError> {
+        let mut this = Self {
+            fields: table
+                .fields
+                .iter()
+                .map(|Named { name, value: field }| FieldPlan {
+                    name: name.clone(),
+                    field: field.clone(),
+                    source: Some(name.clone()),
+                    default: None,
+                })
+                .collect(),
+            main_display_field: table
+                .main_display_field
+                .and_then(|idx| table.fields.get(idx as usize))
+                .map(|field| field.name.clone()),
+            unique: table.unique.clone(),
+            indices: table.indices.clone(),
+        };
+
+        for op in ops {
+            match op {
+                AlterTableOp::AddField {
+                    name,
+                    field,
+                    default,
+                } => {
+                    this.ensure_unused(table_name, &name)?;
+
+                    if !field.ty.accepts(&default) {
+                        return Err(DbError::WrongType { expected: field.ty });
+                    }
+
+                    this.fields.push(FieldPlan {
+                        name,
+                        field,
+                        source: None,
+                        default: Some(default),
+                    });
+                }
+                AlterTableOp::DropField { name } => {
+                    let idx = this.position(table_name, &name)?;
+
+                    this.fields.remove(idx);
+
+                    if this.main_display_field.as_ref() == Some(&name) {
+                        this.main_display_field = None;
+                    }
+
+                    this.unique.retain(|fields| !fields.contains(&name));
+                    this.indices.retain(|fields| !fields.contains(&name));
+                }
+                AlterTableOp::RenameField { name, new_name } => {
+                    this.ensure_unused(table_name, &new_name)?;
+                    let idx = this.position(table_name, &name)?;
+
+                    this.fields[idx].name = new_name.clone();
+
+                    for field in this.unique.iter_mut().chain(&mut this.indices).flatten() {
+                        if field == &name {
+                            *field = new_name.clone();
+                        }
+                    }
+
+                    if this.main_display_field.as_ref() == Some(&name) {
+                        this.main_display_field = Some(new_name);
+                    }
+                }
+                AlterTableOp::RetypeField { name, ty, default } => {
+                    let idx = this.position(table_name, &name)?;
+
+                    if !ty.accepts(&default) {
+                        return Err(DbError::WrongType { expected: ty });
+                    }
+
+                    let field = &mut this.fields[idx];
+
+                    field.field.ty = ty;
+                    field.default = Some(default);
+                }
+                AlterTableOp::MoveField { name, index } => {
+                    let idx = this.position(table_name, &name)?;
+
+                    let field = this.fields.remove(idx);
+                    let index = index.min(this.fields.len());
+
+                    this.fields.insert(index, field);
+                }
+                AlterTableOp::SetMainDisplayField { name } => {
+                    if let Some(name) = &name {
+                        this.position(table_name, name)?;
+                    }
+
+                    this.main_display_field = name;
+                }
+            }
+        }
+
+        Ok(this)
+    }
+
+    fn position(&self, table_name: &Arc<str>, name: &Arc<str>) -> Result<usize, DbError> {
+        self.fields
+            .iter()
+            .position(|field| &field.name == name)
+            .ok_or_else(|| DbError::FieldDoesNotExist {
+                table: table_name.clone(),
+                field: name.clone(),
+            })
+    }
+
+    fn ensure_unused(&self, table_name: &Arc<str>, name: &Arc<str>) -> Result<(), DbError> {
+        if self.fields.iter().any(|field| &field.name == name) {
+            Err(DbError::FieldAlreadyExists {
+                table: table_name.clone(),
+                field: name.clone(),
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    fn table_def(&self) -> TableDef {
+        TableDef {
+            fields: self
+                .fields
+                .iter()
+                .map(|field| Named::new(field.name.clone(), field.field.clone()))
+                .collect(),
+            main_display_field: self.main_display_field.as_ref().and_then(|name| {
+                self.fields
+                    .iter()
+                    .position(|field| &field.name == name)
+                    .map(|idx| idx as u32)
+            }),
+            unique: self.unique.clone(),
+            indices: self.indices.clone(),
+        }
+    }
+
+    fn rewrite(
+        &self,
+        table_name: &Arc<str>,
+        record: &RecordBytes,
+        old_data: &TableData,
+        new_data: &TableData,
+    ) -> Result<Vec<u8>, DbError> {
+        let mut packer = BytePacker::new(new_data.fixed_byte_count());
+
+        for (field, new_field) in self.fields.iter().zip(new_data.fields()) {
+            let source_value = match field.source.as_ref().and_then(|source| old_data.field(source)) {
+                Some(source) => Some(record.get_field(source).ok_or_else(|| {
+                    DbError::CorruptRecord {
+                        table: table_name.clone(),
+                        record: record.id(),
+                    }
+                })?),
+                None => None,
+            };
+
+            let value = source_value
+                .and_then(|value| value.cast(&field.field.ty))
+                .or_else(|| field.default.clone());
+
+            let Some(value) = value else {
+                return Err(DbError::WrongType {
+                    expected: field.field.ty.clone(),
+                });
+            };
+
+            value.pack_as(&new_field.value.ty, new_field.value.offset, &mut packer);
+        }
+
+        Ok(packer.finish())
+    }
+}
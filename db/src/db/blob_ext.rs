@@ -0,0 +1,407 @@
+//! Contents of blob fields that are too large to be stored in their record.
+//!
+//! They are split into chunks in the table `$blobs:<table name>`, keyed by the record id,
+//! the id of the content and the position of the chunk.
+
+use std::{
+    io::{self, Read},
+    sync::Arc,
+};
+
+use bytepack::{BytePacker, PackFormat};
+use db_core::{
+    blob::{Blob, BlobContent},
+    defs::table::{TableData, TableFieldData},
+    named::Named,
+    record::RecordBytes,
+    ty::FieldTy,
+    value::FieldValue,
+};
+use redb::{ReadOnlyTable, ReadableDatabase, TableDefinition, WriteTransaction};
+use ulid::Ulid;
+
+use crate::{Db, DbTransaction, db::TableWithIdDef, error::DbError};
+
+pub(super) type BlobTableDef<'a> = TableDefinition<'a, (u128, u128, u32), &'static [u8]>;
+type BlobChunks = ReadOnlyTable<(u128, u128, u32), &'static [u8]>;
+
+/// Larger contents are stored outside of the record
+const MAX_INLINE_BYTES: usize = 4 * 1024;
+
+const CHUNK_BYTES: usize = 64 * 1024;
+
+pub(super) fn blob_table_name(table_name: &str) -> String {
+    format!("$blobs:{table_name}")
+}
+
+impl Db {
+    /// Streams `reader` into the blob field `field_name` of the record `record_id`, see [`DbTransaction::write_blob`].
+    pub fn write_blob(
+        &self,
+        table_name: &str,
+        record_id: Ulid,
+        field_name: &str,
+        mime: &str,
+        reader: impl Read,
+    ) -> Result<(), DbError> {
+        self.write(|tx| tx.write_blob(table_name, record_id, field_name, mime, reader))
+    }
+
+    /// The content of the blob field `field_name` of the record `record_id`, `None` if the field is null.
+    ///
+    /// External content is read chunk by chunk from a snapshot taken when this is called.
+    pub fn read_blob(
+        &self,
+        table_name: &str,
+        record_id: Ulid,
+        field_name: &str,
+    ) -> Result<Option<BlobReader>, DbError> {
+        let table_data = self.require_table(table_name)?;
+        let field = blob_field(table_name, &table_data, field_name)?;
+
+        let tx = self.inner.db.begin_read()?;
+
+        let record = {
+            let table = tx.open_table(TableWithIdDef::new(table_name))?;
+
+            let Some(value) = table.get(record_id.0)? else {
+                return Err(DbError::RecordDoesNotExist {
+                    table: table_name.into(),
+                    record: record_id,
+                });
+            };
+
+            RecordBytes::new(record_id, value.value().to_owned())
+        };
+
+        let blob = match record.get_field(field) {
+            Some(FieldValue::Blob(blob)) => blob,
+            Some(FieldValue::Null(_)) => return Ok(None),
+            _ => {
+                return Err(DbError::CorruptRecord {
+                    table: table_name.into(),
+                    record: record_id,
+                });
+            }
+        };
+
+        let len = blob.len();
+
+        let (chunk, external) = match blob.content {
+            BlobContent::Inline(bytes) => (bytes, None),
+            BlobContent::External { id, .. } => {
+                let chunks = tx.open_table(BlobTableDef::new(&blob_table_name(table_name)))?;
+
+                (Vec::new(), Some((chunks, record_id.0, id.0)))
+            }
+        };
+
+        Ok(Some(BlobReader {
+            mime: blob.mime,
+            len,
+            read: 0,
+            chunk,
+            pos: 0,
+            external,
+            next_chunk: 0,
+        }))
+    }
+
+    /// Moves inline contents larger than `MAX_INLINE_BYTES` out of `record`, so records stay small.
+    ///
+    /// Returns the record with the moved contents replaced, or `None` if nothing was moved.
+    pub(super) fn store_large_blobs(
+        &self,
+        table_name: &str,
+        record: &RecordBytes,
+        tx: &WriteTransaction,
+    ) -> Result<Option<RecordBytes>, DbError> {
+        let table_data = self.require_table(table_name)?;
+
+        if !table_data
+            .fields()
+            .any(|field| field.value.ty.non_null() == &FieldTy::Blob)
+        {
+            return Ok(None);
+        }
+
+        let mut moved = false;
+        let mut values = Vec::new();
+
+        for Named { value: field, .. } in table_data.fields() {
+            let value = match record.get_field(field) {
+                Some(FieldValue::Blob(Blob {
+                    mime,
+                    content: BlobContent::Inline(bytes),
+                })) if bytes.len() > MAX_INLINE_BYTES => {
+                    moved = true;
+
+                    let content = write_chunks(tx, table_name, record.id(), bytes.as_slice())?;
+
+                    FieldValue::Blob(Blob { mime, content })
+                }
+                Some(value) => value,
+                None => {
+                    return Err(DbError::CorruptRecord {
+                        table: table_name.into(),
+                        record: record.id(),
+                    });
+                }
+            };
+
+            values.push((field, value));
+        }
+
+        if !moved {
+            return Ok(None);
+        }
+
+        let mut packer = BytePacker::new(table_data.fixed_byte_count());
+
+        for (field, value) in values {
+            value.pack_as(&field.ty, field.offset, &mut packer);
+        }
+
+        Ok(Some(RecordBytes::new(record.id(), packer.finish())))
+    }
+
+    /// Deletes the external contents of `old_record` that `new_record` does not use anymore,
+    /// all of them if the record was deleted.
+    pub(super) fn delete_unused_blobs(
+        &self,
+        table_name: &str,
+        old_record: &RecordBytes,
+        new_record: Option<&RecordBytes>,
+        tx: &WriteTransaction,
+    ) -> Result<(), DbError> {
+        let table_data = self.require_table(table_name)?;
+
+        let used = new_record
+            .map(|record| external_blob_ids(&table_data, record))
+            .unwrap_or_default();
+
+        let unused = external_blob_ids(&table_data, old_record)
+            .into_iter()
+            .filter(|id| !used.contains(id));
+
+        delete_chunks(tx, table_name, old_record.id(), unused)
+    }
+}
+
+impl DbTransaction<'_> {
+    /// Streams `reader` into the blob field `field_name` of the record `record_id`, replacing its content.
+    ///
+    /// Small contents are stored in the record, larger ones in chunks outside of it,
+    /// so the whole content is never held in memory.
+    pub fn write_blob(
+        &self,
+        table_name: &str,
+        record_id: Ulid,
+        field_name: &str,
+        mime: &str,
+        mut reader: impl Read,
+    ) -> Result<(), DbError> {
+        let table_data = self.db.require_table(table_name)?;
+        blob_field(table_name, &table_data, field_name)?;
+
+        if self.get(table_name, record_id)?.is_none() {
+            return Err(DbError::RecordDoesNotExist {
+                table: table_name.into(),
+                record: record_id,
+            });
+        }
+
+        let first_chunk = read_chunk(&mut reader)?;
+
+        let content = if first_chunk.len() <= MAX_INLINE_BYTES {
+            BlobContent::Inline(first_chunk)
+        } else {
+            write_chunks(
+                &self.tx,
+                table_name,
+                record_id,
+                first_chunk.as_slice().chain(reader),
+            )?
+        };
+
+        let blob = Blob {
+            mime: mime.into(),
+            content,
+        };
+
+        self.db.patch_record(
+            table_name,
+            record_id,
+            [Named::new(field_name, FieldValue::Blob(blob))],
+            &self.tx,
+        )
+    }
+}
+
+/// Streams the content of a blob field, created by [`Db::read_blob`]
+pub struct BlobReader {
+    mime: Arc<str>,
+    len: u64,
+    /// The number of bytes already read
+    read: u64,
+    /// The current chunk, the whole content if it is stored inline
+    chunk: Vec<u8>,
+    /// The read position in `chunk`
+    pos: usize,
+    /// The chunks of external content with the record id and the content id
+    external: Option<(BlobChunks, u128, u128)>,
+    next_chunk: u32,
+}
+
+impl BlobReader {
+    pub fn mime(&self) -> &str {
+        &self.mime
+    }
+
+    /// The number of bytes of the whole content
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Read for BlobReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.chunk.len() {
+            let Some((chunks, record_id, id)) = &self.external else {
+                return Ok(0);
+            };
+
+            match chunks
+                .get((*record_id, *id, self.next_chunk))
+                .map_err(io::Error::other)?
+            {
+                Some(chunk) => {
+                    self.chunk = chunk.value().to_owned();
+                    self.pos = 0;
+                    self.next_chunk += 1;
+                }
+                None if self.read < self.len => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "blob content is missing chunks",
+                    ));
+                }
+                None => return Ok(0),
+            }
+        }
+
+        let count = buf.len().min(self.chunk.len() - self.pos);
+
+        buf[..count].copy_from_slice(&self.chunk[self.pos..self.pos + count]);
+        self.pos += count;
+        self.read += count as u64;
+
+        Ok(count)
+    }
+}
+
+/// The field `field_name` of `table_data`, which must be a blob field
+fn blob_field<'a>(
+    table_name: &str,
+    table_data: &'a TableData,
+    field_name: &str,
+) -> Result<&'a TableFieldData, DbError> {
+    let Some(field) = table_data.field(field_name) else {
+        return Err(DbError::FieldDoesNotExist {
+            table: table_name.into(),
+            field: field_name.into(),
+        });
+    };
+
+    if field.ty.non_null() != &FieldTy::Blob {
+        return Err(DbError::WrongType {
+            expected: FieldTy::Blob,
+        });
+    }
+
+    Ok(field)
+}
+
+/// Reads `CHUNK_BYTES` bytes, fewer only at the end of `reader`
+fn read_chunk(reader: &mut impl Read) -> Result<Vec<u8>, DbError> {
+    let mut chunk = Vec::new();
+
+    reader
+        .take(CHUNK_BYTES as u64)
+        .read_to_end(&mut chunk)
+        .map_err(|err| DbError::BlobIo { err })?;
+
+    Ok(chunk)
+}
+
+/// Stores the content of `reader` in chunks under a new content id
+fn write_chunks(
+    tx: &WriteTransaction,
+    table_name: &str,
+    record_id: Ulid,
+    mut reader: impl Read,
+) -> Result<BlobContent, DbError> {
+    let id = Ulid::new();
+
+    let mut chunks = tx.open_table(BlobTableDef::new(&blob_table_name(table_name)))?;
+
+    let mut len = 0;
+    let mut idx = 0;
+
+    loop {
+        let chunk = read_chunk(&mut reader)?;
+
+        if chunk.is_empty() {
+            break;
+        }
+
+        chunks.insert((record_id.0, id.0, idx), chunk.as_slice())?;
+
+        len += chunk.len() as u64;
+        idx += 1;
+    }
+
+    Ok(BlobContent::External { id, len })
+}
+
+/// The ids of the external contents of `record`
+pub(super) fn external_blob_ids(table_data: &TableData, record: &RecordBytes) -> Vec<Ulid> {
+    table_data
+        .fields()
+        .filter_map(|field| match record.get_field(&field.value) {
+            Some(FieldValue::Blob(Blob {
+                content: BlobContent::External { id, .. },
+                ..
+            })) => Some(id),
+            _ => None,
+        })
+        .collect()
+}
+
+pub(super) fn delete_chunks(
+    tx: &WriteTransaction,
+    table_name: &str,
+    record_id: Ulid,
+    ids: impl IntoIterator<Item = Ulid>,
+) -> Result<(), DbError> {
+    let mut ids = ids.into_iter().peekable();
+
+    if ids.peek().is_none() {
+        return Ok(());
+    }
+
+    let mut chunks = tx.open_table(BlobTableDef::new(&blob_table_name(table_name)))?;
+
+    for id in ids {
+        chunks.retain_in(
+            (record_id.0, id.0, 0)..=(record_id.0, id.0, u32::MAX),
+            |_, _| false,
+        )?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use std::{collections::HashSet, sync::Arc};
+
+use db_core::{
+    expr::{EvalErr, Expr, TyCtx},
+    query::Query,
+    ty::{FieldTy, Ty},
+};
+
+use crate::{
+    Db,
+    db::{plan_ext::check_query_tables, table_ext::DbTables},
+    error::DbError,
+};
+
+impl Db {
+    /// Checks the types of every clause of `query` against the registered tables.
+    ///
+    /// `run_query` refuses queries that fail this check.
+    pub fn check_query(&self, query: &Query) -> Result<(), DbError> {
+        let tables = self.inner.tables.read().unwrap();
+
+        check_query(&tables, query)?;
+
+        Ok(())
+    }
+}
+
+/// Join conditions and the filter must be `Bool`, sort keys must be field values
+/// and aggregate functions over the records of the query are only allowed in `select`.
+///
+/// Returns the context the query was checked with.
+pub(super) fn check_query(tables: &DbTables, query: &Query) -> Result<TyCtx, DbError> {
+    check_query_tables(tables, query)?;
+
+    let mut ctx = TyCtx {
+        tables: tables
+            .tables
+            .iter()
+            .map(|(name, table)| (name.clone(), Arc::new(table.clone())))
+            .collect(),
+        scope: HashSet::from([query.table_name.clone()]),
+        ..Default::default()
+    };
+
+    let invalid = |clause: String| {
+        move |err| DbError::InvalidQuery {
+            clause: clause.into(),
+            err,
+        }
+    };
+
+    // The condition of a join can only access the tables joined before
+    for join in &query.joins {
+        ctx.scope.insert(join.table_name.clone());
+
+        check_condition(&ctx, &join.on).map_err(invalid(format!("join {}", join.table_name)))?;
+    }
+
+    if let Some(filter) = &query.filter {
+        check_condition(&ctx, filter).map_err(invalid("filter".to_owned()))?;
+    }
+
+    if let Some(group_by) = &query.group_by {
+        check_row_expr(&ctx, group_by).map_err(invalid("group_by".to_owned()))?;
+    }
+
+    for expr in &query.select {
+        expr.value
+            .ty(&ctx)
+            .map_err(invalid(format!("select {}", expr.name)))?;
+    }
+
+    for key in &query.order_by {
+        check_sort_key(&ctx, &key.expr).map_err(invalid(format!("order_by {}", key.expr)))?;
+    }
+
+    Ok(ctx)
+}
+
+fn check_condition(ctx: &TyCtx, expr: &Expr) -> Result<(), EvalErr> {
+    match check_row_expr(ctx, expr)? {
+        Ty::Field(ty) if ty.non_null() == &FieldTy::Bool => Ok(()),
+        found => Err(EvalErr::MissmatchedTypes {
+            found,
+            expected: Some(FieldTy::Bool.into()),
+        }),
+    }
+}
+
+/// Records can not be sorted, only field values
+fn check_sort_key(ctx: &TyCtx, expr: &Expr) -> Result<(), EvalErr> {
+    match check_row_expr(ctx, expr)? {
+        Ty::Field(_) => Ok(()),
+        found => Err(EvalErr::MissmatchedTypes {
+            found,
+            expected: None,
+        }),
+    }
+}
+
+/// The type of an expression that is evaluated for single rows of the query
+fn check_row_expr(ctx: &TyCtx, expr: &Expr) -> Result<Ty, EvalErr> {
+    if let Some(func) = expr.aggregate() {
+        return Err(EvalErr::AggregateOutsideSelect { func });
+    }
+
+    expr.ty(ctx)
+}
@@ -1,177 +1,287 @@
+use std::ops::Bound;
 
-use bytepack::PackFormat;
-use chrono::{DateTime, Utc};
 use db_core::{
-    defs::index::IndexOnDelete,
+    defs::{
+        index::{IndexDef, IndexOnDelete},
+        table::TableFieldData,
+    },
+    named::Named,
     record::RecordBytes,
-    ty::FieldTy, value::FieldValue,
+    ty::FieldTy,
+    value::FieldValue,
+};
+use redb::{
+    ReadTransaction, ReadableDatabase, ReadableMultimapTable, ReadableTable, TableError,
+    WriteTransaction,
 };
-use redb::{MultimapTableDefinition, ReadableDatabase, ReadableMultimapTable, WriteTransaction};
 use ulid::Ulid;
 
 use crate::{
     Db,
-    db::TableWithIdDef,
+    db::{
+        TableWithIdDef,
+        index_key::{IndexKey, IndexTableDef, decode_key, encode_key, encode_text, prefix_end},
+    },
     error::DbError,
 };
 
 impl Db {
-    pub fn index_insert(
+    pub(super) fn index_insert(
         &self,
         tx: &WriteTransaction,
         index_name: &str,
         record: &RecordBytes,
     ) -> Result<(), DbError> {
-        let field = {
-            let guard = self.inner.tables.read().unwrap();
-
-            let index = guard.indices.get(index_name).unwrap();
-
-            let field = guard
-                .table_field(&index.table_name, &index.field_name)
-                .unwrap();
+        let (index, fields) = self.index_fields(index_name)?;
+
+        let keys = index_keys(&index, &fields, record)?;
+
+        for value in keys.iter().flatten() {
+            if let FieldValue::RecordId { id, table_name } = value
+                && !self.record_exists(tx.open_table(TableWithIdDef::new(table_name))?, *id)?
+            {
+                return Err(DbError::RecordDoesNotExist {
+                    table: table_name.clone(),
+                    record: *id,
+                });
+            }
+        }
 
-            field.clone()
-        };
+        let tys = field_tys(&fields);
 
-        match field.ty {
-            FieldTy::RecordId { table_name } => {
-                let mut index = tx.open_multimap_table(
-                    MultimapTableDefinition::<'_, u128, u128>::new(index_name),
-                )?;
-
-                let field_value = record.unpack::<Ulid>(field.offset).unwrap();
-
-                if !self.record_exists(
-                    tx.open_table(TableWithIdDef::new(&table_name))?,
-                    field_value,
-                )? {
-                    return Err(DbError::RecordDoesNotExist {
-                        table: table_name.clone(),
-                        record: field_value,
-                    });
-                }
+        let mut index_table = tx.open_multimap_table(IndexTableDef::new(index_name))?;
 
-                index.insert(field_value.0, record.id().0)?;
-            }
-            FieldTy::Timestamp => {
-                let field_value = record.unpack::<DateTime<Utc>>(field.offset).unwrap();
+        for values in &keys {
+            let Some(key) = encode_key(&tys, values) else {
+                continue;
+            };
 
-                let mut index = tx.open_multimap_table(
-                    MultimapTableDefinition::<'_, i64, u128>::new(index_name),
-                )?;
+            check_unique(&index, &index_table, &key, values, record.id())?;
 
-                index.insert(field_value.timestamp(), record.id().0)?;
-            }
-            _ => todo!("index for ty {:?} not yet implemented", field.ty),
+            index_table.insert(key.as_slice(), record.id().0)?;
         }
 
         Ok(())
     }
 
-    pub fn index_delete_value(
+    pub(super) fn index_update(
         &self,
         tx: &WriteTransaction,
         index_name: &str,
-        record: &RecordBytes,
+        old_record: &RecordBytes,
+        new_record: &RecordBytes,
     ) -> Result<(), DbError> {
-        let field = {
-            let guard = self.inner.tables.read().unwrap();
-
-            let index = guard.indices.get(index_name).unwrap();
-
-            let field = guard
-                .table_field(&index.table_name, &index.field_name)
-                .unwrap();
+        let (index, fields) = self.index_fields(index_name)?;
 
-            field.clone()
-        };
-
-        match &field.ty {
-            FieldTy::RecordId { .. } => {
-                let value = record.unpack::<Ulid>(field.offset).unwrap();
+        if index_keys(&index, &fields, old_record)? == index_keys(&index, &fields, new_record)? {
+            return Ok(());
+        }
 
-                let mut index_table =
-                    tx.open_multimap_table(MultimapTableDefinition::<'_, u128, u128>::new(
-                        index_name,
-                    ))?;
+        self.index_delete_value(tx, index_name, old_record)?;
+        self.index_insert(tx, index_name, new_record)
+    }
 
-                index_table.remove(value.0, record.id().0)?;
+    pub(super) fn index_delete_value(
+        &self,
+        tx: &WriteTransaction,
+        index_name: &str,
+        record: &RecordBytes,
+    ) -> Result<(), DbError> {
+        let (index, fields) = self.index_fields(index_name)?;
 
-                drop(index_table);
-            }
-            FieldTy::Timestamp => {
-                let field_value = record.unpack::<DateTime<Utc>>(field.offset).unwrap();
+        let tys = field_tys(&fields);
 
-                let mut index = tx.open_multimap_table(
-                    MultimapTableDefinition::<'_, i64, u128>::new(index_name),
-                )?;
+        let mut index_table = tx.open_multimap_table(IndexTableDef::new(index_name))?;
 
-                index.remove(field_value.timestamp(), record.id().0)?;
+        for values in index_keys(&index, &fields, record)? {
+            if let Some(key) = encode_key(&tys, &values) {
+                index_table.remove(key.as_slice(), record.id().0)?;
             }
-            _ => todo!(),
         }
 
         Ok(())
     }
 
-    pub fn index_delete_key(
+    pub(super) fn index_delete_key(
         &self,
         tx: &WriteTransaction,
         index_name: &str,
         id: &Ulid,
     ) -> Result<(), DbError> {
-        let (table_field_ty, index) = {
-            let guard = self.inner.tables.read().unwrap();
+        let (index, fields) = self.index_fields(index_name)?;
+
+        let ([field_name], [table_field]) = (index.field_names.as_slice(), fields.as_slice())
+        else {
+            return Err(DbError::UnsupportedIndexType {
+                index: index_name.into(),
+                ty: fields[0].ty.clone(),
+            });
+        };
+
+        let table_field_ty = &table_field.ty;
+        let key_tys = field_tys(&fields);
 
-            let index = guard.indices.get(index_name).unwrap();
+        let FieldTy::RecordId { table_name } = key_tys[0].non_null() else {
+            return Err(DbError::UnsupportedIndexType {
+                index: index_name.into(),
+                ty: table_field_ty.clone(),
+            });
+        };
 
-            let table = guard.tables.get(&index.table_name).unwrap();
-            let table_field = table.field(&index.field_name).unwrap();
+        let value = FieldValue::RecordId {
+            id: *id,
+            table_name: table_name.clone(),
+        };
 
-            (table_field.ty.clone(), index.clone())
+        let Some(key) = encode_key(&key_tys, &[value]) else {
+            return Ok(());
         };
+        let on_delete = &index.on_delete;
 
-        match &table_field_ty {
-            FieldTy::RecordId { .. } => {
-                let on_delete = &index.on_delete;
+        let mut index_table = tx.open_multimap_table(IndexTableDef::new(index_name))?;
 
-                let mut index_table =
-                    tx.open_multimap_table(MultimapTableDefinition::<'_, u128, u128>::new(
-                        index_name,
-                    ))?;
+        let mut referencing_ids = Vec::new();
 
+        if let IndexOnDelete::Restrict = on_delete
+            && let Some(referenced_by) = index_table.get(key.as_slice())?.next()
+        {
+            return Err(DbError::RecordIsReferenced {
+                table: table_name.clone(),
+                record: *id,
+                referenced_by_table: index.table_name.clone(),
+                referenced_by: Ulid(referenced_by?.value()),
+            });
+        }
+
+        for referencing_id in index_table.remove_all(key.as_slice())? {
+            referencing_ids.push(Ulid(referencing_id?.value()));
+        }
+
+        drop(index_table);
+
+        match on_delete {
+            IndexOnDelete::Cascase => {
                 let mut delete_emit = Vec::new();
 
-                match on_delete {
-                    IndexOnDelete::Cascase => {
-                        for id in index_table.remove_all(&id.0)? {
-                            let mut table =
-                                tx.open_table(TableWithIdDef::new(&index.table_name))?;
-                            let id = id?.value();
-                            let value = table.remove(id)?.unwrap();
+                {
+                    let mut table = tx.open_table(TableWithIdDef::new(&index.table_name))?;
 
-                            // TODO  Emit a new delete event for chained data
-                            let bytes = value.value().to_owned();
+                    for id in referencing_ids {
+                        let Some(value) = table.remove(id.0)? else {
+                            return Err(DbError::RecordDoesNotExist {
+                                table: index.table_name.clone(),
+                                record: id,
+                            });
+                        };
 
-                            delete_emit.push(RecordBytes::new(Ulid(id), bytes));
-                        }
-                    }
-                    IndexOnDelete::SetNone => {
-                        unimplemented!()
-                    }
-                    IndexOnDelete::None => {
-                        panic!("Invalid on delete for record")
+                        let bytes = value.value().to_owned();
+
+                        delete_emit.push(RecordBytes::new(id, bytes));
                     }
                 }
 
-                drop(index_table);
-
                 for record in delete_emit {
                     self.emit_delete(&index.table_name, &record, tx)?;
                 }
             }
-            _ => todo!(),
+            IndexOnDelete::SetNone => {
+                for referencing_id in referencing_ids {
+                    let value = match table_field_ty.element() {
+                        // Lists keep their other elements
+                        Some(_) => {
+                            let table = tx.open_table(TableWithIdDef::new(&index.table_name))?;
+
+                            let value = table
+                                .get(referencing_id.0)?
+                                .map(|value| {
+                                    RecordBytes::new(referencing_id, value.value().to_owned())
+                                })
+                                .and_then(|record| record.get_field(table_field));
+
+                            let Some(FieldValue::List { ty, values }) = value else {
+                                return Err(DbError::CorruptRecord {
+                                    table: index.table_name.clone(),
+                                    record: referencing_id,
+                                });
+                            };
+
+                            let values = values
+                                .into_iter()
+                                .filter(|value| {
+                                    !matches!(
+                                        value,
+                                        FieldValue::RecordId { id: value_id, .. } if value_id == id
+                                    )
+                                })
+                                .collect();
+
+                            FieldValue::List { ty, values }
+                        }
+                        None => FieldValue::Null(table_field_ty.non_null().clone()),
+                    };
+
+                    self.patch_record(
+                        &index.table_name,
+                        referencing_id,
+                        [Named::new(field_name.clone(), value)],
+                        tx,
+                    )?;
+                }
+            }
+            IndexOnDelete::Restrict | IndexOnDelete::None => (),
+        }
+
+        Ok(())
+    }
+
+    /// Recreates the index `index_name` from the records of its table.
+    ///
+    /// Unlike `index_insert` this does not check that referenced records exist,
+    /// the stored records are indexed as they are.
+    pub(super) fn index_rebuild(
+        &self,
+        tx: &WriteTransaction,
+        index_name: &str,
+    ) -> Result<(), DbError> {
+        let (index, fields) = self.index_fields(index_name)?;
+
+        tx.delete_multimap_table(IndexTableDef::new(index_name))?;
+
+        let table = tx.open_table(TableWithIdDef::new(&index.table_name))?;
+        let mut index_table = tx.open_multimap_table(IndexTableDef::new(index_name))?;
+
+        let tys = field_tys(&fields);
+
+        for entry in table.iter()? {
+            let (id, value) = entry?;
+
+            let record = RecordBytes::new(Ulid(id.value()), value.value().to_owned());
+
+            for values in index_keys(&index, &fields, &record)? {
+                if let Some(key) = encode_key(&tys, &values) {
+                    check_unique(&index, &index_table, &key, &values, record.id())?;
+                    index_table.insert(key.as_slice(), record.id().0)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Rebuilds all indices whose table was written with an older key layout.
+    pub(super) fn migrate_indices(&self, tx: &WriteTransaction) -> Result<(), DbError> {
+        let index_names = {
+            let guard = self.inner.tables.read().unwrap();
+
+            guard.indices.keys().cloned().collect::<Vec<_>>()
+        };
+
+        for index_name in index_names {
+            match tx.open_multimap_table(IndexTableDef::new(&index_name)) {
+                Ok(_) => (),
+                Err(TableError::TableTypeMismatch { .. }) => self.index_rebuild(tx, &index_name)?,
+                Err(err) => return Err(err.into()),
+            }
         }
 
         Ok(())
@@ -179,106 +289,293 @@ impl Db {
 }
 
 impl Db {
+    /// Returns all index entries with `min_value <= value < max_value`.
+    ///
+    /// For indices over multiple fields the bounds and the returned values refer to the first field.
     pub fn index_query(
         &self,
         index_name: &str,
         min_value: Option<FieldValue>,
         max_value: Option<FieldValue>,
     ) -> Result<Vec<(FieldValue, Ulid)>, DbError> {
+        let result = self.index_query_composite(index_name, &[], min_value, max_value)?;
+
+        Ok(first_values(result))
+    }
+
+    /// Returns all entries of an index over multiple fields whose first values are equal to `prefix`
+    /// and whose next value is in `min_value <= value < max_value`.
+    ///
+    /// The returned values contain every field of the index.
+    pub fn index_query_composite(
+        &self,
+        index_name: &str,
+        prefix: &[FieldValue],
+        min_value: Option<FieldValue>,
+        max_value: Option<FieldValue>,
+    ) -> Result<Vec<(Vec<FieldValue>, Ulid)>, DbError> {
+        let (index, fields) = self.index_fields(index_name)?;
+
         let tx = self.inner.db.begin_read()?;
 
-        let (table_field_ty, index) = {
-            let guard = self.inner.tables.read().unwrap();
+        self.index_range(
+            &tx,
+            &index,
+            &fields,
+            prefix,
+            min_value.map_or(Bound::Unbounded, Bound::Included),
+            max_value.map_or(Bound::Unbounded, Bound::Excluded),
+        )
+    }
 
-            let index = guard.indices.get(index_name).unwrap();
+    /// Returns all entries of `index` whose first values are equal to `prefix`
+    /// and whose next value is within `lower` and `upper`.
+    pub(super) fn index_range(
+        &self,
+        tx: &ReadTransaction,
+        index: &IndexDef,
+        fields: &[TableFieldData],
+        prefix: &[FieldValue],
+        lower: Bound<FieldValue>,
+        upper: Bound<FieldValue>,
+    ) -> Result<Vec<(Vec<FieldValue>, Ulid)>, DbError> {
+        let has_range = !matches!((&lower, &upper), (Bound::Unbounded, Bound::Unbounded));
+        let field_count = prefix.len() + has_range as usize;
+
+        if field_count > fields.len() {
+            return Err(DbError::IndexFieldCount {
+                index: index.index_name.clone(),
+                expected: fields.len(),
+                found: field_count,
+            });
+        }
 
-            let table = guard.tables.get(&index.table_name).unwrap();
-            let table_field = table.field(&index.field_name).unwrap();
+        let tys = field_tys(fields);
 
-            (table_field.ty.clone(), index.clone())
+        for (ty, value) in tys.iter().zip(prefix) {
+            if !ty.accepts(value) {
+                return Err(DbError::WrongType {
+                    expected: ty.clone(),
+                });
+            }
+        }
+
+        // Null values of fields that are not nullable are not indexed
+        let Some(mut prefix_key) = encode_key(&tys, prefix) else {
+            return Ok(Vec::new());
         };
 
-        match table_field_ty {
-            FieldTy::RecordId { .. } => {
-                let index_table =
-                    tx.open_multimap_table(MultimapTableDefinition::<'_, u128, u128>::new(
-                        index_name,
-                    ))?;
+        let encode_bound = |value: FieldValue| {
+            let field_ty = tys[prefix.len()].non_null();
 
-                let mut result = Vec::new();
+            if !field_ty.accepts(&value) {
+                return Err(DbError::WrongType {
+                    expected: field_ty.clone(),
+                });
+            }
 
-                for key in index_table.iter()? {
-                    let (key, values) = key?;
+            let values = prefix.iter().cloned().chain([value]).collect::<Vec<_>>();
 
-                    let key_id = Ulid::from(key.value());
+            Ok(encode_key(&tys, &values))
+        };
 
-                    for value in values {
-                        let value = value?.value();
+        // Null values are not within any range, they are the keys with `00` after the prefix
+        if has_range && tys[prefix.len()].is_nullable() {
+            prefix_key.push(0x01);
+        }
 
-                        let value = Ulid::from(value);
+        // All keys of a value start with its encoding, so everything up to `prefix_end` belongs to it
+        let lower = match lower {
+            Bound::Included(value) => encode_bound(value)?.map(Bound::Included),
+            Bound::Excluded(value) => encode_bound(value)?.map(|key| prefix_end(&key)),
+            Bound::Unbounded => Some(Bound::Included(prefix_key.clone())),
+        };
+        let upper = match upper {
+            Bound::Included(value) => encode_bound(value)?.map(|key| prefix_end(&key)),
+            Bound::Excluded(value) => encode_bound(value)?.map(Bound::Excluded),
+            Bound::Unbounded => Some(prefix_end(&prefix_key)),
+        };
 
-                        result.push((FieldValue::RecordId {
-                            id: key_id,
-                            table_name: index.table_name.clone(),
-                        }, value));
-                    }
-                }
+        let (lower, upper) = match (lower, upper) {
+            (Some(Bound::Excluded(lower)), Some(upper)) => (Bound::Included(lower), upper),
+            (Some(Bound::Included(lower)), Some(upper)) => (Bound::Included(lower), upper),
+            // Null bounds and exclusive bounds above the largest key match nothing
+            _ => return Ok(Vec::new()),
+        };
 
-                Ok(result)
-            }
-            FieldTy::Timestamp => {
-                let index_table = tx.open_multimap_table(
-                    MultimapTableDefinition::<'_, i64, u128>::new(index_name),
-                )?;
-
-                let min_value = match min_value {
-                    Some(FieldValue::Timestamp(value)) => Some(value),
-                    Some(_) => {
-                        return Err(DbError::WrongType {
-                            expected: FieldTy::Timestamp,
-                        });
-                    }
-                    None => None,
-                };
+        self.index_scan(tx, index, fields, lower, upper)
+    }
 
-                let max_value = match max_value {
-                    Some(FieldValue::Timestamp(value)) => Some(value),
-                    Some(_) => {
-                        return Err(DbError::WrongType {
-                            expected: FieldTy::Timestamp,
-                        });
-                    }
-                    None => None,
-                };
+    /// Returns all index entries of a `Text` index whose value starts with `prefix`.
+    pub fn index_query_prefix(
+        &self,
+        index_name: &str,
+        prefix: &str,
+    ) -> Result<Vec<(FieldValue, Ulid)>, DbError> {
+        let (index, fields) = self.index_fields(index_name)?;
+        let tys = field_tys(&fields);
+
+        if tys[0].non_null() != &FieldTy::Text {
+            return Err(DbError::UnsupportedIndexType {
+                index: index_name.into(),
+                ty: fields[0].ty.clone(),
+            });
+        }
 
-                let iter = match (min_value, max_value) {
-                    (None, None) => index_table.iter()?,
-                    (None, Some(max)) => index_table.range(..max.timestamp())?,
-                    (Some(min), None) => index_table.range(min.timestamp()..)?,
-                    (Some(min), Some(max)) => {
-                        index_table.range(min.timestamp()..max.timestamp())?
-                    }
-                };
+        let mut start = Vec::new();
 
-                let mut result = Vec::new();
+        if tys[0].is_nullable() {
+            start.push(0x01);
+        }
 
-                for key in iter {
-                    let (key, values) = key?;
+        encode_text(prefix, &mut start);
 
-                    let key = DateTime::from_timestamp(key.value(), 0).unwrap();
+        let end = prefix_end(&start);
 
-                    for value in values {
-                        let value = value?.value();
+        let tx = self.inner.db.begin_read()?;
 
-                        let value = Ulid::from(value);
+        let result = self.index_scan(&tx, &index, &fields, Bound::Included(start), end)?;
 
-                        result.push((FieldValue::Timestamp(key), value));
-                    }
-                }
+        Ok(first_values(result))
+    }
 
-                Ok(result)
+    fn index_scan(
+        &self,
+        tx: &ReadTransaction,
+        index: &IndexDef,
+        fields: &[TableFieldData],
+        lower: Bound<Vec<u8>>,
+        upper: Bound<Vec<u8>>,
+    ) -> Result<Vec<(Vec<FieldValue>, Ulid)>, DbError> {
+        let index_table = match tx.open_multimap_table(IndexTableDef::new(&index.index_name)) {
+            Ok(index_table) => index_table,
+            Err(TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let range = (
+            lower.as_ref().map(Vec::as_slice),
+            upper.as_ref().map(Vec::as_slice),
+        );
+
+        let field_tys = field_tys(fields);
+
+        let mut result = Vec::new();
+
+        for entry in index_table.range::<&[u8]>(range)? {
+            let (key, values) = entry?;
+
+            let key = decode_key(&field_tys, key.value());
+
+            for value in values {
+                let value = Ulid(value?.value());
+
+                let Some(key) = &key else {
+                    return Err(DbError::CorruptRecord {
+                        table: index.table_name.clone(),
+                        record: value,
+                    });
+                };
+
+                result.push((key.clone(), value));
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn index_fields(&self, index_name: &str) -> Result<(IndexDef, Vec<TableFieldData>), DbError> {
+        self.inner.tables.read().unwrap().index_fields(index_name)
+    }
+}
+
+/// The values of every index entry of `record`.
+///
+/// Lists have an entry for each distinct element, so a record with an empty or null list has none.
+fn index_keys(
+    index: &IndexDef,
+    fields: &[TableFieldData],
+    record: &RecordBytes,
+) -> Result<Vec<Vec<FieldValue>>, DbError> {
+    let mut keys = vec![Vec::new()];
+
+    for field in fields {
+        let value = record
+            .get_field(field)
+            .ok_or_else(|| DbError::CorruptRecord {
+                table: index.table_name.clone(),
+                record: record.id(),
+            })?;
+
+        let values = match value {
+            FieldValue::List { values, .. } => values,
+            FieldValue::Null(FieldTy::List(_)) => Vec::new(),
+            value => vec![value],
+        };
+
+        let mut elements = Vec::with_capacity(values.len());
+
+        for value in values {
+            if !elements.contains(&value) {
+                elements.push(value);
             }
-            _ => todo!(),
+        }
+
+        keys = keys
+            .into_iter()
+            .flat_map(|key| {
+                elements.iter().map(move |element| {
+                    let mut key = key.clone();
+                    key.push(element.clone());
+                    key
+                })
+            })
+            .collect();
+    }
+
+    Ok(keys)
+}
+
+/// The types of the index keys, lists are indexed by their elements
+fn field_tys(fields: &[TableFieldData]) -> Vec<FieldTy> {
+    fields
+        .iter()
+        .map(|field| field.ty.indexed().clone())
+        .collect()
+}
+
+fn first_values(result: Vec<(Vec<FieldValue>, Ulid)>) -> Vec<(FieldValue, Ulid)> {
+    result
+        .into_iter()
+        .map(|(mut values, id)| (values.swap_remove(0), id))
+        .collect()
+}
+
+/// Fails if another record than `record_id` already uses `key` in the unique index `index`.
+///
+/// Null is not equal to anything, so keys of `values` with a null value are never used by another record.
+fn check_unique(
+    index: &IndexDef,
+    index_table: &impl ReadableMultimapTable<IndexKey, u128>,
+    key: &[u8],
+    values: &[FieldValue],
+    record_id: Ulid,
+) -> Result<(), DbError> {
+    if !index.unique || values.iter().any(|value| matches!(value, FieldValue::Null(_))) {
+        return Ok(());
+    }
+
+    for id in index_table.get(key)? {
+        let id = Ulid(id?.value());
+
+        if id != record_id {
+            return Err(DbError::UniqueViolation {
+                table: index.table_name.clone(),
+                field: index.field_names.join(",").into(),
+                record: id,
+            });
         }
     }
+
+    Ok(())
 }
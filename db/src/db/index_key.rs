@@ -0,0 +1,254 @@
+//! Order preserving byte encoding of index keys.
+//!
+//! Comparing two encoded keys byte by byte gives the same order as comparing the values,
+//! so range scans over the encoded keys are range scans over the values.
+//!
+//! Values of nullable fields start with `00` for null and `01` otherwise, so null values come first.
+
+use std::{cmp::Ordering, ops::Bound};
+
+use chrono::{DateTime, TimeDelta};
+use db_core::{decimal::Decimal, float::Float, json::Json, ty::FieldTy, value::FieldValue};
+use redb::{Key, MultimapTableDefinition, TypeName, Value};
+use ulid::Ulid;
+
+pub(super) type IndexTableDef<'a> = MultimapTableDefinition<'a, IndexKey, u128>;
+
+/// Encoded index keys, compared byte by byte.
+///
+/// Stored like `&[u8]`, the own type name makes indices with an older key layout fail to open,
+/// so they are rebuilt by `Db::migrate_indices`. It changes with every new layout.
+#[derive(Debug)]
+pub(super) struct IndexKey;
+
+impl Value for IndexKey {
+    type SelfType<'a> = &'a [u8];
+    type AsBytes<'a> = &'a [u8];
+
+    fn fixed_width() -> Option<usize> {
+        None
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Self::SelfType<'a>
+    where
+        Self: 'a,
+    {
+        data
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a>
+    where
+        Self: 'b,
+    {
+        value
+    }
+
+    fn type_name() -> TypeName {
+        TypeName::new("db::IndexKey:2")
+    }
+}
+
+impl Key for IndexKey {
+    fn compare(data1: &[u8], data2: &[u8]) -> Ordering {
+        data1.cmp(data2)
+    }
+}
+
+/// Encodes the values of the first indexed fields, of types `tys`, as index key.
+///
+/// Returns `None` if a value is null but its field is not nullable.
+pub(super) fn encode_key(tys: &[FieldTy], values: &[FieldValue]) -> Option<Vec<u8>> {
+    let mut key = Vec::new();
+
+    for (ty, value) in tys.iter().zip(values) {
+        match (ty.is_nullable(), value) {
+            (true, FieldValue::Null(_)) => key.push(0x00),
+            (true, value) => {
+                key.push(0x01);
+                encode_value(value, &mut key)?;
+            }
+            (false, value) => encode_value(value, &mut key)?,
+        }
+    }
+
+    Some(key)
+}
+
+fn encode_value(value: &FieldValue, key: &mut Vec<u8>) -> Option<()> {
+    match value {
+        FieldValue::Int(value) => key.extend((*value as u32 ^ (1 << 31)).to_be_bytes()),
+        FieldValue::I64(value) => key.extend((*value as u64 ^ (1 << 63)).to_be_bytes()),
+        FieldValue::F64(value) => key.extend(encode_f64(value.0).to_be_bytes()),
+        FieldValue::Decimal(value) => key.extend((value.units() as u64 ^ (1 << 63)).to_be_bytes()),
+        FieldValue::Bool(value) => key.push(*value as u8),
+        FieldValue::Timestamp(value) => {
+            key.extend((value.timestamp() as u64 ^ (1 << 63)).to_be_bytes());
+            key.extend(value.timestamp_subsec_nanos().to_be_bytes());
+        }
+        FieldValue::Duration(value) => {
+            let (seconds, nanos) = split_duration(value);
+
+            key.extend((seconds as u64 ^ (1 << 63)).to_be_bytes());
+            key.extend(nanos.to_be_bytes());
+        }
+        FieldValue::Text(value) => {
+            encode_text(value, key);
+            key.extend([0x00, 0x00]);
+        }
+        FieldValue::RecordId { id, .. } => key.extend(id.0.to_be_bytes()),
+        FieldValue::Enum { index, .. } => key.extend(index.to_be_bytes()),
+        // JSON is indexed by its text, which `FieldValue::total_cmp` orders it by
+        FieldValue::Json(value) => {
+            encode_text(&value.to_string(), key);
+            key.extend([0x00, 0x00]);
+        }
+        // Lists are indexed by their elements, see `Db::index_insert`
+        FieldValue::Null(_) | FieldValue::List { .. } | FieldValue::Blob(_) => return None,
+    }
+
+    Some(())
+}
+
+/// Whole seconds rounded down and the nanoseconds after them, so the nanoseconds are never negative
+fn split_duration(value: &TimeDelta) -> (i64, u32) {
+    let seconds = value.num_seconds();
+    let nanos = value.subsec_nanos();
+
+    if nanos < 0 {
+        (seconds - 1, (nanos + 1_000_000_000) as u32)
+    } else {
+        (seconds, nanos as u32)
+    }
+}
+
+/// Negative floats have all bits flipped and positive floats the sign bit,
+/// so the bytes are ordered like `f64::total_cmp`
+fn encode_f64(value: f64) -> u64 {
+    let bits = value.to_bits();
+
+    if bits >> 63 == 1 { !bits } else { bits ^ (1 << 63) }
+}
+
+fn decode_f64(bits: u64) -> f64 {
+    if bits >> 63 == 1 {
+        f64::from_bits(bits ^ (1 << 63))
+    } else {
+        f64::from_bits(!bits)
+    }
+}
+
+/// Text is terminated by `00 00`, so `00` bytes inside the text are escaped as `00 ff`.
+///
+/// Without the terminator the encoding of a text is a prefix of the encoding of every text starting with it.
+pub(super) fn encode_text(value: &str, key: &mut Vec<u8>) {
+    for byte in value.bytes() {
+        match byte {
+            0x00 => key.extend([0x00, 0xff]),
+            byte => key.push(byte),
+        }
+    }
+}
+
+pub(super) fn decode_key(tys: &[FieldTy], mut key: &[u8]) -> Option<Vec<FieldValue>> {
+    let values = tys
+        .iter()
+        .map(|ty| decode_value(ty, &mut key))
+        .collect::<Option<Vec<_>>>()?;
+
+    key.is_empty().then_some(values)
+}
+
+fn decode_value(ty: &FieldTy, key: &mut &[u8]) -> Option<FieldValue> {
+    let value = match ty {
+        FieldTy::IntI32 => FieldValue::Int((u32::from_be_bytes(take(key)?) ^ (1 << 31)) as i32),
+        FieldTy::IntI64 => FieldValue::I64((u64::from_be_bytes(take(key)?) ^ (1 << 63)) as i64),
+        FieldTy::F64 => FieldValue::F64(Float(decode_f64(u64::from_be_bytes(take(key)?)))),
+        FieldTy::Decimal => {
+            let units = (u64::from_be_bytes(take(key)?) ^ (1 << 63)) as i64;
+
+            FieldValue::Decimal(Decimal::from_units(units))
+        }
+        FieldTy::Bool => match take(key)? {
+            [0] => FieldValue::Bool(false),
+            [1] => FieldValue::Bool(true),
+            _ => return None,
+        },
+        FieldTy::Timestamp => {
+            let timestamp = (u64::from_be_bytes(take(key)?) ^ (1 << 63)) as i64;
+            let nanos = u32::from_be_bytes(take(key)?);
+
+            FieldValue::Timestamp(DateTime::from_timestamp(timestamp, nanos)?)
+        }
+        FieldTy::Duration => {
+            let seconds = (u64::from_be_bytes(take(key)?) ^ (1 << 63)) as i64;
+            let nanos = u32::from_be_bytes(take(key)?);
+
+            FieldValue::Duration(TimeDelta::new(seconds, nanos)?)
+        }
+        FieldTy::Text => FieldValue::Text(decode_text(key)?),
+        FieldTy::Json => FieldValue::Json(Json::parse(&decode_text(key)?)?),
+        FieldTy::RecordId { table_name } => FieldValue::RecordId {
+            id: Ulid(u128::from_be_bytes(take(key)?)),
+            table_name: table_name.clone(),
+        },
+        FieldTy::Nullable(ty) => match take(key)? {
+            [0x00] => FieldValue::Null(ty.as_ref().clone()),
+            [0x01] => decode_value(ty, key)?,
+            _ => return None,
+        },
+        FieldTy::Enum { variants } => {
+            let index = u32::from_be_bytes(take(key)?);
+
+            if index as usize >= variants.len() {
+                return None;
+            }
+
+            FieldValue::Enum {
+                index,
+                variants: variants.clone(),
+            }
+        }
+        FieldTy::List(_) | FieldTy::Blob => return None,
+    };
+
+    Some(value)
+}
+
+fn decode_text(key: &mut &[u8]) -> Option<String> {
+    let mut bytes = Vec::new();
+
+    loop {
+        match take(key)? {
+            [0x00] => match take(key)? {
+                [0x00] => break,
+                [0xff] => bytes.push(0x00),
+                _ => return None,
+            },
+            [byte] => bytes.push(byte),
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+fn take<const N: usize>(key: &mut &[u8]) -> Option<[u8; N]> {
+    let (bytes, rest) = key.split_first_chunk::<N>()?;
+
+    *key = rest;
+
+    Some(*bytes)
+}
+
+/// The exclusive upper bound of all keys starting with `prefix`.
+pub(super) fn prefix_end(prefix: &[u8]) -> Bound<Vec<u8>> {
+    let mut end = prefix.to_vec();
+
+    while let Some(byte) = end.pop() {
+        if byte < 0xff {
+            end.push(byte + 1);
+            return Bound::Excluded(end);
+        }
+    }
+
+    Bound::Unbounded
+}
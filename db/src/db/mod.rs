@@ -1,8 +1,18 @@
 mod table_ext;
+mod alter_ext;
 mod record_ext;
 mod index_ext;
+mod index_key;
 mod trigger_ext;
 mod query_ext;
+mod plan_ext;
+mod check_ext;
+mod blob_ext;
+mod transaction;
+
+pub use transaction::DbTransaction;
+pub use plan_ext::{JoinLookup, JoinPlan, QueryPlan};
+pub use blob_ext::BlobReader;
 
 use db_core::record::RecordBytes;
 
@@ -14,7 +24,7 @@ use std::{
 use redb::{Database, ReadableDatabase, ReadableTable, TableDefinition};
 use ulid::Ulid;
 
-use crate::{db::table_ext::DbTables};
+use crate::{db::table_ext::DbTables, error::DbError};
      
 #[derive(Clone)]
 pub struct Db {
@@ -30,9 +40,11 @@ pub struct DbInner {
 type TableWithIdDef<'a> = TableDefinition<'a, u128, &'static [u8]>;
 
 impl Db {
-    pub fn new(path: impl AsRef<Path>) -> Result<Self, redb::DatabaseError> {
-        let db = Database::create(path)?;
+    pub fn new(path: impl AsRef<Path>) -> Result<Self, DbError> {
+        Self::from_database(Database::create(path)?)
+    }
 
+    fn from_database(db: Database) -> Result<Self, DbError> {
         let inner = DbInner {
             db,
             tables: RwLock::new(Default::default()),
@@ -42,43 +54,50 @@ impl Db {
             inner: Arc::new(inner),
         };
 
-        this.update_table_map();
+        this.update_table_map()?;
+        this.write(|tx| this.migrate_indices(&tx.tx))?;
 
         Ok(this)
     }
 
-    pub fn get(&self, table_name: &str, id: Ulid) -> Option<RecordBytes> {
-        let tx = self.inner.db.begin_read().ok()?;
+    pub fn get(&self, table_name: &str, id: Ulid) -> Result<Option<RecordBytes>, DbError> {
+        self.require_table(table_name)?;
+
+        let tx = self.inner.db.begin_read()?;
 
         let result;
 
         {
-            let table = tx.open_table(TableWithIdDef::new(table_name)).ok()?;
+            let table = tx.open_table(TableWithIdDef::new(table_name))?;
 
-            let value = table.get(id.0).ok()??;
+            let Some(value) = table.get(id.0)? else {
+                return Ok(None);
+            };
 
             let bytes = value.value();
 
             result = RecordBytes::new(id, bytes.to_owned());
         }
 
-        tx.close().ok()?;
+        tx.close()?;
 
-        Some(result)
+        Ok(Some(result))
     }
 
-    pub fn get_all(&self, table_name: &str) -> Option<Vec<RecordBytes>> {
-        let tx = self.inner.db.begin_read().ok()?;
+    pub fn get_all(&self, table_name: &str) -> Result<Vec<RecordBytes>, DbError> {
+        self.require_table(table_name)?;
+
+        let tx = self.inner.db.begin_read()?;
 
         let mut result = Vec::new();
 
         {
-            let table = tx.open_table(TableWithIdDef::new(table_name)).ok()?;
+            let table = tx.open_table(TableWithIdDef::new(table_name))?;
 
-            let values = table.iter().ok()?;
+            let values = table.iter()?;
 
             for v in values {
-                let (id, value) = v.ok()?;
+                let (id, value) = v?;
 
                 let id = id.value();
                 let id = Ulid(id);
@@ -89,13 +108,3351 @@ impl Db {
             }
         }
 
-        tx.close().ok()?;
+        tx.close()?;
 
-        Some(result)
+        Ok(result)
     }
 }
 
-
 impl DbTables {
 
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use bytepack::{BytePacker, ByteUnpacker, Pack, PackFormat, Unpack};
+    use chrono::{DateTime, TimeDelta};
+    use std::{io::Read, ops::Bound};
+
+    use db_core::{
+        blob::{Blob, BlobContent},
+        decimal::Decimal,
+        defs::{
+            index::IndexOnDelete,
+            table::{AlterTableOp, TableData, TableDef, TableFieldDef},
+        },
+        expr::{BinaryOp, CompareOp, DidYouMeanHint, EqOp, EvalErr, Expr, LogicOp},
+        float::Float,
+        json::Json,
+        named::Named,
+        query::{OnError, OrderBy, Query, QueryResult, SortDirection},
+        ty::{FieldTy, Ty},
+        value::{FieldValue, Value},
+    };
+    use redb::{ReadableTableMetadata, backends::InMemoryBackend};
+
+    use super::*;
+    use crate::db::{
+        blob_ext::BlobTableDef,
+        index_key::{decode_key, encode_key},
+        table_ext::TABLE_DEF_TABLE,
+    };
+
+    fn in_memory_database() -> Database {
+        Database::builder()
+            .create_with_backend(InMemoryBackend::new())
+            .unwrap()
+    }
+
+    fn table_def(fields: impl IntoIterator<Item = (&'static str, FieldTy, bool)>) -> TableDef {
+        TableDef {
+            fields: fields
+                .into_iter()
+                .map(|(name, ty, has_index)| {
+                    Named::new(
+                        name,
+                        TableFieldDef {
+                            ty,
+                            has_index,
+                            on_delete: IndexOnDelete::None,
+                            unique: false,
+                        },
+                    )
+                })
+                .collect(),
+            main_display_field: None,
+            unique: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    fn create_record(db: &Db, table_name: &str, values: &[FieldValue]) -> RecordBytes {
+        let table = db.table(table_name).unwrap();
+
+        let mut packer = BytePacker::new(table.fixed_byte_count());
+
+        for (field, value) in table.fields().zip(values) {
+            value.pack_as(&field.value.ty, field.value.offset, &mut packer);
+        }
+
+        RecordBytes::create(packer.finish())
+    }
+
+    fn test_db() -> Db {
+        let db = Db::from_database(in_memory_database()).unwrap();
+
+        db.register_table(Named::new(
+            "person",
+            table_def([
+                ("name", FieldTy::Text, false),
+                ("active", FieldTy::Bool, false),
+            ]),
+        ))
+        .unwrap();
+
+        db
+    }
+
+    #[test]
+    fn duplicate_table() {
+        let db = test_db();
+
+        let result = db.register_table(Named::new("person", table_def([])));
+
+        assert!(matches!(result, Err(DbError::DuplicateTable { .. })));
+    }
+
+    #[test]
+    fn schema_mismatch() {
+        let db = test_db();
+
+        {
+            let tx = db.inner.db.begin_write().unwrap();
+            {
+                let mut tables = tx.open_table(TABLE_DEF_TABLE).unwrap();
+                let bytes = table_def([("name", FieldTy::Text, false)]).to_stored();
+                tables.insert("animal", &*bytes).unwrap();
+            }
+            tx.commit().unwrap();
+        }
+
+        let result = db.register_table(Named::new(
+            "animal",
+            table_def([("age", FieldTy::IntI32, false)]),
+        ));
+
+        assert!(matches!(result, Err(DbError::SchemaMismatch { .. })));
+        assert!(db.table("animal").is_none());
+    }
+
+    #[test]
+    fn corrupt_table_def() {
+        let database = in_memory_database();
+
+        {
+            let tx = database.begin_write().unwrap();
+            {
+                let mut tables = tx.open_table(TABLE_DEF_TABLE).unwrap();
+                tables.insert("person", [0xff].as_slice()).unwrap();
+            }
+            tx.commit().unwrap();
+        }
+
+        let result = Db::from_database(database);
+
+        assert!(matches!(result, Err(DbError::CorruptTableDef { .. })));
+    }
+
+    #[test]
+    fn open_baseline_database() {
+        #[derive(Pack)]
+        struct BaselineTableDef {
+            fields: Vec<Named<BaselineFieldDef>>,
+            main_display_field: Option<u32>,
+        }
+
+        #[derive(Pack)]
+        struct BaselineFieldDef {
+            ty: FieldTy,
+            has_index: bool,
+        }
+
+        let person_def = BytePacker::pack_value(&BaselineTableDef {
+            fields: vec![
+                Named::new(
+                    "name",
+                    BaselineFieldDef {
+                        ty: FieldTy::Text,
+                        has_index: false,
+                    },
+                ),
+                Named::new(
+                    "active",
+                    BaselineFieldDef {
+                        ty: FieldTy::Bool,
+                        has_index: false,
+                    },
+                ),
+            ],
+            main_display_field: Some(0),
+        });
+        let task_def = BytePacker::pack_value(&BaselineTableDef {
+            fields: vec![
+                Named::new(
+                    "owner",
+                    BaselineFieldDef {
+                        ty: FieldTy::RecordId {
+                            table_name: "person".into(),
+                        },
+                        has_index: true,
+                    },
+                ),
+                Named::new(
+                    "hours",
+                    BaselineFieldDef {
+                        ty: FieldTy::IntI32,
+                        has_index: true,
+                    },
+                ),
+            ],
+            main_display_field: None,
+        });
+
+        let pack_record = |def: &[u8], values: &[FieldValue]| {
+            let table = TableData::from(TableDef::from_stored(def).unwrap());
+
+            let mut packer = BytePacker::new(table.fixed_byte_count());
+
+            for (field, value) in table.fields().zip(values) {
+                value.pack_as(&field.value.ty, field.value.offset, &mut packer);
+            }
+
+            RecordBytes::create(packer.finish())
+        };
+
+        let person = pack_record(
+            &person_def,
+            &[FieldValue::Text("Peter".into()), FieldValue::Bool(true)],
+        );
+        let owner = FieldValue::RecordId {
+            id: person.id(),
+            table_name: "person".into(),
+        };
+        let task = pack_record(&task_def, &[owner.clone(), FieldValue::Int(3)]);
+
+        let database = in_memory_database();
+
+        {
+            let tx = database.begin_write().unwrap();
+            {
+                let mut tables = tx.open_table(TABLE_DEF_TABLE).unwrap();
+                tables.insert("person", &*person_def).unwrap();
+                tables.insert("task", &*task_def).unwrap();
+
+                let mut people = tx.open_table(TableWithIdDef::new("person")).unwrap();
+                people.insert(person.id().0, person.bytes()).unwrap();
+
+                let mut tasks = tx.open_table(TableWithIdDef::new("task")).unwrap();
+                tasks.insert(task.id().0, task.bytes()).unwrap();
+
+                // Indices used to have their own key types
+                let mut owner_index = tx
+                    .open_multimap_table(redb::MultimapTableDefinition::<u128, u128>::new(
+                        "#task:owner",
+                    ))
+                    .unwrap();
+                owner_index.insert(person.id().0, task.id().0).unwrap();
+
+                let mut hours_index = tx
+                    .open_multimap_table(redb::MultimapTableDefinition::<i64, u128>::new(
+                        "#task:hours",
+                    ))
+                    .unwrap();
+                hours_index.insert(3, task.id().0).unwrap();
+            }
+            tx.commit().unwrap();
+        }
+
+        let db = Db::from_database(database).unwrap();
+
+        let person_table = db.table("person").unwrap();
+        assert_eq!(
+            person_table
+                .main_display_field()
+                .map(|field| field.name.as_ref()),
+            Some("name")
+        );
+
+        let task_table = db.table("task").unwrap();
+        let owner_field = task_table.fields().next().unwrap();
+        assert_eq!(owner_field.value.on_delete, IndexOnDelete::Cascase);
+        assert!(!owner_field.value.unique);
+
+        assert_eq!(
+            db.get("task", task.id()).unwrap().unwrap().bytes(),
+            task.bytes()
+        );
+        assert_eq!(
+            db.index_query("#task:owner", None, None).unwrap(),
+            vec![(owner, task.id())]
+        );
+        assert_eq!(
+            db.index_query("#task:hours", None, None).unwrap(),
+            vec![(FieldValue::Int(3), task.id())]
+        );
+
+        // References stored before `on_delete` existed still cascade
+        db.delete_record("person", person.id()).unwrap();
+        assert!(db.get("task", task.id()).unwrap().is_none());
+    }
+
+    #[test]
+    fn unknown_table() {
+        let db = test_db();
+
+        let record = create_record(
+            &db,
+            "person",
+            &[FieldValue::Text("Peter".into()), FieldValue::Bool(true)],
+        );
+
+        assert!(matches!(
+            db.get("animal", record.id()),
+            Err(DbError::TableDoesNotExist { .. })
+        ));
+        assert!(matches!(
+            db.get_all("animal"),
+            Err(DbError::TableDoesNotExist { .. })
+        ));
+        assert!(matches!(
+            db.insert_record("animal", &record),
+            Err(DbError::TableDoesNotExist { .. })
+        ));
+        assert!(matches!(
+            db.delete_table("animal"),
+            Err(DbError::TableDoesNotExist { .. })
+        ));
+    }
+
+    #[test]
+    fn unknown_index() {
+        let db = test_db();
+
+        let result = db.index_query("#person:age", None, None);
+
+        assert!(matches!(result, Err(DbError::UnknownIndex { .. })));
+    }
+
+    #[test]
+    fn unsupported_index_type() {
+        let db = test_db();
+
+        db.register_table(Named::new(
+            "flag",
+            table_def([("value", FieldTy::Bool, true)]),
+        ))
+        .unwrap();
+
+        let result = db.index_query_prefix("#flag:value", "t");
+
+        assert!(matches!(result, Err(DbError::UnsupportedIndexType { .. })));
+    }
+
+    #[test]
+    fn int_index_range() {
+        let db = test_db();
+
+        db.register_table(Named::new(
+            "measurement",
+            table_def([("value", FieldTy::IntI32, true)]),
+        ))
+        .unwrap();
+
+        for value in [7, -3, 0, i32::MIN, 12, -20, i32::MAX] {
+            let record = create_record(&db, "measurement", &[FieldValue::Int(value)]);
+            db.insert_record("measurement", &record).unwrap();
+        }
+
+        let values = |min: Option<i32>, max: Option<i32>| {
+            db.index_query(
+                "#measurement:value",
+                min.map(FieldValue::Int),
+                max.map(FieldValue::Int),
+            )
+            .unwrap()
+            .into_iter()
+            .map(|(value, _)| value)
+            .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            values(None, None),
+            [i32::MIN, -20, -3, 0, 7, 12, i32::MAX].map(FieldValue::Int)
+        );
+        assert_eq!(values(Some(-3), Some(12)), [-3, 0, 7].map(FieldValue::Int));
+        assert_eq!(
+            values(Some(1), None),
+            [7, 12, i32::MAX].map(FieldValue::Int)
+        );
+    }
+
+    #[test]
+    fn text_index_prefix() {
+        let db = test_db();
+
+        db.register_table(Named::new(
+            "word",
+            table_def([("value", FieldTy::Text, true)]),
+        ))
+        .unwrap();
+
+        for value in ["car", "ca", "cat\0s", "cart", "c", "dog", "cb"] {
+            let record = create_record(&db, "word", &[FieldValue::Text(value.into())]);
+            db.insert_record("word", &record).unwrap();
+        }
+
+        let values = |prefix: &str| {
+            db.index_query_prefix("#word:value", prefix)
+                .unwrap()
+                .into_iter()
+                .map(|(value, _)| value)
+                .collect::<Vec<_>>()
+        };
+
+        let texts = |values: &[&str]| {
+            values
+                .iter()
+                .map(|value| FieldValue::Text((*value).into()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(values("ca"), texts(&["ca", "car", "cart", "cat\0s"]));
+        assert_eq!(values("cat\0"), texts(&["cat\0s"]));
+        assert_eq!(
+            values(""),
+            texts(&["c", "ca", "car", "cart", "cat\0s", "cb", "dog"])
+        );
+    }
+
+    #[test]
+    fn update_record() {
+        let db = test_db();
+
+        db.register_table(Named::new(
+            "task",
+            table_def([
+                ("title", FieldTy::Text, true),
+                ("hours", FieldTy::IntI32, true),
+            ]),
+        ))
+        .unwrap();
+
+        let task = |title: &str, hours: i32| {
+            create_record(
+                &db,
+                "task",
+                &[FieldValue::Text(title.into()), FieldValue::Int(hours)],
+            )
+        };
+        let values = |index_name: &str| {
+            db.index_query(index_name, None, None)
+                .unwrap()
+                .into_iter()
+                .map(|(value, _)| value)
+                .collect::<Vec<_>>()
+        };
+
+        let record = task("write", 3);
+        db.insert_record("task", &record).unwrap();
+
+        // The index entries of the old values are replaced
+        db.update_record("task", record.id(), &task("review", 5))
+            .unwrap();
+
+        assert_eq!(values("#task:title"), [FieldValue::Text("review".into())]);
+        assert_eq!(values("#task:hours"), [FieldValue::Int(5)]);
+
+        db.update_record_fields(
+            "task",
+            record.id(),
+            [Named::new("hours", FieldValue::Int(8))],
+        )
+        .unwrap();
+
+        assert_eq!(values("#task:title"), [FieldValue::Text("review".into())]);
+        assert_eq!(values("#task:hours"), [FieldValue::Int(8)]);
+
+        // Updating a missing record writes nothing, even if the error is ignored
+        let missing = task("test", 1);
+
+        assert!(matches!(
+            db.update_record("task", missing.id(), &missing),
+            Err(DbError::RecordDoesNotExist { .. })
+        ));
+        assert!(matches!(
+            db.update_record_fields(
+                "task",
+                missing.id(),
+                [Named::new("hours", FieldValue::Int(2))]
+            ),
+            Err(DbError::RecordDoesNotExist { .. })
+        ));
+
+        db.write(|tx| {
+            assert!(tx.update("task", missing.id(), &missing).is_err());
+
+            Ok(())
+        })
+        .unwrap();
+
+        assert!(db.get("task", missing.id()).unwrap().is_none());
+        assert_eq!(db.get_all("task").unwrap().len(), 1);
+        assert_eq!(values("#task:hours"), [FieldValue::Int(8)]);
+    }
+
+    #[test]
+    fn alter_table() {
+        let db = test_db();
+
+        let mut task = table_def([
+            ("title", FieldTy::Text, true),
+            ("hours", FieldTy::IntI32, true),
+        ]);
+        task.main_display_field = Some(0);
+        task.indices = vec![vec!["title".into(), "hours".into()]];
+
+        db.register_table(Named::new("task", task)).unwrap();
+
+        let records = [("12", 3), ("write", 5)].map(|(title, hours)| {
+            let record = create_record(
+                &db,
+                "task",
+                &[FieldValue::Text(title.into()), FieldValue::Int(hours)],
+            );
+            db.insert_record("task", &record).unwrap();
+
+            record.id()
+        });
+
+        let names = || {
+            db.table("task")
+                .unwrap()
+                .fields()
+                .map(|field| field.name.to_string())
+                .collect::<Vec<_>>()
+        };
+        let fields = |idx: usize| {
+            let table = db.table("task").unwrap();
+            let record = db.get("task", records[idx]).unwrap().unwrap();
+
+            table
+                .fields()
+                .map(|field| record.get_field(&field.value).unwrap())
+                .collect::<Vec<_>>()
+        };
+        let values = |index_name: &str| {
+            db.index_query(index_name, None, None)
+                .unwrap()
+                .into_iter()
+                .map(|(value, _)| value)
+                .collect::<Vec<_>>()
+        };
+        let unknown = |index_name: &str| {
+            matches!(
+                db.index_query(index_name, None, None),
+                Err(DbError::UnknownIndex { .. })
+            )
+        };
+        let main_display_field = || {
+            db.table("task")
+                .unwrap()
+                .main_display_field()
+                .map(|field| field.name.to_string())
+        };
+
+        // Existing records get the default value
+        db.alter_table(
+            "task",
+            [AlterTableOp::AddField {
+                name: "done".into(),
+                field: TableFieldDef {
+                    ty: FieldTy::Bool,
+                    has_index: true,
+                    on_delete: IndexOnDelete::None,
+                    unique: false,
+                },
+                default: FieldValue::Bool(false),
+            }],
+        )
+        .unwrap();
+
+        assert_eq!(names(), ["title", "hours", "done"]);
+        assert_eq!(
+            fields(0),
+            [
+                FieldValue::Text("12".into()),
+                FieldValue::Int(3),
+                FieldValue::Bool(false)
+            ]
+        );
+        assert_eq!(values("#task:done"), [false; 2].map(FieldValue::Bool));
+        assert_eq!(values("#task:hours"), [3, 5].map(FieldValue::Int));
+
+        // Indices follow the renamed fields
+        db.alter_table(
+            "task",
+            [
+                AlterTableOp::RenameField {
+                    name: "hours".into(),
+                    new_name: "time".into(),
+                },
+                AlterTableOp::RenameField {
+                    name: "title".into(),
+                    new_name: "name".into(),
+                },
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(names(), ["name", "time", "done"]);
+        assert_eq!(
+            fields(1)[..2],
+            [FieldValue::Text("write".into()), FieldValue::Int(5)]
+        );
+        assert_eq!(main_display_field().as_deref(), Some("name"));
+        assert!(unknown("#task:hours"));
+        assert!(unknown("#task:title,hours"));
+        assert_eq!(values("#task:time"), [3, 5].map(FieldValue::Int));
+        assert_eq!(
+            values("#task:name,time"),
+            [
+                FieldValue::Text("12".into()),
+                FieldValue::Text("write".into())
+            ]
+        );
+
+        // Values that can not be cast get the default value
+        db.alter_table(
+            "task",
+            [
+                AlterTableOp::RetypeField {
+                    name: "name".into(),
+                    ty: FieldTy::IntI32,
+                    default: FieldValue::Int(0),
+                },
+                AlterTableOp::RetypeField {
+                    name: "time".into(),
+                    ty: FieldTy::Text,
+                    default: FieldValue::Text(String::new()),
+                },
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            fields(0)[..2],
+            [FieldValue::Int(12), FieldValue::Text("3".into())]
+        );
+        assert_eq!(
+            fields(1)[..2],
+            [FieldValue::Int(0), FieldValue::Text("5".into())]
+        );
+        assert_eq!(values("#task:name"), [0, 12].map(FieldValue::Int));
+        assert_eq!(
+            values("#task:time"),
+            [FieldValue::Text("3".into()), FieldValue::Text("5".into())]
+        );
+        assert_eq!(values("#task:name,time"), [0, 12].map(FieldValue::Int));
+
+        assert!(matches!(
+            db.alter_table(
+                "task",
+                [AlterTableOp::RetypeField {
+                    name: "time".into(),
+                    ty: FieldTy::IntI32,
+                    default: FieldValue::Text(String::new()),
+                }],
+            ),
+            Err(DbError::WrongType { .. })
+        ));
+
+        // Moving past the last field moves to the end
+        db.alter_table(
+            "task",
+            [
+                AlterTableOp::MoveField {
+                    name: "done".into(),
+                    index: 0,
+                },
+                AlterTableOp::MoveField {
+                    name: "name".into(),
+                    index: 10,
+                },
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(names(), ["done", "time", "name"]);
+        assert_eq!(
+            fields(0),
+            [
+                FieldValue::Bool(false),
+                FieldValue::Text("3".into()),
+                FieldValue::Int(12)
+            ]
+        );
+        assert_eq!(main_display_field().as_deref(), Some("name"));
+        assert_eq!(values("#task:name"), [0, 12].map(FieldValue::Int));
+        assert_eq!(values("#task:done"), [false; 2].map(FieldValue::Bool));
+
+        // Dropping a field removes its indices and the composite indices it is part of
+        db.alter_table(
+            "task",
+            [
+                AlterTableOp::DropField {
+                    name: "time".into(),
+                },
+                AlterTableOp::DropField {
+                    name: "name".into(),
+                },
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(names(), ["done"]);
+        assert_eq!(fields(1), [FieldValue::Bool(false)]);
+        assert_eq!(main_display_field(), None);
+        assert!(unknown("#task:time"));
+        assert!(unknown("#task:name"));
+        assert!(unknown("#task:name,time"));
+        assert_eq!(values("#task:done"), [false; 2].map(FieldValue::Bool));
+
+        db.alter_table(
+            "task",
+            [AlterTableOp::SetMainDisplayField {
+                name: Some("done".into()),
+            }],
+        )
+        .unwrap();
+
+        assert_eq!(main_display_field().as_deref(), Some("done"));
+
+        // References in new values must point to existing records
+        let person = create_record(
+            &db,
+            "person",
+            &[FieldValue::Text("Peter".into()), FieldValue::Bool(true)],
+        );
+        db.insert_record("person", &person).unwrap();
+
+        let add_owner = |id| {
+            db.alter_table(
+                "task",
+                [AlterTableOp::AddField {
+                    name: "owner".into(),
+                    field: TableFieldDef {
+                        ty: FieldTy::RecordId {
+                            table_name: "person".into(),
+                        },
+                        has_index: true,
+                        on_delete: IndexOnDelete::Cascase,
+                        unique: false,
+                    },
+                    default: FieldValue::RecordId {
+                        id,
+                        table_name: "person".into(),
+                    },
+                }],
+            )
+        };
+
+        let missing = Ulid::new();
+
+        assert!(matches!(
+            add_owner(missing),
+            Err(DbError::RecordDoesNotExist { table, record })
+                if table.as_ref() == "person" && record == missing
+        ));
+        assert_eq!(names(), ["done"]);
+
+        add_owner(person.id()).unwrap();
+
+        assert_eq!(values("#task:owner").len(), 2);
+
+        // Values cast from text are checked as well
+        db.alter_table(
+            "task",
+            [
+                AlterTableOp::RetypeField {
+                    name: "owner".into(),
+                    ty: FieldTy::Text,
+                    default: FieldValue::Text(String::new()),
+                },
+                AlterTableOp::AddField {
+                    name: "reviewer".into(),
+                    field: TableFieldDef {
+                        ty: FieldTy::Text,
+                        has_index: false,
+                        on_delete: IndexOnDelete::None,
+                        unique: false,
+                    },
+                    default: FieldValue::Text(missing.to_string()),
+                },
+            ],
+        )
+        .unwrap();
+
+        let result = db.alter_table(
+            "task",
+            [AlterTableOp::RetypeField {
+                name: "reviewer".into(),
+                ty: FieldTy::RecordId {
+                    table_name: "person".into(),
+                },
+                default: FieldValue::RecordId {
+                    id: person.id(),
+                    table_name: "person".into(),
+                },
+            }],
+        );
+        assert!(matches!(result, Err(DbError::RecordDoesNotExist { .. })));
+        assert_eq!(fields(0)[2], FieldValue::Text(missing.to_string()));
+    }
+
+    #[test]
+    fn insert_existing_id() {
+        let db = test_db();
+
+        db.register_table(Named::new(
+            "task",
+            table_def([("hours", FieldTy::IntI32, true)]),
+        ))
+        .unwrap();
+
+        let record = create_record(&db, "task", &[FieldValue::Int(7)]);
+        db.insert_record("task", &record).unwrap();
+
+        let other = create_record(&db, "task", &[FieldValue::Int(9)]);
+        let same_id = RecordBytes::new(record.id(), other.bytes().to_owned());
+
+        assert!(matches!(
+            db.insert_record("task", &same_id),
+            Err(DbError::RecordAlreadyExists { .. })
+        ));
+        assert_eq!(
+            db.index_query("#task:hours", None, None).unwrap(),
+            [(FieldValue::Int(7), record.id())]
+        );
+        assert_eq!(db.get("task", record.id()).unwrap(), Some(record));
+    }
+
+    #[test]
+    fn write_transaction() {
+        let db = test_db();
+
+        db.register_table(Named::new(
+            "task",
+            table_def([("hours", FieldTy::IntI32, true)]),
+        ))
+        .unwrap();
+
+        let task = |hours: i32| create_record(&db, "task", &[FieldValue::Int(hours)]);
+        let hours = || {
+            db.index_query("#task:hours", None, None)
+                .unwrap()
+                .into_iter()
+                .map(|(value, _)| value)
+                .collect::<Vec<_>>()
+        };
+
+        let first = task(3);
+        let second = task(5);
+
+        // Later operations see the writes of earlier ones, all are committed together
+        db.write(|tx| {
+            tx.insert("task", &first)?;
+            tx.insert("task", &second)?;
+
+            assert_eq!(tx.get("task", first.id())?, Some(first.clone()));
+
+            tx.update_fields(
+                "task",
+                second.id(),
+                [Named::new("hours", FieldValue::Int(8))],
+            )
+        })
+        .unwrap();
+
+        assert_eq!(hours(), [3, 8].map(FieldValue::Int));
+
+        // An error rolls back every write of the transaction
+        let third = task(1);
+
+        let result = db.write(|tx| {
+            tx.insert("task", &third)?;
+            tx.delete("task", first.id())?;
+            tx.delete("task", first.id())
+        });
+
+        assert!(matches!(result, Err(DbError::RecordDoesNotExist { .. })));
+        assert!(db.get("task", third.id()).unwrap().is_none());
+        assert!(db.get("task", first.id()).unwrap().is_some());
+        assert_eq!(hours(), [3, 8].map(FieldValue::Int));
+
+        // The table map is reloaded if a failed transaction changed the schema
+        let result = db.write(|tx| {
+            tx.alter_table(
+                "task",
+                [AlterTableOp::AddField {
+                    name: "note".into(),
+                    field: TableFieldDef {
+                        ty: FieldTy::Text,
+                        has_index: false,
+                        on_delete: IndexOnDelete::None,
+                        unique: false,
+                    },
+                    default: FieldValue::Text(String::new()),
+                }],
+            )?;
+
+            assert!(db.table("task").unwrap().field("note").is_some());
+
+            tx.insert("task", &third)
+        });
+
+        assert!(matches!(result, Err(DbError::CorruptRecord { .. })));
+        assert!(db.table("task").unwrap().field("note").is_none());
+        assert_eq!(db.get_all("task").unwrap().len(), 2);
+        assert_eq!(hours(), [3, 8].map(FieldValue::Int));
+    }
+
+    #[test]
+    fn time_index_keys() {
+        let at = |nanos: u32| {
+            FieldValue::Timestamp(DateTime::from_timestamp(1_710_000_000, nanos).unwrap())
+        };
+        let took = |millis: i64| FieldValue::Duration(TimeDelta::milliseconds(millis));
+
+        // Values within the same second keep their order and their sub-second part
+        for values in [
+            [0, 1, 2, 500_000_000, 900_000_000].map(at),
+            [-1500, -1000, -500, 0, 1500].map(took),
+        ] {
+            let tys = [values[0].ty()];
+            let keys = values
+                .iter()
+                .map(|value| encode_key(&tys, std::slice::from_ref(value)).unwrap())
+                .collect::<Vec<_>>();
+
+            assert!(keys.windows(2).all(|keys| keys[0] < keys[1]));
+
+            for (key, value) in keys.iter().zip(&values) {
+                assert_eq!(decode_key(&tys, key), Some(vec![value.clone()]));
+            }
+        }
+    }
+
+    #[test]
+    fn enum_variants_required() {
+        let db = test_db();
+
+        let status = |variants: &[&str]| FieldTy::Enum {
+            variants: variants.iter().map(|variant| (*variant).into()).collect(),
+        };
+        let register =
+            |ty: FieldTy| db.register_table(Named::new("task", table_def([("status", ty, false)])));
+
+        for variants in [&[][..], &[""], &["open", "open"], &["open", "in\0progress"]] {
+            assert!(
+                matches!(
+                    register(status(variants)),
+                    Err(DbError::InvalidEnumVariants { .. })
+                ),
+                "{variants:?}"
+            );
+        }
+
+        // Nested enums are checked as well
+        assert!(matches!(
+            register(FieldTy::List(Box::new(status(&["a", "a"])))),
+            Err(DbError::InvalidEnumVariants { .. })
+        ));
+        assert!(db.table("task").is_none());
+
+        register(status(&["open", "done"])).unwrap();
+
+        assert!(matches!(
+            db.alter_table(
+                "task",
+                [AlterTableOp::AddField {
+                    name: "priority".into(),
+                    field: TableFieldDef {
+                        ty: FieldTy::Nullable(Box::new(status(&[]))),
+                        has_index: false,
+                        on_delete: IndexOnDelete::None,
+                        unique: false,
+                    },
+                    default: FieldValue::Null(status(&[])),
+                }],
+            ),
+            Err(DbError::InvalidEnumVariants { .. })
+        ));
+        assert_eq!(db.table("task").unwrap().fields().count(), 1);
+    }
+
+    #[test]
+    fn set_none_requires_nullable() {
+        let db = test_db();
+
+        let person = FieldTy::RecordId {
+            table_name: "person".into(),
+        };
+        let set_none = |ty: FieldTy| TableFieldDef {
+            ty,
+            has_index: true,
+            on_delete: IndexOnDelete::SetNone,
+            unique: false,
+        };
+
+        let mut task = table_def([]);
+        task.fields = vec![Named::new("owner", set_none(person.clone()))];
+
+        assert!(matches!(
+            db.register_table(Named::new("task", task.clone())),
+            Err(DbError::InvalidOnDelete { .. })
+        ));
+        assert!(db.table("task").is_none());
+
+        task.fields = vec![
+            Named::new(
+                "owner",
+                set_none(FieldTy::Nullable(Box::new(person.clone()))),
+            ),
+            Named::new(
+                "watchers",
+                set_none(FieldTy::List(Box::new(person.clone()))),
+            ),
+        ];
+        db.register_table(Named::new("task", task)).unwrap();
+
+        assert!(matches!(
+            db.alter_table(
+                "task",
+                [AlterTableOp::RetypeField {
+                    name: "owner".into(),
+                    ty: person.clone(),
+                    default: FieldValue::RecordId {
+                        id: Ulid::nil(),
+                        table_name: "person".into(),
+                    },
+                }],
+            ),
+            Err(DbError::InvalidOnDelete { .. })
+        ));
+        assert!(matches!(
+            db.alter_table(
+                "task",
+                [AlterTableOp::AddField {
+                    name: "reviewer".into(),
+                    field: set_none(person),
+                    default: FieldValue::RecordId {
+                        id: Ulid::nil(),
+                        table_name: "person".into(),
+                    },
+                }],
+            ),
+            Err(DbError::InvalidOnDelete { .. })
+        ));
+        assert!(
+            db.table("task")
+                .unwrap()
+                .field("owner")
+                .unwrap()
+                .ty
+                .is_nullable()
+        );
+    }
+
+    /// Registers `task` with an `owner` field referencing `person` and inserts two people
+    fn reference_db(owner: FieldTy, on_delete: IndexOnDelete) -> (Db, [FieldValue; 2]) {
+        let db = test_db();
+
+        let mut task = table_def([("title", FieldTy::Text, false)]);
+        task.fields.push(Named::new(
+            "owner",
+            TableFieldDef {
+                ty: owner,
+                has_index: true,
+                on_delete,
+                unique: false,
+            },
+        ));
+
+        db.register_table(Named::new("task", task)).unwrap();
+
+        let people = ["Peter", "Anna"].map(|name| {
+            let record = create_record(
+                &db,
+                "person",
+                &[FieldValue::Text(name.into()), FieldValue::Bool(true)],
+            );
+            db.insert_record("person", &record).unwrap();
+
+            FieldValue::RecordId {
+                id: record.id(),
+                table_name: "person".into(),
+            }
+        });
+
+        (db, people)
+    }
+
+    fn insert_task(db: &Db, title: &str, owner: FieldValue) -> Ulid {
+        let record = create_record(db, "task", &[FieldValue::Text(title.into()), owner]);
+        db.insert_record("task", &record).unwrap();
+
+        record.id()
+    }
+
+    fn task_owner(db: &Db, id: Ulid) -> Option<FieldValue> {
+        let table = db.table("task").unwrap();
+        let record = db.get("task", id).unwrap()?;
+
+        record.get_field(table.field("owner").unwrap())
+    }
+
+    fn record_id(value: &FieldValue) -> Ulid {
+        let FieldValue::RecordId { id, .. } = value else {
+            panic!("not a record id: {value:?}");
+        };
+
+        *id
+    }
+
+    #[test]
+    fn on_delete_cascade() {
+        let person = FieldTy::RecordId {
+            table_name: "person".into(),
+        };
+        let (db, [peter, anna]) = reference_db(person, IndexOnDelete::Cascase);
+
+        let write = insert_task(&db, "write", peter.clone());
+        let review = insert_task(&db, "review", peter.clone());
+        let test = insert_task(&db, "test", anna.clone());
+
+        db.delete_record("person", record_id(&peter)).unwrap();
+
+        assert!(db.get("task", write).unwrap().is_none());
+        assert!(db.get("task", review).unwrap().is_none());
+        assert_eq!(task_owner(&db, test), Some(anna.clone()));
+        assert_eq!(
+            db.index_query("#task:owner", None, None).unwrap(),
+            [(anna, test)]
+        );
+    }
+
+    #[test]
+    fn on_delete_restrict() {
+        let person = FieldTy::RecordId {
+            table_name: "person".into(),
+        };
+        let (db, [peter, anna]) = reference_db(person, IndexOnDelete::Restrict);
+
+        let write = insert_task(&db, "write", peter.clone());
+
+        let result = db.delete_record("person", record_id(&peter));
+
+        assert!(matches!(
+            result,
+            Err(DbError::RecordIsReferenced { referenced_by_table, referenced_by, .. })
+                if referenced_by_table.as_ref() == "task" && referenced_by == write
+        ));
+        assert!(db.get("person", record_id(&peter)).unwrap().is_some());
+        assert_eq!(task_owner(&db, write), Some(peter.clone()));
+
+        // Records without references can be deleted
+        db.delete_record("person", record_id(&anna)).unwrap();
+
+        db.delete_record("task", write).unwrap();
+        db.delete_record("person", record_id(&peter)).unwrap();
+
+        assert!(db.get_all("person").unwrap().is_empty());
+    }
+
+    #[test]
+    fn on_delete_set_none() {
+        let person = FieldTy::RecordId {
+            table_name: "person".into(),
+        };
+        let (db, [peter, anna]) = reference_db(
+            FieldTy::Nullable(Box::new(person.clone())),
+            IndexOnDelete::SetNone,
+        );
+
+        let write = insert_task(&db, "write", peter.clone());
+        let test = insert_task(&db, "test", anna.clone());
+
+        db.delete_record("person", record_id(&peter)).unwrap();
+
+        assert_eq!(
+            task_owner(&db, write),
+            Some(FieldValue::Null(person.clone()))
+        );
+        assert_eq!(task_owner(&db, test), Some(anna.clone()));
+        assert_eq!(
+            db.index_query("#task:owner", None, None).unwrap(),
+            [(FieldValue::Null(person.clone()), write), (anna, test)]
+        );
+
+        // References in lists are removed, the other elements are kept
+        let (db, [peter, anna]) = reference_db(
+            FieldTy::List(Box::new(person.clone())),
+            IndexOnDelete::SetNone,
+        );
+
+        let list = |values: &[&FieldValue]| FieldValue::List {
+            ty: Box::new(person.clone()),
+            values: values.iter().map(|value| (*value).clone()).collect(),
+        };
+
+        let write = insert_task(&db, "write", list(&[&peter, &anna]));
+        let review = insert_task(&db, "review", list(&[&peter]));
+
+        db.delete_record("person", record_id(&peter)).unwrap();
+
+        assert_eq!(task_owner(&db, write), Some(list(&[&anna])));
+        assert_eq!(task_owner(&db, review), Some(list(&[])));
+        assert_eq!(
+            db.index_query("#task:owner", None, None).unwrap(),
+            [(anna, write)]
+        );
+    }
+
+    #[test]
+    fn on_delete_none() {
+        let person = FieldTy::RecordId {
+            table_name: "person".into(),
+        };
+        let (db, [peter, anna]) = reference_db(person, IndexOnDelete::None);
+
+        let write = insert_task(&db, "write", peter.clone());
+        let test = insert_task(&db, "test", anna.clone());
+
+        db.delete_record("person", record_id(&peter)).unwrap();
+
+        // The reference is kept, only the index entries of the deleted record are removed
+        assert_eq!(task_owner(&db, write), Some(peter));
+        assert_eq!(
+            db.index_query("#task:owner", None, None).unwrap(),
+            [(anna, test)]
+        );
+    }
+
+    #[test]
+    fn record_errors() {
+        let person = FieldTy::RecordId {
+            table_name: "person".into(),
+        };
+        let (db, [peter, _]) = reference_db(person, IndexOnDelete::Restrict);
+
+        let missing = Ulid::new();
+
+        // References must point to existing records
+        let record = create_record(
+            &db,
+            "task",
+            &[
+                FieldValue::Text("write".into()),
+                FieldValue::RecordId {
+                    id: missing,
+                    table_name: "person".into(),
+                },
+            ],
+        );
+        assert!(matches!(
+            db.insert_record("task", &record),
+            Err(DbError::RecordDoesNotExist { table, record })
+                if table.as_ref() == "person" && record == missing
+        ));
+        assert!(db.get_all("task").unwrap().is_empty());
+
+        assert!(matches!(
+            db.delete_record("person", missing),
+            Err(DbError::RecordDoesNotExist { table, record })
+                if table.as_ref() == "person" && record == missing
+        ));
+
+        let write = insert_task(&db, "write", peter.clone());
+
+        assert!(matches!(
+            db.update_record_fields(
+                "task",
+                write,
+                [Named::new(
+                    "owner",
+                    FieldValue::RecordId {
+                        id: missing,
+                        table_name: "person".into(),
+                    }
+                )]
+            ),
+            Err(DbError::RecordDoesNotExist { .. })
+        ));
+        assert_eq!(task_owner(&db, write), Some(peter.clone()));
+
+        // A failed delete inside a transaction fails the whole transaction
+        let result = db.write(|tx| {
+            tx.delete("task", write)?;
+            tx.delete("person", record_id(&peter))?;
+            tx.delete("person", record_id(&peter))
+        });
+        assert!(matches!(result, Err(DbError::RecordDoesNotExist { .. })));
+        assert!(db.get("task", write).unwrap().is_some());
+
+        let result = db.write(|tx| tx.delete("person", record_id(&peter)));
+        assert!(matches!(
+            result,
+            Err(DbError::RecordIsReferenced { table, record, referenced_by_table, referenced_by })
+                if table.as_ref() == "person"
+                    && record == record_id(&peter)
+                    && referenced_by_table.as_ref() == "task"
+                    && referenced_by == write
+        ));
+        assert!(db.get("person", record_id(&peter)).unwrap().is_some());
+    }
+
+    #[test]
+    fn field_errors() {
+        let db = test_db();
+
+        let record = create_record(
+            &db,
+            "person",
+            &[FieldValue::Text("Peter".into()), FieldValue::Bool(true)],
+        );
+        db.insert_record("person", &record).unwrap();
+
+        let field_does_not_exist = |result: Result<(), DbError>| {
+            matches!(
+                result,
+                Err(DbError::FieldDoesNotExist { table, field })
+                    if table.as_ref() == "person" && field.as_ref() == "age"
+            )
+        };
+        let field_already_exists = |result: Result<(), DbError>| {
+            matches!(
+                result,
+                Err(DbError::FieldAlreadyExists { table, field })
+                    if table.as_ref() == "person" && field.as_ref() == "name"
+            )
+        };
+
+        assert!(field_does_not_exist(db.update_record_fields(
+            "person",
+            record.id(),
+            [Named::new("age", FieldValue::Int(30))]
+        )));
+        assert!(field_does_not_exist(db.alter_table(
+            "person",
+            [AlterTableOp::DropField { name: "age".into() }]
+        )));
+        assert!(field_does_not_exist(db.alter_table(
+            "person",
+            [AlterTableOp::RenameField {
+                name: "age".into(),
+                new_name: "years".into(),
+            }]
+        )));
+        assert!(field_does_not_exist(db.alter_table(
+            "person",
+            [AlterTableOp::RetypeField {
+                name: "age".into(),
+                ty: FieldTy::IntI64,
+                default: FieldValue::I64(0),
+            }]
+        )));
+        assert!(field_does_not_exist(db.alter_table(
+            "person",
+            [AlterTableOp::MoveField {
+                name: "age".into(),
+                index: 0,
+            }]
+        )));
+        assert!(field_does_not_exist(db.alter_table(
+            "person",
+            [AlterTableOp::SetMainDisplayField {
+                name: Some("age".into()),
+            }]
+        )));
+
+        // Earlier operations do not count once a later one fails
+        assert!(field_does_not_exist(db.alter_table(
+            "person",
+            [
+                AlterTableOp::RenameField {
+                    name: "name".into(),
+                    new_name: "full_name".into(),
+                },
+                AlterTableOp::DropField { name: "age".into() },
+            ]
+        )));
+
+        assert!(field_already_exists(db.alter_table(
+            "person",
+            [AlterTableOp::AddField {
+                name: "name".into(),
+                field: TableFieldDef {
+                    ty: FieldTy::Text,
+                    has_index: false,
+                    on_delete: IndexOnDelete::None,
+                    unique: false,
+                },
+                default: FieldValue::Text(String::new()),
+            }]
+        )));
+        assert!(field_already_exists(db.alter_table(
+            "person",
+            [AlterTableOp::RenameField {
+                name: "active".into(),
+                new_name: "name".into(),
+            }]
+        )));
+
+        let table = db.table("person").unwrap();
+        assert_eq!(
+            table
+                .fields()
+                .map(|field| field.name.as_ref())
+                .collect::<Vec<_>>(),
+            ["name", "active"]
+        );
+        assert_eq!(db.get("person", record.id()).unwrap().unwrap(), record);
+    }
+
+    #[test]
+    fn unique_violation() {
+        let db = test_db();
+
+        let mut project = table_def([
+            ("name", FieldTy::Text, false),
+            ("year", FieldTy::IntI32, false),
+        ]);
+        project.fields[0].value.unique = true;
+        project.unique = vec![vec!["year".into(), "name".into()]];
+
+        db.register_table(Named::new("project", project)).unwrap();
+
+        let first = create_record(
+            &db,
+            "project",
+            &[FieldValue::Text("tabletool".into()), FieldValue::Int(2025)],
+        );
+        db.insert_record("project", &first).unwrap();
+
+        let duplicate = create_record(
+            &db,
+            "project",
+            &[FieldValue::Text("tabletool".into()), FieldValue::Int(2026)],
+        );
+
+        let Err(DbError::UniqueViolation { field, record, .. }) =
+            db.insert_record("project", &duplicate)
+        else {
+            panic!("expected a unique violation");
+        };
+        assert_eq!(field.as_ref(), "name");
+        assert_eq!(record, first.id());
+        assert!(db.get("project", duplicate.id()).unwrap().is_none());
+
+        let second = create_record(
+            &db,
+            "project",
+            &[FieldValue::Text("crate".into()), FieldValue::Int(2025)],
+        );
+        db.insert_record("project", &second).unwrap();
+
+        let result = db.update_record_fields(
+            "project",
+            second.id(),
+            [Named::new("name", FieldValue::Text("tabletool".into()))],
+        );
+        assert!(matches!(result, Err(DbError::UniqueViolation { .. })));
+
+        db.update_record_fields(
+            "project",
+            first.id(),
+            [Named::new("year", FieldValue::Int(2026))],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn unique_requires_indexable_fields() {
+        let db = test_db();
+
+        let mut file = table_def([
+            ("name", FieldTy::Text, false),
+            ("data", FieldTy::Blob, false),
+        ]);
+        file.fields[1].value.unique = true;
+
+        let result = db.register_table(Named::new("file", file.clone()));
+        assert!(matches!(result, Err(DbError::UnsupportedIndexType { .. })));
+
+        file.fields[1].value.unique = false;
+        file.unique = vec![vec!["name".into(), "size".into()]];
+
+        let Err(DbError::FieldDoesNotExist { field, .. }) =
+            db.register_table(Named::new("file", file.clone()))
+        else {
+            panic!("expected a missing field");
+        };
+        assert_eq!(field.as_ref(), "size");
+        assert!(db.table("file").is_none());
+
+        file.unique = Vec::new();
+        db.register_table(Named::new("file", file)).unwrap();
+
+        let result = db.alter_table(
+            "file",
+            [AlterTableOp::AddField {
+                name: "thumbnail".into(),
+                field: TableFieldDef {
+                    ty: FieldTy::Nullable(Box::new(FieldTy::Blob)),
+                    has_index: false,
+                    on_delete: IndexOnDelete::None,
+                    unique: true,
+                },
+                default: FieldValue::Null(FieldTy::Blob),
+            }],
+        );
+        assert!(matches!(result, Err(DbError::UnsupportedIndexType { .. })));
+        assert_eq!(db.table("file").unwrap().fields().count(), 2);
+    }
+
+    #[test]
+    fn composite_index_query() {
+        let db = test_db();
+
+        let person = FieldTy::RecordId {
+            table_name: "person".into(),
+        };
+
+        let mut work_time = table_def([
+            ("person", person, false),
+            ("hours", FieldTy::IntI32, false),
+        ]);
+        work_time.indices = vec![vec!["person".into(), "hours".into()]];
+
+        db.register_table(Named::new("work_time", work_time))
+            .unwrap();
+
+        let people = ["Peter", "Anna"].map(|name| {
+            let record = create_record(
+                &db,
+                "person",
+                &[FieldValue::Text(name.into()), FieldValue::Bool(true)],
+            );
+            db.insert_record("person", &record).unwrap();
+
+            FieldValue::RecordId {
+                id: record.id(),
+                table_name: "person".into(),
+            }
+        });
+
+        for (person, hours) in [(0, 8), (1, 3), (0, 2), (0, 5), (1, 9)] {
+            let record = create_record(
+                &db,
+                "work_time",
+                &[people[person].clone(), FieldValue::Int(hours)],
+            );
+            db.insert_record("work_time", &record).unwrap();
+        }
+
+        let hours = |prefix: &[FieldValue], min: Option<i32>, max: Option<i32>| {
+            db.index_query_composite(
+                "#work_time:person,hours",
+                prefix,
+                min.map(FieldValue::Int),
+                max.map(FieldValue::Int),
+            )
+            .unwrap()
+            .into_iter()
+            .map(|(values, _)| values[1].clone())
+            .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            hours(&people[..1], None, None),
+            [2, 5, 8].map(FieldValue::Int)
+        );
+        assert_eq!(
+            hours(&people[..1], Some(3), Some(8)),
+            [5].map(FieldValue::Int)
+        );
+        assert_eq!(hours(&people[1..], Some(4), None), [9].map(FieldValue::Int));
+
+        let result = db.index_query_composite(
+            "#work_time:person,hours",
+            &[people[0].clone(), FieldValue::Int(8)],
+            Some(FieldValue::Int(0)),
+            None,
+        );
+        assert!(matches!(result, Err(DbError::IndexFieldCount { .. })));
+    }
+
+    #[test]
+    fn composite_index_requires_indexable_fields() {
+        let db = test_db();
+
+        let mut work_time = table_def([
+            ("title", FieldTy::Text, false),
+            ("hours", FieldTy::IntI32, false),
+            ("tags", FieldTy::List(Box::new(FieldTy::Text)), true),
+            ("attachment", FieldTy::Blob, false),
+        ]);
+
+        work_time.indices = vec![vec!["title".into(), "project".into()]];
+        let result = db.register_table(Named::new("work_time", work_time.clone()));
+        assert!(matches!(result, Err(DbError::FieldDoesNotExist { .. })));
+
+        for field in ["tags", "attachment"] {
+            work_time.indices = vec![vec!["title".into(), field.into()]];
+            let result = db.register_table(Named::new("work_time", work_time.clone()));
+            assert!(matches!(result, Err(DbError::UnsupportedIndexType { .. })));
+        }
+
+        assert!(db.table("work_time").is_none());
+
+        work_time.indices = vec![vec!["title".into(), "hours".into()]];
+        db.register_table(Named::new("work_time", work_time))
+            .unwrap();
+
+        let result = db.alter_table(
+            "work_time",
+            [AlterTableOp::RetypeField {
+                name: "hours".into(),
+                ty: FieldTy::Nullable(Box::new(FieldTy::Blob)),
+                default: FieldValue::Null(FieldTy::Blob),
+            }],
+        );
+        assert!(matches!(result, Err(DbError::UnsupportedIndexType { .. })));
+    }
+
+    #[test]
+    fn query_plan() {
+        let db = test_db();
+
+        let person = FieldTy::RecordId {
+            table_name: "person".into(),
+        };
+
+        let mut work_time = table_def([
+            ("person", person, false),
+            ("hours", FieldTy::IntI32, false),
+        ]);
+        work_time.indices = vec![vec!["person".into(), "hours".into()]];
+
+        db.register_table(Named::new("work_time", work_time))
+            .unwrap();
+
+        let people = ["Peter", "Anna"].map(|name| {
+            let record = create_record(
+                &db,
+                "person",
+                &[FieldValue::Text(name.into()), FieldValue::Bool(true)],
+            );
+            db.insert_record("person", &record).unwrap();
+
+            FieldValue::RecordId {
+                id: record.id(),
+                table_name: "person".into(),
+            }
+        });
+
+        for (person, hours) in [(0, 8), (1, 3), (0, 2), (0, 5), (1, 9)] {
+            let record = create_record(
+                &db,
+                "work_time",
+                &[people[person].clone(), FieldValue::Int(hours)],
+            );
+            db.insert_record("work_time", &record).unwrap();
+        }
+
+        let field = |name: &str| Expr::FieldAccess {
+            value: Box::new(Expr::TableAccess {
+                name: "work_time".into(),
+            }),
+            field: name.into(),
+        };
+        let binary = |a: Expr, op: BinaryOp, b: Expr| Expr::BinaryOp {
+            a: Box::new(a),
+            op,
+            b: Box::new(b),
+        };
+        let query = |filter: Expr| Query {
+            table_name: "work_time".into(),
+            joins: Vec::new(),
+            filter: Some(filter),
+            group_by: None,
+            select: Vec::new(),
+            order_by: Vec::new(),
+            limit: None,
+            offset: None,
+            on_error: OnError::Skip,
+        };
+        let hours = |query: &Query| {
+            let Ok(QueryResult::Records(result)) = db.run_query(query) else {
+                panic!("expected records");
+            };
+
+            let field = result.format.field("hours").unwrap();
+
+            let mut hours = result
+                .records
+                .iter()
+                .map(|record| match record.get_field(field) {
+                    Some(FieldValue::Int(hours)) => hours,
+                    value => panic!("unexpected value {value:?}"),
+                })
+                .collect::<Vec<_>>();
+            hours.sort();
+
+            hours
+        };
+
+        // `3 < hours` is flipped to `hours > 3`, `hours != 8` is left to the filter
+        let indexed = query(binary(
+            binary(
+                field("person"),
+                BinaryOp::Eq(EqOp::Eq),
+                Expr::Literal(people[0].clone()),
+            ),
+            BinaryOp::Logic(LogicOp::And),
+            binary(
+                binary(
+                    Expr::Literal(FieldValue::Int(3)),
+                    BinaryOp::Compare(CompareOp::Less),
+                    field("hours"),
+                ),
+                BinaryOp::Logic(LogicOp::And),
+                binary(
+                    field("hours"),
+                    BinaryOp::Eq(EqOp::Neq),
+                    Expr::Literal(FieldValue::Int(8)),
+                ),
+            ),
+        ));
+
+        assert_eq!(
+            db.explain(&indexed).unwrap(),
+            QueryPlan::IndexScan {
+                index_name: "#work_time:person,hours".into(),
+                field_names: vec!["person".into(), "hours".into()],
+                prefix: vec![people[0].clone()],
+                lower: Bound::Excluded(FieldValue::Int(3)),
+                upper: Bound::Unbounded,
+                reverse: false,
+                joins: Vec::new(),
+                filter: Some(binary(
+                    field("hours"),
+                    BinaryOp::Eq(EqOp::Neq),
+                    Expr::Literal(FieldValue::Int(8)),
+                )),
+                order_by: Vec::new(),
+            }
+        );
+        assert_eq!(hours(&indexed), [5]);
+
+        // The index can not be used without a value for its first field
+        let full_scan = query(binary(
+            field("hours"),
+            BinaryOp::Compare(CompareOp::GreaterEq),
+            Expr::Literal(FieldValue::Int(5)),
+        ));
+
+        assert!(matches!(
+            db.explain(&full_scan).unwrap(),
+            QueryPlan::FullScan { .. }
+        ));
+        assert_eq!(hours(&full_scan), [5, 8, 9]);
+
+        let by_person = query(binary(
+            field("person"),
+            BinaryOp::Eq(EqOp::Eq),
+            Expr::Literal(people[1].clone()),
+        ));
+
+        assert_eq!(
+            db.explain(&by_person).unwrap().to_string(),
+            format!("index scan of #work_time:person where person == {}", people[1])
+        );
+        assert_eq!(hours(&by_person), [3, 9]);
+    }
+
+    #[test]
+    fn query_order_limit() {
+        let db = test_db();
+
+        db.register_table(Named::new(
+            "task",
+            table_def([
+                ("priority", FieldTy::IntI32, true),
+                ("title", FieldTy::Text, false),
+            ]),
+        ))
+        .unwrap();
+
+        for (priority, title) in [(3, "c"), (1, "e"), (2, "a"), (5, "d"), (4, "b")] {
+            let record = create_record(
+                &db,
+                "task",
+                &[FieldValue::Int(priority), FieldValue::Text(title.into())],
+            );
+            db.insert_record("task", &record).unwrap();
+        }
+
+        let order_by = |field: &str, direction: SortDirection| OrderBy {
+            expr: Expr::FieldAccess {
+                value: Box::new(Expr::TableAccess {
+                    name: "task".into(),
+                }),
+                field: field.into(),
+            },
+            direction,
+        };
+        let query = |order_by: OrderBy, limit: Option<usize>, offset: Option<usize>| Query {
+            table_name: "task".into(),
+            joins: Vec::new(),
+            filter: None,
+            group_by: None,
+            select: Vec::new(),
+            order_by: vec![order_by],
+            limit,
+            offset,
+            on_error: OnError::Skip,
+        };
+        let titles = |query: &Query| {
+            let Ok(QueryResult::Records(result)) = db.run_query(query) else {
+                panic!("expected records");
+            };
+
+            let field = result.format.field("title").unwrap();
+
+            result
+                .records
+                .iter()
+                .map(|record| match record.get_field(field) {
+                    Some(FieldValue::Text(title)) => title,
+                    value => panic!("unexpected value {value:?}"),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // The index on `priority` is read backwards instead of sorting
+        let by_priority = query(order_by("priority", SortDirection::Desc), Some(2), None);
+
+        assert_eq!(
+            db.explain(&by_priority).unwrap().to_string(),
+            "index scan of #task:priority in reverse"
+        );
+        assert_eq!(titles(&by_priority), ["d", "b"]);
+
+        let by_title = query(order_by("title", SortDirection::Asc), Some(3), Some(1));
+
+        assert_eq!(
+            db.explain(&by_title).unwrap().to_string(),
+            "full scan of task, then sort by task.title asc"
+        );
+        assert_eq!(titles(&by_title), ["b", "c", "d"]);
+    }
+
+    #[test]
+    fn query_select() {
+        let db = test_db();
+
+        db.register_table(Named::new(
+            "task",
+            table_def([
+                ("project", FieldTy::Text, false),
+                ("hours", FieldTy::IntI32, false),
+            ]),
+        ))
+        .unwrap();
+
+        for (project, hours) in [("a", 3), ("b", 5), ("a", 4), ("b", 1), ("a", 2)] {
+            let record = create_record(
+                &db,
+                "task",
+                &[FieldValue::Text(project.into()), FieldValue::Int(hours)],
+            );
+            db.insert_record("task", &record).unwrap();
+        }
+
+        let query = query_parse::parse(
+            "query task group_by task.project select count(), sum(task.hours), max(task.hours), avg(task.hours) order_by task.hours",
+        )
+        .unwrap();
+
+        let Ok(QueryResult::Grouped { groups }) = db.run_query(&query) else {
+            panic!("expected groups");
+        };
+
+        let groups = groups
+            .into_iter()
+            .map(|group| {
+                let QueryResult::Records(result) = group.result else {
+                    panic!("expected records");
+                };
+
+                let values = result
+                    .aggregates
+                    .into_iter()
+                    .map(|aggregate| aggregate.value)
+                    .collect::<Vec<_>>();
+
+                (group.group, values)
+            })
+            .collect::<Vec<_>>();
+
+        let int = |value| Value::Field(FieldValue::Int(value));
+
+        // Groups are ordered by their first record, `b` has the smallest value
+        assert_eq!(
+            groups,
+            [
+                (
+                    Value::Field(FieldValue::Text("b".into())),
+                    vec![int(2), int(6), int(5), int(3)]
+                ),
+                (
+                    Value::Field(FieldValue::Text("a".into())),
+                    vec![int(3), int(9), int(4), int(3)]
+                ),
+            ]
+        );
+
+        let query = query_parse::parse(
+            "query task where task.project == \"a\" select first(task.hours) * 10, count() order_by task.hours desc",
+        )
+        .unwrap();
+
+        let Ok(QueryResult::Records(result)) = db.run_query(&query) else {
+            panic!("expected records");
+        };
+
+        let values = result
+            .aggregates
+            .into_iter()
+            .map(|aggregate| (aggregate.name, aggregate.value))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            values,
+            [
+                ("first(task.hours) * 10".into(), int(40)),
+                ("count()".into(), int(3)),
+            ]
+        );
+
+        // Without aggregate functions every record becomes a row
+        let query = query_parse::parse(
+            "query task select task.hours * 2 as double, task.project order_by task.hours limit 2",
+        )
+        .unwrap();
+
+        let Ok(QueryResult::Rows(result)) = db.run_query(&query) else {
+            panic!("expected rows");
+        };
+
+        assert_eq!(
+            result.columns,
+            [
+                Named::new("double", Ty::Field(FieldTy::IntI32)),
+                Named::new("task.project", Ty::Field(FieldTy::Text)),
+            ]
+        );
+
+        let text = |value: &str| Value::Field(FieldValue::Text(value.into()));
+
+        assert_eq!(
+            result
+                .rows
+                .into_iter()
+                .map(|row| row.values)
+                .collect::<Vec<_>>(),
+            [vec![int(2), text("b")], vec![int(4), text("a")]]
+        );
+    }
+
+    #[test]
+    fn query_columns() {
+        let db = work_time_db();
+
+        let columns = |query: &str| {
+            let query = query_parse::parse(query).unwrap();
+
+            let Ok(QueryResult::Rows(result)) = db.run_query(&query) else {
+                panic!("expected rows");
+            };
+
+            result.columns
+        };
+
+        let int = Ty::Field(FieldTy::IntI32);
+        let text = Ty::Field(FieldTy::Text);
+
+        // Without `as` the expression is the name, nested operators are put in parentheses
+        assert_eq!(
+            columns(
+                "query work_time join project on work_time.project == project select project.name as project, work_time.hours * 2 + 1, work_time.hours * (2 + 1), -work_time.hours, lower(project.name), \"total\", work_time.project.group.name"
+            ),
+            [
+                Named::new("project", text.clone()),
+                Named::new("(work_time.hours * 2) + 1", int.clone()),
+                Named::new("work_time.hours * (2 + 1)", int.clone()),
+                Named::new("-work_time.hours", int.clone()),
+                Named::new("lower(project.name)", text.clone()),
+                Named::new("\"total\"", text.clone()),
+                Named::new(
+                    "work_time.project.group.name",
+                    Ty::Field(FieldTy::Nullable(Box::new(FieldTy::Text))),
+                ),
+            ]
+        );
+
+        // Names may repeat or shadow fields, columns are known even without rows
+        assert_eq!(
+            columns(
+                "query work_time where work_time.hours > 100 select work_time.hours as project, work_time.project as project, work_time.hours"
+            ),
+            [
+                Named::new("project", int.clone()),
+                Named::new(
+                    "project",
+                    Ty::Field(FieldTy::RecordId {
+                        table_name: "project".into()
+                    }),
+                ),
+                Named::new("work_time.hours", int),
+            ]
+        );
+    }
+
+    /// Projects `app` in group `dev` with 3 and 5 hours of work time, and `docs` without group with 2 hours
+    fn work_time_db() -> Db {
+        let db = test_db();
+
+        let record_id = |table_name: &str| FieldTy::RecordId {
+            table_name: table_name.into(),
+        };
+
+        db.register_table(Named::new(
+            "project_group",
+            table_def([("name", FieldTy::Text, false)]),
+        ))
+        .unwrap();
+        db.register_table(Named::new(
+            "project",
+            table_def([
+                ("name", FieldTy::Text, false),
+                (
+                    "group",
+                    FieldTy::Nullable(Box::new(record_id("project_group"))),
+                    false,
+                ),
+            ]),
+        ))
+        .unwrap();
+        db.register_table(Named::new(
+            "work_time",
+            table_def([
+                ("project", record_id("project"), false),
+                ("hours", FieldTy::IntI32, false),
+            ]),
+        ))
+        .unwrap();
+
+        let insert = |table_name: &str, values: &[FieldValue]| {
+            let record = create_record(&db, table_name, values);
+            db.insert_record(table_name, &record).unwrap();
+
+            FieldValue::RecordId {
+                id: record.id(),
+                table_name: table_name.into(),
+            }
+        };
+
+        let group = insert("project_group", &[FieldValue::Text("dev".into())]);
+        let app = insert("project", &[FieldValue::Text("app".into()), group]);
+        let docs = insert(
+            "project",
+            &[
+                FieldValue::Text("docs".into()),
+                FieldValue::Null(record_id("project_group")),
+            ],
+        );
+
+        for (project, hours) in [(&app, 3), (&app, 5), (&docs, 2)] {
+            insert("work_time", &[project.clone(), FieldValue::Int(hours)]);
+        }
+
+        db
+    }
+
+    #[test]
+    fn query_join() {
+        let db = work_time_db();
+
+        let int = |value| Value::Field(FieldValue::Int(value));
+        let text = |value: &str| Value::Field(FieldValue::Text(value.into()));
+
+        let rows = |query: &str| {
+            let query = query_parse::parse(query).unwrap();
+
+            let Ok(QueryResult::Rows(result)) = db.run_query(&query) else {
+                panic!("expected rows");
+            };
+
+            result
+                .rows
+                .into_iter()
+                .map(|row| row.values)
+                .collect::<Vec<_>>()
+        };
+
+        // `RecordId` fields are dereferenced, the group of `docs` is null and does not match
+        let query = query_parse::parse(
+            "query work_time where work_time.project.group.name == \"dev\" select sum(work_time.hours)",
+        )
+        .unwrap();
+
+        let Ok(QueryResult::Records(result)) = db.run_query(&query) else {
+            panic!("expected records");
+        };
+
+        assert_eq!(result.records.len(), 2);
+        assert_eq!(result.aggregates[0].value, int(8));
+
+        let query = query_parse::parse(
+            "query work_time group_by work_time.project.name select sum(work_time.hours) order_by work_time.hours",
+        )
+        .unwrap();
+
+        let Ok(QueryResult::Grouped { groups }) = db.run_query(&query) else {
+            panic!("expected groups");
+        };
+
+        let groups = groups
+            .into_iter()
+            .map(|group| {
+                let QueryResult::Records(result) = group.result else {
+                    panic!("expected records");
+                };
+
+                (group.group, result.aggregates[0].value.clone())
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(groups, [(text("docs"), int(2)), (text("app"), int(8))]);
+
+        // Joining the referenced record reads it by id
+        let query = query_parse::parse(
+            "query work_time join project on work_time.project == project where project.name == \"docs\"",
+        )
+        .unwrap();
+
+        assert_eq!(
+            db.explain(&query).unwrap().to_string(),
+            "full scan of work_time, then join project by id work_time.project, then filter project.name == \"docs\""
+        );
+
+        assert_eq!(
+            rows(
+                "query work_time join project on work_time.project == project where project.name == \"docs\" select work_time.hours, project.name"
+            ),
+            [vec![int(2), text("docs")]]
+        );
+
+        // Joining the referencing records uses the index of the `RecordId` field
+        let query = query_parse::parse(
+            "query project join work_time on work_time.project == project order_by work_time.hours",
+        )
+        .unwrap();
+
+        assert_eq!(
+            db.explain(&query).unwrap().to_string(),
+            "full scan of project, then join work_time by index #work_time:project where work_time.project == project, then sort by work_time.hours asc"
+        );
+
+        // Every record of the queried table is returned once
+        let Ok(QueryResult::Records(result)) = db.run_query(&query) else {
+            panic!("expected records");
+        };
+
+        assert_eq!(result.records.len(), 2);
+
+        assert_eq!(
+            rows(
+                "query project join work_time on work_time.project == project select project.name, work_time.hours order_by work_time.hours"
+            ),
+            [
+                vec![text("docs"), int(2)],
+                vec![text("app"), int(3)],
+                vec![text("app"), int(5)],
+            ]
+        );
+
+        // Conditions without an equality are evaluated for every pair of records
+        assert_eq!(
+            rows(
+                "query project_group join project on project.name != project_group.name select project.name order_by project.name"
+            ),
+            [vec![text("app")], vec![text("docs")]]
+        );
+
+        assert!(matches!(
+            db.run_query(&query_parse::parse("query project join project on true").unwrap()),
+            Err(DbError::DuplicateQueryTable { .. })
+        ));
+    }
+
+    #[test]
+    fn query_referenced_by() {
+        let db = work_time_db();
+
+        let int = |value| Value::Field(FieldValue::Int(value));
+        let text = |value: &str| Value::Field(FieldValue::Text(value.into()));
+
+        let query = query_parse::parse(
+            "query project select project.name, count(project.referenced_by(work_time.project)) as entries, sum(project.referenced_by(work_time.project).hours) order_by project.name",
+        )
+        .unwrap();
+
+        let Ok(QueryResult::Rows(result)) = db.run_query(&query) else {
+            panic!("expected rows");
+        };
+
+        assert_eq!(
+            result.columns,
+            [
+                Named::new("project.name", Ty::Field(FieldTy::Text)),
+                Named::new("entries", Ty::Field(FieldTy::IntI32)),
+                Named::new(
+                    "sum(project.referenced_by(work_time.project).hours)",
+                    Ty::Field(FieldTy::IntI32),
+                ),
+            ]
+        );
+        assert_eq!(
+            result
+                .rows
+                .into_iter()
+                .map(|row| row.values)
+                .collect::<Vec<_>>(),
+            [
+                vec![text("app"), int(2), int(8)],
+                vec![text("docs"), int(1), int(2)],
+            ]
+        );
+
+        // Relations can be followed from referenced records and used in filters
+        let query = query_parse::parse(
+            "query project_group where count(project_group.referenced_by(project.group)) > 0 select project_group.name, count(project_group.referenced_by(project.group)), first(project_group.referenced_by(project.group)).name",
+        )
+        .unwrap();
+
+        let Ok(QueryResult::Rows(result)) = db.run_query(&query) else {
+            panic!("expected rows");
+        };
+
+        assert_eq!(result.rows[0].values, [text("dev"), int(1), text("app")]);
+
+        // The relation itself evaluates to the referencing records
+        let query = query_parse::parse(
+            "query project where project.name == \"app\" select project.referenced_by(work_time.project)",
+        )
+        .unwrap();
+
+        let Ok(QueryResult::Rows(result)) = db.run_query(&query) else {
+            panic!("expected rows");
+        };
+
+        let Value::Records { table, records } = &result.rows[0].values[0] else {
+            panic!("expected records");
+        };
+
+        assert_eq!(table.name.as_ref(), "work_time");
+        assert_eq!(records.len(), 2);
+
+        // Only `RecordId` fields can be followed backwards
+        let query =
+            query_parse::parse("query project select project.referenced_by(work_time.hours)")
+                .unwrap();
+
+        assert!(matches!(
+            db.run_query(&query),
+            Err(DbError::InvalidQuery {
+                err: EvalErr::NotAReference { .. },
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn query_duration() {
+        let db = test_db();
+
+        db.register_table(Named::new(
+            "shift",
+            table_def([
+                ("start_time", FieldTy::Timestamp, false),
+                ("end_time", FieldTy::Timestamp, false),
+                ("pause", FieldTy::Duration, true),
+            ]),
+        ))
+        .unwrap();
+
+        let timestamp = |hour: i64| {
+            FieldValue::Timestamp(DateTime::from_timestamp(1_710_000_000 + hour * 3600, 0).unwrap())
+        };
+
+        for (start, end, pause) in [(0, 8, 30), (24, 30, 0), (48, 58, 45)] {
+            let record = create_record(
+                &db,
+                "shift",
+                &[
+                    timestamp(start),
+                    timestamp(end),
+                    FieldValue::Duration(TimeDelta::minutes(pause)),
+                ],
+            );
+            db.insert_record("shift", &record).unwrap();
+        }
+
+        let query = query_parse::parse(
+            "query shift where shift.pause >= 30m select sum(shift.end_time - shift.start_time - shift.pause) as worked, hours(max(shift.end_time - shift.start_time))",
+        )
+        .unwrap();
+
+        assert_eq!(
+            db.explain(&query).unwrap().to_string(),
+            "index scan of #shift:pause where pause >= 30m"
+        );
+
+        let Ok(QueryResult::Records(result)) = db.run_query(&query) else {
+            panic!("expected records");
+        };
+
+        assert_eq!(result.records.len(), 2);
+        assert_eq!(
+            result.aggregates,
+            [
+                Named::new(
+                    "worked",
+                    Value::Field(FieldValue::Duration(TimeDelta::minutes(16 * 60 + 45)))
+                ),
+                Named::new(
+                    "hours(max(shift.end_time - shift.start_time))",
+                    Value::Field(FieldValue::Int(10))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn query_numbers() {
+        let db = test_db();
+
+        db.register_table(Named::new(
+            "item",
+            table_def([
+                ("price", FieldTy::Decimal, true),
+                ("weight", FieldTy::F64, true),
+                ("stock", FieldTy::IntI64, true),
+            ]),
+        ))
+        .unwrap();
+
+        let decimal = |value: &str| FieldValue::Decimal(value.parse::<Decimal>().unwrap());
+
+        for (price, weight, stock) in [
+            ("4.99", -0.5, 3_000_000_000),
+            ("5", 1.25, 2),
+            ("12.5", 2.0, 10),
+            ("7.0001", 0.25, -4),
+        ] {
+            let record = create_record(
+                &db,
+                "item",
+                &[
+                    decimal(price),
+                    FieldValue::F64(Float(weight)),
+                    FieldValue::I64(stock),
+                ],
+            );
+            db.insert_record("item", &record).unwrap();
+        }
+
+        // The integer literal is converted to the decimal type of the index
+        let query = query_parse::parse(
+            "query item where item.price > 5 select sum(item.price) as total, avg(item.weight) as weight, max(item.stock) as stock",
+        )
+        .unwrap();
+
+        assert_eq!(
+            db.explain(&query).unwrap().to_string(),
+            "index scan of #item:price where price > 5"
+        );
+
+        let Ok(QueryResult::Records(result)) = db.run_query(&query) else {
+            panic!("expected records");
+        };
+
+        assert_eq!(result.records.len(), 2);
+        assert_eq!(
+            result.aggregates,
+            [
+                Named::new("total", Value::Field(decimal("19.5001"))),
+                Named::new("weight", Value::Field(FieldValue::F64(Float(1.125)))),
+                Named::new("stock", Value::Field(FieldValue::I64(10))),
+            ]
+        );
+
+        // Negative floats are ordered before positive ones in the index, stored `NaN`s are filtered out
+        let query =
+            query_parse::parse("query item where item.weight < 1 select item.price").unwrap();
+
+        assert_eq!(
+            db.explain(&query).unwrap().to_string(),
+            "index scan of #item:weight where weight < 1.0, then filter item.weight < 1"
+        );
+
+        let Ok(QueryResult::Rows(result)) = db.run_query(&query) else {
+            panic!("expected rows");
+        };
+
+        assert_eq!(
+            result
+                .rows
+                .into_iter()
+                .map(|row| row.values)
+                .collect::<Vec<_>>(),
+            [
+                vec![Value::Field(decimal("4.99"))],
+                vec![Value::Field(decimal("7.0001"))],
+            ]
+        );
+
+        // Comparing with a float converts the stored integers, so the index can not be used
+        let query = query_parse::parse("query item where item.stock > 2.5").unwrap();
+
+        assert!(matches!(
+            db.explain(&query).unwrap(),
+            QueryPlan::FullScan { .. }
+        ));
+    }
+
+    #[test]
+    fn query_inexact_keys() {
+        let db = test_db();
+
+        db.register_table(Named::new(
+            "sample",
+            table_def([
+                ("at", FieldTy::Timestamp, true),
+                ("value", FieldTy::F64, true),
+            ]),
+        ))
+        .unwrap();
+
+        let at = |nanos: u32| {
+            FieldValue::Timestamp(DateTime::from_timestamp(1_710_000_000, nanos).unwrap())
+        };
+
+        for (seconds, value) in [(0, -0.0), (1, 0.0), (2, f64::NAN), (3, 1.5), (4, -1.5)] {
+            let record = create_record(
+                &db,
+                "sample",
+                &[
+                    FieldValue::Timestamp(
+                        DateTime::from_timestamp(1_710_000_000 + seconds, 0).unwrap(),
+                    ),
+                    FieldValue::F64(Float(value)),
+                ],
+            );
+            db.insert_record("sample", &record).unwrap();
+        }
+
+        let count = |op: BinaryOp, field_name: &str, value: FieldValue| {
+            let query = Query {
+                table_name: "sample".into(),
+                joins: Vec::new(),
+                filter: Some(Expr::BinaryOp {
+                    a: Box::new(Expr::FieldAccess {
+                        value: Box::new(Expr::TableAccess {
+                            name: "sample".into(),
+                        }),
+                        field: field_name.into(),
+                    }),
+                    op,
+                    b: Box::new(Expr::Literal(value)),
+                }),
+                group_by: None,
+                select: Vec::new(),
+                order_by: Vec::new(),
+                limit: None,
+                offset: None,
+                on_error: OnError::Skip,
+            };
+
+            assert!(matches!(
+                db.explain(&query),
+                Ok(QueryPlan::IndexScan { .. })
+            ));
+
+            let Ok(QueryResult::Records(result)) = db.run_query(&query) else {
+                panic!("expected records");
+            };
+
+            result.records.len()
+        };
+
+        let eq = BinaryOp::Eq(EqOp::Eq);
+        let compare = BinaryOp::Compare;
+
+        // Times with a sub-second part lie between the keys of whole seconds
+        assert_eq!(count(compare(CompareOp::Greater), "at", at(500_000_000)), 4);
+        assert_eq!(count(compare(CompareOp::LessEq), "at", at(500_000_000)), 1);
+        assert_eq!(count(eq, "at", at(500_000_000)), 0);
+        assert_eq!(count(eq, "at", at(0)), 1);
+
+        // `-0.0` is equal to `0.0` and `NaN` to nothing
+        let float = |value: f64| FieldValue::F64(Float(value));
+
+        let query = query_parse::parse("query sample where sample.value == 0.0").unwrap();
+        assert!(matches!(db.explain(&query), Ok(QueryPlan::FullScan { .. })));
+        let Ok(QueryResult::Records(result)) = db.run_query(&query) else {
+            panic!("expected records");
+        };
+        assert_eq!(result.records.len(), 2);
+
+        assert_eq!(count(compare(CompareOp::GreaterEq), "value", float(0.0)), 3);
+        assert_eq!(count(compare(CompareOp::LessEq), "value", float(-0.0)), 3);
+        assert_eq!(count(compare(CompareOp::Less), "value", float(0.0)), 1);
+        assert_eq!(count(compare(CompareOp::Greater), "value", float(-0.0)), 1);
+        assert_eq!(count(compare(CompareOp::Greater), "value", float(1.0)), 1);
+        assert_eq!(count(eq, "value", float(1.5)), 1);
+    }
+
+    #[test]
+    fn query_on_error() {
+        let db = test_db();
+
+        db.register_table(Named::new(
+            "batch",
+            table_def([
+                ("total", FieldTy::IntI32, false),
+                ("count", FieldTy::IntI32, false),
+            ]),
+        ))
+        .unwrap();
+
+        for (total, count) in [(10, 2), (5, 0), (9, 3)] {
+            let record = create_record(
+                &db,
+                "batch",
+                &[FieldValue::Int(total), FieldValue::Int(count)],
+            );
+            db.insert_record("batch", &record).unwrap();
+        }
+
+        let query = query_parse::parse("query batch where batch.total / batch.count > 3").unwrap();
+
+        let Ok(QueryResult::Records(result)) = db.run_query(&query) else {
+            panic!("expected records");
+        };
+
+        assert_eq!(result.records.len(), 1);
+
+        let query = query_parse::parse(
+            "query batch where batch.total / batch.count > 3 on_error fail",
+        )
+        .unwrap();
+
+        let Err(DbError::Eval {
+            err: EvalErr::DivisionByZero { a, b },
+        }) = db.run_query(&query)
+        else {
+            panic!("expected division by zero");
+        };
+
+        assert_eq!((*a, *b), (FieldValue::Int(5), FieldValue::Int(0)));
+
+        // Order keys that can not be evaluated are sorted like null values, unless the query fails
+        let query = query_parse::parse("query batch order_by batch.total / batch.count").unwrap();
+
+        let Ok(QueryResult::Records(result)) = db.run_query(&query) else {
+            panic!("expected records");
+        };
+
+        assert_eq!(result.records.len(), 3);
+
+        let query =
+            query_parse::parse("query batch order_by batch.total / batch.count on_error fail")
+                .unwrap();
+
+        assert!(matches!(
+            db.run_query(&query),
+            Err(DbError::Eval {
+                err: EvalErr::DivisionByZero { .. }
+            })
+        ));
+
+        // Errors in select fail the query either way
+        let query = query_parse::parse(
+            "query batch where batch.count == 0 select batch.total * 1000000000",
+        )
+        .unwrap();
+
+        assert!(matches!(
+            db.run_query(&query),
+            Err(DbError::Eval {
+                err: EvalErr::Overflow { .. }
+            })
+        ));
+    }
+
+    #[test]
+    fn query_null() {
+        let db = test_db();
+
+        let mut timer = table_def([
+            ("start_time", FieldTy::Timestamp, false),
+            (
+                "end_time",
+                FieldTy::Nullable(Box::new(FieldTy::Timestamp)),
+                true,
+            ),
+        ]);
+        timer.fields[1].value.unique = true;
+
+        db.register_table(Named::new("timer", timer)).unwrap();
+
+        let timestamp = |hour: i64| {
+            FieldValue::Timestamp(DateTime::from_timestamp(1_710_000_000 + hour * 3600, 0).unwrap())
+        };
+        let running = FieldValue::Null(FieldTy::Timestamp);
+
+        // Unique fields may hold any number of nulls
+        for (start, end) in [(0, timestamp(8)), (24, running.clone()), (48, running)] {
+            let record = create_record(&db, "timer", &[timestamp(start), end]);
+            db.insert_record("timer", &record).unwrap();
+        }
+
+        let duplicate = create_record(&db, "timer", &[timestamp(72), timestamp(8)]);
+        assert!(matches!(
+            db.insert_record("timer", &duplicate),
+            Err(DbError::UniqueViolation { .. })
+        ));
+
+        let count = |query: &str| {
+            let query = query_parse::parse(query).unwrap();
+
+            let Ok(QueryResult::Records(result)) = db.run_query(&query) else {
+                panic!("expected records");
+            };
+
+            result.records.len()
+        };
+
+        let query = query_parse::parse("query timer where timer.end_time is null").unwrap();
+
+        assert_eq!(
+            db.explain(&query).unwrap().to_string(),
+            "index scan of #timer:end_time where end_time is null"
+        );
+        assert_eq!(count("query timer where timer.end_time is null"), 2);
+
+        // Ranges skip the null entries, which sort before every value
+        let query = query_parse::parse("query timer where timer.end_time < now()").unwrap();
+
+        assert!(matches!(
+            db.explain(&query).unwrap(),
+            QueryPlan::IndexScan { .. }
+        ));
+        assert_eq!(count("query timer where timer.end_time < now()"), 1);
+        assert_eq!(count("query timer where timer.end_time is not null"), 1);
+
+        // A null condition is not true, and neither is its negation
+        assert_eq!(count("query timer where !(timer.end_time < now())"), 0);
+
+        let query = query_parse::parse(
+            "query timer select hours(coalesce(timer.end_time, timer.start_time + 2h) - timer.start_time) order_by timer.start_time",
+        )
+        .unwrap();
+
+        let Ok(QueryResult::Rows(result)) = db.run_query(&query) else {
+            panic!("expected rows");
+        };
+
+        let hours = result
+            .rows
+            .into_iter()
+            .map(|row| row.values)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            hours,
+            [8, 2, 2].map(|hours| vec![Value::Field(FieldValue::Int(hours))])
+        );
+    }
+
+    #[test]
+    fn query_enum() {
+        let db = test_db();
+
+        let status = FieldTy::Enum {
+            variants: ["open", "in progress", "done"].map(Arc::from).into(),
+        };
+
+        // The variants are stored with the type
+        let bytes = BytePacker::pack_value(&status);
+        assert_eq!(
+            FieldTy::unpack(0, &ByteUnpacker::new(&bytes)),
+            Some(status.clone())
+        );
+
+        db.register_table(Named::new(
+            "issue",
+            table_def([
+                ("title", FieldTy::Text, false),
+                ("status", status.clone(), true),
+            ]),
+        ))
+        .unwrap();
+
+        for (title, variant) in [
+            ("a", "done"),
+            ("b", "open"),
+            ("c", "done"),
+            ("d", "in progress"),
+        ] {
+            let record = create_record(
+                &db,
+                "issue",
+                &[
+                    FieldValue::Text(title.into()),
+                    status.variant(variant).unwrap(),
+                ],
+            );
+            db.insert_record("issue", &record).unwrap();
+        }
+
+        let FieldTy::Enum { variants } = &status else {
+            unreachable!();
+        };
+
+        let invalid = create_record(
+            &db,
+            "issue",
+            &[
+                FieldValue::Text("e".into()),
+                FieldValue::Enum {
+                    index: 3,
+                    variants: variants.clone(),
+                },
+            ],
+        );
+        assert!(matches!(
+            db.insert_record("issue", &invalid),
+            Err(DbError::CorruptRecord { .. })
+        ));
+
+        let rows = |query: &str| {
+            let query = query_parse::parse(query).unwrap();
+
+            let Ok(QueryResult::Rows(result)) = db.run_query(&query) else {
+                panic!("expected rows");
+            };
+
+            result
+                .rows
+                .into_iter()
+                .map(|row| row.values)
+                .collect::<Vec<_>>()
+        };
+        let text = |value: &str| vec![Value::Field(FieldValue::Text(value.into()))];
+
+        let query =
+            query_parse::parse("query issue where issue.status == \"done\" select issue.title")
+                .unwrap();
+
+        assert_eq!(
+            db.explain(&query).unwrap().to_string(),
+            "index scan of #issue:status where status == \"done\""
+        );
+        assert_eq!(
+            rows(
+                "query issue where issue.status == \"done\" select issue.title order_by issue.title"
+            ),
+            [text("a"), text("c")]
+        );
+
+        // Variants are ordered like they were declared
+        assert_eq!(
+            rows(
+                "query issue where issue.status < \"done\" select issue.title order_by issue.status"
+            ),
+            [text("b"), text("d")]
+        );
+        assert_eq!(
+            rows(
+                "query issue where \"open\" != issue.status select to_text(issue.status) order_by issue.status desc"
+            ),
+            [text("done"), text("done"), text("in progress")]
+        );
+
+        let query = query_parse::parse(
+            "query issue group_by issue.status select count() order_by issue.status",
+        )
+        .unwrap();
+
+        let Ok(QueryResult::Grouped { groups }) = db.run_query(&query) else {
+            panic!("expected groups");
+        };
+
+        let groups = groups
+            .into_iter()
+            .map(|group| {
+                let QueryResult::Records(result) = group.result else {
+                    panic!("expected records");
+                };
+
+                (group.group, result.aggregates[0].value.clone())
+            })
+            .collect::<Vec<_>>();
+
+        let group = |variant, count| {
+            (
+                Value::Field(status.variant(variant).unwrap()),
+                Value::Field(FieldValue::Int(count)),
+            )
+        };
+
+        assert_eq!(
+            groups,
+            [group("open", 1), group("in progress", 1), group("done", 2)]
+        );
+
+        // A misspelled variant is found before the query runs
+        let query = query_parse::parse("query issue where issue.status == \"dne\"").unwrap();
+
+        assert!(matches!(
+            db.check_query(&query),
+            Err(DbError::InvalidQuery {
+                err: EvalErr::UnknownVariant { .. },
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn query_list() {
+        let db = test_db();
+
+        let person = FieldTy::RecordId {
+            table_name: "person".into(),
+        };
+        let tags = FieldTy::List(Box::new(FieldTy::Text));
+
+        let bytes = BytePacker::pack_value(&tags);
+        assert_eq!(
+            FieldTy::unpack(0, &ByteUnpacker::new(&bytes)),
+            Some(tags.clone())
+        );
+
+        let mut task = table_def([
+            ("title", FieldTy::Text, false),
+            ("tags", tags, true),
+            ("assignees", FieldTy::List(Box::new(person.clone())), false),
+        ]);
+        task.fields[2].value.on_delete = IndexOnDelete::SetNone;
+
+        db.register_table(Named::new("task", task)).unwrap();
+
+        let people = ["ada", "bob"].map(|name| {
+            let record = create_record(
+                &db,
+                "person",
+                &[FieldValue::Text(name.into()), FieldValue::Bool(true)],
+            );
+            db.insert_record("person", &record).unwrap();
+
+            FieldValue::RecordId {
+                id: record.id(),
+                table_name: "person".into(),
+            }
+        });
+
+        let text = |value: &str| FieldValue::Text(value.into());
+        let list = |ty: &FieldTy, values: Vec<FieldValue>| FieldValue::List {
+            ty: Box::new(ty.clone()),
+            values,
+        };
+
+        for (title, task_tags, assignees) in [
+            ("a", vec!["bug", "ui"], vec![0, 1]),
+            ("b", vec!["bug", "bug"], vec![1]),
+            ("c", vec![], vec![]),
+        ] {
+            let record = create_record(
+                &db,
+                "task",
+                &[
+                    text(title),
+                    list(&FieldTy::Text, task_tags.into_iter().map(text).collect()),
+                    list(
+                        &person,
+                        assignees
+                            .into_iter()
+                            .map(|idx| people[idx].clone())
+                            .collect(),
+                    ),
+                ],
+            );
+            db.insert_record("task", &record).unwrap();
+        }
+
+        // Every distinct element has an entry, empty lists have none
+        assert_eq!(db.index_query("#task:tags", None, None).unwrap().len(), 3);
+
+        let missing = create_record(
+            &db,
+            "task",
+            &[
+                text("d"),
+                list(&FieldTy::Text, Vec::new()),
+                list(
+                    &person,
+                    vec![FieldValue::RecordId {
+                        id: Ulid::new(),
+                        table_name: "person".into(),
+                    }],
+                ),
+            ],
+        );
+        assert!(matches!(
+            db.insert_record("task", &missing),
+            Err(DbError::RecordDoesNotExist { .. })
+        ));
+
+        let rows = |query: &str| {
+            let query = query_parse::parse(query).unwrap();
+
+            let Ok(QueryResult::Rows(result)) = db.run_query(&query) else {
+                panic!("expected rows");
+            };
+
+            result
+                .rows
+                .into_iter()
+                .map(|row| row.values)
+                .collect::<Vec<_>>()
+        };
+        let row =
+            |values: &[FieldValue]| values.iter().cloned().map(Value::Field).collect::<Vec<_>>();
+
+        let query = query_parse::parse("query task where contains(task.tags, \"bug\")").unwrap();
+
+        assert_eq!(
+            db.explain(&query).unwrap().to_string(),
+            "index scan of #task:tags where tags == \"bug\""
+        );
+        assert_eq!(
+            rows(
+                "query task where contains(task.tags, \"bug\") select task.title order_by task.title"
+            ),
+            [row(&[text("a")]), row(&[text("b")])]
+        );
+        assert_eq!(
+            rows("query task where len(task.tags) == 0 select task.title"),
+            [row(&[text("c")])]
+        );
+        assert_eq!(
+            rows(
+                "query task where any(assignee in task.assignees, assignee.name == \"bob\") select task.title order_by task.title"
+            ),
+            [row(&[text("a")]), row(&[text("b")])]
+        );
+        assert_eq!(
+            rows(
+                "query person select person.name, count(person.referenced_by(task.assignees)) order_by person.name"
+            ),
+            [
+                row(&[text("ada"), FieldValue::Int(1)]),
+                row(&[text("bob"), FieldValue::Int(2)]),
+            ]
+        );
+
+        // Deleted references are removed from the lists
+        let FieldValue::RecordId { id: bob, .. } = &people[1] else {
+            unreachable!();
+        };
+        db.delete_record("person", *bob).unwrap();
+
+        assert_eq!(
+            rows("query task select len(task.assignees) order_by task.title"),
+            [
+                row(&[FieldValue::Int(1)]),
+                row(&[FieldValue::Int(0)]),
+                row(&[FieldValue::Int(0)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn query_json() {
+        let db = test_db();
+
+        let meta = Json::parse(
+            r#" {"customer": {"id": 7, "name": "Acme \"Inc\"\u00e9"}, "tags": ["a", null], "paid": true, "total": -12.5} "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            meta.to_string(),
+            r#"{"customer":{"id":7,"name":"Acme \"Inc\"é"},"tags":["a",null],"paid":true,"total":-12.5}"#
+        );
+        assert_eq!(Json::parse(&meta.to_string()), Some(meta.clone()));
+        assert_eq!(Json::parse("[1, 2"), None);
+        assert_eq!(Json::parse("NaN"), None);
+
+        let bytes = BytePacker::pack_value(&meta);
+        assert_eq!(
+            Json::unpack(0, &ByteUnpacker::new(&bytes)),
+            Some(meta.clone())
+        );
+
+        db.register_table(Named::new(
+            "deal",
+            table_def([
+                ("title", FieldTy::Text, false),
+                ("meta", FieldTy::Json, true),
+            ]),
+        ))
+        .unwrap();
+
+        let text = |value: &str| FieldValue::Text(value.into());
+
+        for (title, meta) in [
+            ("a", meta.to_string().as_str()),
+            ("b", r#"{"customer": {"id": 8, "name": "Bolt"}}"#),
+            ("c", r#"{"customer": null}"#),
+        ] {
+            let record = create_record(
+                &db,
+                "deal",
+                &[text(title), text(meta).cast(&FieldTy::Json).unwrap()],
+            );
+            db.insert_record("deal", &record).unwrap();
+        }
+
+        assert_eq!(db.index_query("#deal:meta", None, None).unwrap().len(), 3);
+
+        let rows = |query: &str| {
+            let query = query_parse::parse(query).unwrap();
+
+            let Ok(QueryResult::Rows(result)) = db.run_query(&query) else {
+                panic!("expected rows");
+            };
+
+            result
+                .rows
+                .into_iter()
+                .map(|row| row.values)
+                .collect::<Vec<_>>()
+        };
+        let row =
+            |values: &[FieldValue]| values.iter().cloned().map(Value::Field).collect::<Vec<_>>();
+
+        // Missing keys and JSON nulls are null
+        assert_eq!(
+            rows("query deal select json_i64(deal.meta.customer.id) order_by deal.title"),
+            [
+                row(&[FieldValue::I64(7)]),
+                row(&[FieldValue::I64(8)]),
+                row(&[FieldValue::Null(FieldTy::IntI64)]),
+            ]
+        );
+        assert_eq!(
+            rows(
+                "query deal where json_text(deal.meta.customer.name) == \"Bolt\" select deal.title"
+            ),
+            [row(&[text("b")])]
+        );
+        assert_eq!(
+            rows("query deal where deal.meta.paid is null select deal.title order_by deal.title"),
+            [row(&[text("b")]), row(&[text("c")])]
+        );
+        assert_eq!(
+            rows(
+                "query deal where json_bool(deal.meta.paid) select json_f64(deal.meta.total), to_text(deal.meta.tags)"
+            ),
+            [row(&[FieldValue::F64(Float(-12.5)), text("[\"a\",null]")])]
+        );
+        assert_eq!(
+            rows("query deal where deal.meta.customer.id == to_json(\"8\") select deal.title"),
+            [row(&[text("b")])]
+        );
+
+        // Values of another kind are not converted
+        let query =
+            query_parse::parse("query deal select json_text(deal.meta.customer.id)").unwrap();
+        assert!(matches!(
+            db.run_query(&query),
+            Err(DbError::Eval {
+                err: EvalErr::InvalidCast { .. }
+            })
+        ));
+    }
+
+    #[test]
+    fn query_blob() {
+        let db = test_db();
+
+        db.register_table(Named::new(
+            "attachment",
+            table_def([
+                ("name", FieldTy::Text, false),
+                ("file", FieldTy::Nullable(Box::new(FieldTy::Blob)), false),
+            ]),
+        ))
+        .unwrap();
+
+        let large = (0..200_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+
+        let read = |record_id: Ulid| {
+            let mut reader = db.read_blob("attachment", record_id, "file").unwrap()?;
+            let mut content = Vec::new();
+            reader.read_to_end(&mut content).unwrap();
+
+            assert_eq!(reader.len(), content.len() as u64);
+
+            Some((reader.mime().to_owned(), content))
+        };
+        let file = |record_id: Ulid| {
+            let table = db.table("attachment").unwrap();
+            let record = db.get("attachment", record_id).unwrap().unwrap();
+
+            match record.get_field(table.field("file").unwrap()) {
+                Some(FieldValue::Blob(blob)) => blob.content,
+                value => panic!("expected a blob, found {value:?}"),
+            }
+        };
+        let chunk_count = || {
+            let tx = db.inner.db.begin_read().unwrap();
+            let chunks = tx
+                .open_table(BlobTableDef::new("$blobs:attachment"))
+                .unwrap();
+
+            chunks.len().unwrap()
+        };
+
+        let record = create_record(
+            &db,
+            "attachment",
+            &[
+                FieldValue::Text("receipt".into()),
+                FieldValue::Null(FieldTy::Blob),
+            ],
+        );
+        db.insert_record("attachment", &record).unwrap();
+
+        assert_eq!(read(record.id()), None);
+
+        // Small contents stay in the record
+        db.write_blob(
+            "attachment",
+            record.id(),
+            "file",
+            "text/plain",
+            &b"paid"[..],
+        )
+        .unwrap();
+
+        assert_eq!(
+            read(record.id()),
+            Some(("text/plain".into(), b"paid".to_vec()))
+        );
+        assert_eq!(file(record.id()), BlobContent::Inline(b"paid".to_vec()));
+
+        db.write_blob(
+            "attachment",
+            record.id(),
+            "file",
+            "image/png",
+            large.as_slice(),
+        )
+        .unwrap();
+
+        assert_eq!(read(record.id()), Some(("image/png".into(), large.clone())));
+        assert!(matches!(
+            file(record.id()),
+            BlobContent::External { len: 200_000, .. }
+        ));
+        assert_eq!(chunk_count(), 4);
+
+        // Replaced contents are deleted
+        db.write_blob("attachment", record.id(), "file", "text/plain", &b""[..])
+            .unwrap();
+
+        assert_eq!(read(record.id()), Some(("text/plain".into(), Vec::new())));
+        assert_eq!(chunk_count(), 0);
+
+        // Large inline contents are moved out of the record
+        let screenshot = create_record(
+            &db,
+            "attachment",
+            &[
+                FieldValue::Text("screenshot".into()),
+                FieldValue::Blob(Blob {
+                    mime: "image/png".into(),
+                    content: BlobContent::Inline(large.clone()),
+                }),
+            ],
+        );
+        db.insert_record("attachment", &screenshot).unwrap();
+
+        assert!(matches!(
+            file(screenshot.id()),
+            BlobContent::External { len: 200_000, .. }
+        ));
+        assert_eq!(read(screenshot.id()), Some(("image/png".into(), large)));
+        assert_eq!(chunk_count(), 4);
+
+        // Updating other fields keeps the content
+        db.update_record_fields(
+            "attachment",
+            screenshot.id(),
+            [Named::new("name", FieldValue::Text("login".into()))],
+        )
+        .unwrap();
+
+        assert_eq!(chunk_count(), 4);
+
+        db.delete_record("attachment", screenshot.id()).unwrap();
+
+        assert_eq!(chunk_count(), 0);
+
+        let blob = Blob {
+            mime: "application/pdf".into(),
+            content: BlobContent::External {
+                id: Ulid::new(),
+                len: 12,
+            },
+        };
+        let bytes = BytePacker::pack_value(&blob);
+        assert_eq!(Blob::unpack(0, &ByteUnpacker::new(&bytes)), Some(blob));
+
+        assert!(matches!(
+            db.write_blob("attachment", record.id(), "name", "text/plain", &b""[..]),
+            Err(DbError::WrongType { .. })
+        ));
+    }
+
+    #[test]
+    fn query_check() {
+        let db = work_time_db();
+
+        let check = |query: &str| db.check_query(&query_parse::parse(query).unwrap());
+
+        check(
+            "query work_time join project on project == work_time.project where project.name == \"app\" group_by project.name select count(), sum(work_time.hours) order_by work_time.hours",
+        )
+        .unwrap();
+
+        assert!(matches!(
+            check("query work_time where work_time.hours"),
+            Err(DbError::InvalidQuery {
+                err: EvalErr::MissmatchedTypes { .. },
+                ..
+            })
+        ));
+        assert!(matches!(
+            check("query work_time where work_time.hours == \"3\""),
+            Err(DbError::InvalidQuery {
+                err: EvalErr::InvalidTypeForBinaryOp { .. },
+                ..
+            })
+        ));
+        assert!(matches!(
+            check("query work_time where sum(work_time.hours) > 3"),
+            Err(DbError::InvalidQuery {
+                err: EvalErr::AggregateOutsideSelect { .. },
+                ..
+            })
+        ));
+        assert!(matches!(
+            check("query work_time select work_time.minutes"),
+            Err(DbError::InvalidQuery {
+                err: EvalErr::UnknownField { .. },
+                ..
+            })
+        ));
+
+        // Tables are only accessible after they were joined
+        let Err(DbError::InvalidQuery {
+            err: EvalErr::UnknownTable {
+                did_you_mean_hint, ..
+            },
+            ..
+        }) = check("query work_time select projects.name")
+        else {
+            panic!("expected unknown table");
+        };
+        assert!(matches!(did_you_mean_hint, DidYouMeanHint::None));
+
+        let Err(DbError::InvalidQuery {
+            err: EvalErr::UnknownTable {
+                did_you_mean_hint, ..
+            },
+            ..
+        }) = check(
+            "query work_time join project on project == work_time.project select projects.name",
+        )
+        else {
+            panic!("expected unknown table");
+        };
+        assert!(matches!(
+            did_you_mean_hint,
+            DidYouMeanHint::Table { name } if name.as_ref() == "project"
+        ));
+
+        // Queries are checked before they run
+        let query = query_parse::parse("query work_time where work_time.hours + 1").unwrap();
+        assert!(matches!(
+            db.run_query(&query),
+            Err(DbError::InvalidQuery { .. })
+        ));
+    }
+
+    #[test]
+    fn corrupt_record() {
+        let db = test_db();
+
+        let record = RecordBytes::create(vec![0xff]);
+
+        assert!(matches!(
+            db.insert_record("person", &record),
+            Err(DbError::CorruptRecord { .. })
+        ));
+
+        {
+            let tx = db.inner.db.begin_write().unwrap();
+            {
+                let mut table = tx.open_table(TableWithIdDef::new("person")).unwrap();
+                table.insert(record.id().0, record.bytes()).unwrap();
+            }
+            tx.commit().unwrap();
+        }
+
+        let result = db.update_record_fields(
+            "person",
+            record.id(),
+            [Named::new("active", FieldValue::Bool(false))],
+        );
+
+        assert!(matches!(result, Err(DbError::CorruptRecord { .. })));
+    }
+}
@@ -0,0 +1,758 @@
+use std::{fmt::Display, ops::Bound, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use db_core::{
+    expr::{BinaryOp, CompareOp, EqOp, EvalCtx, Expr, LogicOp, UnaryOp},
+    float::Float,
+    query::{Join, OrderBy, Query, SortDirection},
+    ty::FieldTy,
+    value::{FieldValue, Value},
+};
+
+use crate::{
+    Db,
+    db::{check_ext::check_query, table_ext::DbTables},
+    error::DbError,
+};
+
+/// How `Db::run_query` finds the records of a query, see `Db::explain`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryPlan {
+    /// Reads every record of the table
+    FullScan {
+        table_name: Arc<str>,
+        joins: Vec<JoinPlan>,
+        filter: Option<Expr>,
+        order_by: Vec<OrderBy>,
+    },
+    /// Reads only the records within a range of an index
+    IndexScan {
+        index_name: Arc<str>,
+        field_names: Vec<Arc<str>>,
+        /// Values of the first fields of the index
+        prefix: Vec<FieldValue>,
+        /// Bounds of the field following the prefix
+        lower: Bound<FieldValue>,
+        upper: Bound<FieldValue>,
+        /// Reads the index from the largest to the smallest key
+        reverse: bool,
+        joins: Vec<JoinPlan>,
+        /// The part of the query filter that is not answered by the index
+        filter: Option<Expr>,
+        /// The sort keys of the query, empty if the index already returns the records in order
+        order_by: Vec<OrderBy>,
+    },
+}
+
+/// How the records of a joined table are found for every combination of the earlier records
+#[derive(Debug, Clone, PartialEq)]
+pub struct JoinPlan {
+    pub table_name: Arc<str>,
+    pub lookup: JoinLookup,
+    /// The part of the join condition that is not answered by the lookup
+    pub filter: Option<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JoinLookup {
+    /// Reads the record with the id `id` evaluates to
+    Id { id: Expr },
+    /// Reads the records whose value in the first field of the index is equal to `value`
+    Index {
+        index_name: Arc<str>,
+        field_name: Arc<str>,
+        value: Expr,
+    },
+    /// Reads every record of the table
+    Scan,
+}
+
+impl QueryPlan {
+    /// The joins that are applied to every record read by the plan
+    pub fn joins(&self) -> &[JoinPlan] {
+        match self {
+            QueryPlan::FullScan { joins, .. } | QueryPlan::IndexScan { joins, .. } => joins,
+        }
+    }
+
+    /// The predicate that is evaluated for every combination of records after the joins
+    pub fn filter(&self) -> Option<&Expr> {
+        match self {
+            QueryPlan::FullScan { filter, .. } | QueryPlan::IndexScan { filter, .. } => {
+                filter.as_ref()
+            }
+        }
+    }
+
+    /// The sort keys that are applied to the filtered records
+    pub fn order_by(&self) -> &[OrderBy] {
+        match self {
+            QueryPlan::FullScan { order_by, .. } | QueryPlan::IndexScan { order_by, .. } => {
+                order_by
+            }
+        }
+    }
+}
+
+impl Display for QueryPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryPlan::FullScan { table_name, .. } => write!(f, "full scan of {table_name}")?,
+            QueryPlan::IndexScan {
+                index_name,
+                field_names,
+                prefix,
+                lower,
+                upper,
+                reverse,
+                ..
+            } => {
+                write!(f, "index scan of {index_name}")?;
+
+                let mut conditions = field_names
+                    .iter()
+                    .zip(prefix)
+                    .map(|(field_name, value)| match value {
+                        FieldValue::Null(_) => format!("{field_name} is null"),
+                        value => format!("{field_name} == {value}"),
+                    })
+                    .collect::<Vec<_>>();
+
+                if let Some(field_name) = field_names.get(prefix.len()) {
+                    match lower {
+                        Bound::Included(value) => {
+                            conditions.push(format!("{field_name} >= {value}"))
+                        }
+                        Bound::Excluded(value) => {
+                            conditions.push(format!("{field_name} > {value}"))
+                        }
+                        Bound::Unbounded => (),
+                    }
+                    match upper {
+                        Bound::Included(value) => {
+                            conditions.push(format!("{field_name} <= {value}"))
+                        }
+                        Bound::Excluded(value) => {
+                            conditions.push(format!("{field_name} < {value}"))
+                        }
+                        Bound::Unbounded => (),
+                    }
+                }
+
+                if !conditions.is_empty() {
+                    write!(f, " where {}", conditions.join(" && "))?;
+                }
+
+                if *reverse {
+                    write!(f, " in reverse")?;
+                }
+            }
+        }
+
+        for join in self.joins() {
+            write!(f, ", then join {join}")?;
+        }
+
+        if let Some(filter) = self.filter() {
+            write!(f, ", then filter {filter}")?;
+        }
+
+        if !self.order_by().is_empty() {
+            let keys = self
+                .order_by()
+                .iter()
+                .map(|key| match key.direction {
+                    SortDirection::Asc => format!("{} asc", key.expr),
+                    SortDirection::Desc => format!("{} desc", key.expr),
+                })
+                .collect::<Vec<_>>();
+
+            write!(f, ", then sort by {}", keys.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for JoinPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.table_name)?;
+
+        match &self.lookup {
+            JoinLookup::Id { id } => write!(f, " by id {id}")?,
+            JoinLookup::Index {
+                index_name,
+                field_name,
+                value,
+            } => write!(
+                f,
+                " by index {index_name} where {}.{field_name} == {value}",
+                self.table_name
+            )?,
+            JoinLookup::Scan => write!(f, " by full scan")?,
+        }
+
+        if let Some(filter) = &self.filter {
+            write!(f, " on {filter}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Db {
+    /// Returns the plan `run_query` would use for `query`.
+    pub fn explain(&self, query: &Query) -> Result<QueryPlan, DbError> {
+        let tables = self.inner.tables.read().unwrap();
+
+        check_query(&tables, query)?;
+
+        Ok(plan_query(&tables, query, Utc::now()))
+    }
+}
+
+/// Every table of the query must exist and can only be used once,
+/// because its records are accessed by the table name.
+pub(super) fn check_query_tables(tables: &DbTables, query: &Query) -> Result<(), DbError> {
+    let table_names = std::iter::once(&query.table_name)
+        .chain(query.joins.iter().map(|join| &join.table_name))
+        .collect::<Vec<_>>();
+
+    for (idx, table_name) in table_names.iter().enumerate() {
+        if tables.table(table_name).is_none() {
+            return Err(DbError::TableDoesNotExist {
+                table: (*table_name).clone(),
+            });
+        }
+
+        if table_names[..idx].contains(table_name) {
+            return Err(DbError::DuplicateQueryTable {
+                table: (*table_name).clone(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// A comparison of a field of the queried table with a constant value
+struct Condition<'a> {
+    expr: &'a Expr,
+    field_name: &'a Arc<str>,
+    /// The operator with the field on the left side
+    op: BinaryOp,
+    value: FieldValue,
+}
+
+/// Chooses the index that answers the largest part of the query filter.
+///
+/// Indices are used for `==` comparisons on their first fields, followed by a range on the next field.
+/// Every part of the filter the chosen index does not answer exactly is kept as filter of the plan.
+/// Between equally good indices the one returning the records in the order of the query is preferred,
+/// which also makes an index useful for queries without filter.
+pub(super) fn plan_query(tables: &DbTables, query: &Query, now: DateTime<Utc>) -> QueryPlan {
+    let joins = query
+        .joins
+        .iter()
+        .map(|join| plan_join(tables, join))
+        .collect::<Vec<_>>();
+
+    let mut conjuncts = Vec::new();
+
+    if let Some(filter) = &query.filter {
+        collect_conjuncts(filter, &mut conjuncts);
+    }
+
+    // Constants are evaluated without any records, so expressions reading a record fail
+    let const_ctx = EvalCtx {
+        now,
+        ..Default::default()
+    };
+
+    let conditions = conjuncts
+        .iter()
+        .copied()
+        .filter_map(|expr| condition(expr, &query.table_name, tables, &const_ctx))
+        .collect::<Vec<_>>();
+
+    let mut best_score = (0, 0, false);
+    let mut best = None;
+
+    for index in tables.indices.values() {
+        if index.table_name != query.table_name {
+            continue;
+        }
+
+        let mut prefix = Vec::new();
+        let mut lower = Bound::Unbounded;
+        let mut upper = Bound::Unbounded;
+        let mut answered = Vec::new();
+
+        for field_name in &index.field_names {
+            let mut field_conditions = conditions
+                .iter()
+                .filter(|condition| condition.field_name == field_name);
+
+            if let Some(condition) = field_conditions
+                .clone()
+                .find(|condition| condition.op == BinaryOp::Eq(EqOp::Eq))
+            {
+                prefix.push(condition.value.clone());
+
+                if is_exact(condition) {
+                    answered.push(condition.expr);
+                }
+
+                continue;
+            }
+
+            for condition in field_conditions.by_ref() {
+                let BinaryOp::Compare(op) = condition.op else {
+                    continue;
+                };
+
+                let bound = if matches!(op, CompareOp::LessEq | CompareOp::GreaterEq) {
+                    Bound::Included(condition.value.clone())
+                } else {
+                    Bound::Excluded(condition.value.clone())
+                };
+
+                let target = match op {
+                    CompareOp::Greater | CompareOp::GreaterEq => &mut lower,
+                    CompareOp::Less | CompareOp::LessEq => &mut upper,
+                };
+
+                if target != &Bound::Unbounded {
+                    continue;
+                }
+
+                *target = bound;
+
+                if is_exact(condition) {
+                    answered.push(condition.expr);
+                }
+            }
+
+            break;
+        }
+
+        // Records are found once for every element of a list outside of the prefix, and never for blobs
+        if index.field_names[prefix.len()..]
+            .iter()
+            .any(|field_name| !has_one_key(tables, &query.table_name, field_name))
+        {
+            continue;
+        }
+
+        let range_count =
+            (lower != Bound::Unbounded) as usize + (upper != Bound::Unbounded) as usize;
+        let order = index_order(
+            &index.field_names,
+            prefix.len(),
+            &query.order_by,
+            &query.table_name,
+        );
+        let score = (
+            prefix.len(),
+            range_count,
+            !query.order_by.is_empty() && order.is_some(),
+        );
+
+        if score <= best_score {
+            continue;
+        }
+
+        best_score = score;
+        best = Some((index, prefix, lower, upper, order, answered));
+    }
+
+    let Some((index, prefix, lower, upper, order, answered)) = best else {
+        return QueryPlan::FullScan {
+            table_name: query.table_name.clone(),
+            joins,
+            filter: query.filter.clone(),
+            order_by: query.order_by.clone(),
+        };
+    };
+
+    let filter = conjuncts
+        .into_iter()
+        .filter(|expr| {
+            !answered
+                .iter()
+                .any(|answered| std::ptr::eq(*answered, *expr))
+        })
+        .cloned()
+        .reduce(|a, b| Expr::BinaryOp {
+            a: Box::new(a),
+            op: BinaryOp::Logic(LogicOp::And),
+            b: Box::new(b),
+        });
+
+    QueryPlan::IndexScan {
+        index_name: index.index_name.clone(),
+        field_names: index.field_names.clone(),
+        prefix,
+        lower,
+        upper,
+        reverse: order == Some(SortDirection::Desc),
+        joins,
+        filter,
+        order_by: match order {
+            Some(_) => Vec::new(),
+            None => query.order_by.clone(),
+        },
+    }
+}
+
+/// Looks up the joined records by id, if the condition compares the joined table with an id,
+/// or by an index whose first field is compared with a value of the earlier tables.
+fn plan_join(tables: &DbTables, join: &Join) -> JoinPlan {
+    let mut conjuncts = Vec::new();
+    collect_conjuncts(&join.on, &mut conjuncts);
+
+    // Sides of `==` comparisons, only the first side reads the joined table
+    let equalities = conjuncts
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, expr)| {
+            let Expr::BinaryOp {
+                a,
+                op: BinaryOp::Eq(EqOp::Eq),
+                b,
+            } = expr
+            else {
+                return None;
+            };
+
+            match (
+                reads_table(a, &join.table_name),
+                reads_table(b, &join.table_name),
+            ) {
+                (true, false) => Some((idx, a.as_ref(), b.as_ref())),
+                (false, true) => Some((idx, b.as_ref(), a.as_ref())),
+                _ => None,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let id_lookup = equalities.iter().find_map(|(idx, side, other)| match side {
+        Expr::TableAccess { name } if name == &join.table_name => Some((
+            *idx,
+            JoinLookup::Id {
+                id: (*other).clone(),
+            },
+        )),
+        _ => None,
+    });
+
+    let index_lookup = || {
+        equalities.iter().find_map(|(idx, side, other)| {
+            let field_name = field_of(side, &join.table_name)?;
+            let index_name = first_field_index(tables, &join.table_name, field_name)?;
+
+            // Joined values are only known while running, they may be `0.0` or `NaN`, see `float_key`
+            if tables
+                .table_field(&join.table_name, field_name)?
+                .ty
+                .non_null()
+                == &FieldTy::F64
+            {
+                return None;
+            }
+
+            Some((
+                *idx,
+                JoinLookup::Index {
+                    index_name: index_name.clone(),
+                    field_name: field_name.clone(),
+                    value: (*other).clone(),
+                },
+            ))
+        })
+    };
+
+    let Some((answered, lookup)) = id_lookup.or_else(index_lookup) else {
+        return JoinPlan {
+            table_name: join.table_name.clone(),
+            lookup: JoinLookup::Scan,
+            filter: Some(join.on.clone()),
+        };
+    };
+
+    let filter = conjuncts
+        .into_iter()
+        .enumerate()
+        .filter(|(idx, _)| *idx != answered)
+        .map(|(_, expr)| expr.clone())
+        .reduce(|a, b| Expr::BinaryOp {
+            a: Box::new(a),
+            op: BinaryOp::Logic(LogicOp::And),
+            b: Box::new(b),
+        });
+
+    JoinPlan {
+        table_name: join.table_name.clone(),
+        lookup,
+        filter,
+    }
+}
+
+/// An index starting with `field_name`
+fn first_field_index<'a>(
+    tables: &'a DbTables,
+    table_name: &Arc<str>,
+    field_name: &Arc<str>,
+) -> Option<&'a Arc<str>> {
+    tables
+        .indices
+        .values()
+        .filter(|index| &index.table_name == table_name)
+        .find(|index| index.field_names.first() == Some(field_name))
+        .filter(|_| has_one_key(tables, table_name, field_name))
+        .map(|index| &index.index_name)
+}
+
+/// Whether every record has exactly one entry in an index over the field `field_name`,
+/// lists have an entry for every element and blobs none
+fn has_one_key(tables: &DbTables, table_name: &Arc<str>, field_name: &Arc<str>) -> bool {
+    tables
+        .table_field(table_name, field_name)
+        .is_some_and(|field| field.ty.element().is_none() && field.ty.non_null() != &FieldTy::Blob)
+}
+
+/// Whether `expr` accesses the table `table_name`
+fn reads_table(expr: &Expr, table_name: &Arc<str>) -> bool {
+    match expr {
+        Expr::Literal(_) => false,
+        Expr::TableAccess { name } => name == table_name,
+        Expr::BinaryOp { a, b, .. } => reads_table(a, table_name) || reads_table(b, table_name),
+        Expr::UnaryOp { value, .. }
+        | Expr::FieldAccess { value, .. }
+        | Expr::ReferencedBy { value, .. } => reads_table(value, table_name),
+        Expr::FnCall { args, .. } | Expr::Coalesce { args } => {
+            args.iter().any(|arg| reads_table(arg, table_name))
+        }
+        Expr::Aggregate { arg, .. } => arg.as_ref().is_some_and(|arg| reads_table(arg, table_name)),
+        // The variable shadows a table of the same name
+        Expr::Any {
+            var,
+            list,
+            predicate,
+        } => {
+            reads_table(list, table_name) || var != table_name && reads_table(predicate, table_name)
+        }
+    }
+}
+
+/// The direction to read the index in, so the records are sorted by `order_by`,
+/// or `None` if the index order does not match.
+///
+/// Fields in the first `prefix_len` index fields have the same value in every record and are ignored.
+fn index_order(
+    index_fields: &[Arc<str>],
+    prefix_len: usize,
+    order_by: &[OrderBy],
+    table_name: &Arc<str>,
+) -> Option<SortDirection> {
+    let (prefix, rest) = index_fields.split_at(prefix_len);
+    let mut rest = rest.iter();
+
+    let mut direction = None;
+
+    for key in order_by {
+        let field_name = field_of(&key.expr, table_name)?;
+
+        if prefix.contains(field_name) {
+            continue;
+        }
+
+        if rest.next() != Some(field_name) {
+            return None;
+        }
+
+        if *direction.get_or_insert(key.direction) != key.direction {
+            return None;
+        }
+    }
+
+    Some(direction.unwrap_or_default())
+}
+
+fn collect_conjuncts<'a>(expr: &'a Expr, conjuncts: &mut Vec<&'a Expr>) {
+    match expr {
+        Expr::BinaryOp {
+            a,
+            op: BinaryOp::Logic(LogicOp::And),
+            b,
+        } => {
+            collect_conjuncts(a, conjuncts);
+            collect_conjuncts(b, conjuncts);
+        }
+        expr => conjuncts.push(expr),
+    }
+}
+
+fn condition<'a>(
+    expr: &'a Expr,
+    table_name: &Arc<str>,
+    tables: &DbTables,
+    const_ctx: &EvalCtx,
+) -> Option<Condition<'a>> {
+    // Null values are indexed, so `field is null` is an `==` comparison with null
+    if let Expr::UnaryOp {
+        op: UnaryOp::IsNull,
+        value,
+    } = expr
+    {
+        let field_name = field_of(value, table_name)?;
+        let field = tables.table_field(table_name, field_name)?;
+
+        // Null lists have no index entry
+        if field.ty.element().is_some() {
+            return None;
+        }
+
+        return Some(Condition {
+            expr,
+            field_name,
+            op: BinaryOp::Eq(EqOp::Eq),
+            value: FieldValue::Null(field.ty.non_null().clone()),
+        });
+    }
+
+    // Lists are indexed by their elements, so `contains(list, value)` is an `==` comparison with an element
+    if let Expr::FnCall { name, args } = expr
+        && name.as_ref() == "contains"
+        && let [list, value] = args.as_slice()
+    {
+        let field_name = field_of(list, table_name)?;
+        let element_ty = tables
+            .table_field(table_name, field_name)?
+            .ty
+            .element()?
+            .non_null();
+
+        let Ok(Value::Field(value)) = value.eval(const_ctx) else {
+            return None;
+        };
+
+        // No element contains null
+        if value.is_null() {
+            return None;
+        }
+
+        return Some(Condition {
+            expr,
+            field_name,
+            op: BinaryOp::Eq(EqOp::Eq),
+            value: float_key(BinaryOp::Eq(EqOp::Eq), key_value(element_ty, value)?)?,
+        });
+    }
+
+    let Expr::BinaryOp { a, op, b } = expr else {
+        return None;
+    };
+
+    let (field_name, op, value) = match (field_of(a, table_name), field_of(b, table_name)) {
+        (Some(field_name), None) => (field_name, *op, b),
+        (None, Some(field_name)) => (field_name, flip(*op)?, a),
+        _ => return None,
+    };
+
+    if !matches!(op, BinaryOp::Eq(EqOp::Eq) | BinaryOp::Compare(_)) {
+        return None;
+    }
+
+    let Ok(Value::Field(value)) = value.eval(const_ctx) else {
+        return None;
+    };
+
+    // Comparisons that fail to evaluate can not be answered by an index
+    let field = tables.table_field(table_name, field_name)?;
+    let field_ty = field.ty.non_null();
+    op.ty(field_ty, &value.ty())?;
+
+    if field.ty.element().is_some() {
+        return None;
+    }
+
+    Some(Condition {
+        expr,
+        field_name,
+        op,
+        value: float_key(op, key_value(field_ty, value)?)?,
+    })
+}
+
+/// The key `value` is looked up with when compared by `op`.
+///
+/// `-0.0` is equal to `0.0` but has its own key, so ranges around zero include both keys
+/// and `== 0.0` can not be looked up. `NaN` is equal to nothing and ordered after every other key.
+fn float_key(op: BinaryOp, value: FieldValue) -> Option<FieldValue> {
+    let FieldValue::F64(Float(float)) = value else {
+        return Some(value);
+    };
+
+    if float.is_nan() {
+        return None;
+    }
+
+    match op {
+        _ if float != 0.0 => Some(value),
+        BinaryOp::Compare(CompareOp::Greater | CompareOp::GreaterEq) => {
+            Some(FieldValue::F64(Float(-0.0)))
+        }
+        BinaryOp::Compare(CompareOp::Less | CompareOp::LessEq) => Some(FieldValue::F64(Float(0.0))),
+        _ => None,
+    }
+}
+
+/// `value` converted to the type of the index keys `ty`, or `None` if it is compared as another type
+fn key_value(ty: &FieldTy, value: FieldValue) -> Option<FieldValue> {
+    BinaryOp::Eq(EqOp::Eq).ty(ty, &value.ty())?;
+
+    // Numbers of different types are compared as the wider type, which must be the type of the field
+    match ty.common_numeric(&value.ty()) {
+        Some(common) if &common == ty => value.cast(ty),
+        Some(_) => None,
+        // Text is compared as the variant of the same name
+        None if matches!(ty, FieldTy::Enum { .. }) => value.cast(ty),
+        None => Some(value),
+    }
+}
+
+fn field_of<'a>(expr: &'a Expr, table_name: &Arc<str>) -> Option<&'a Arc<str>> {
+    match expr {
+        Expr::FieldAccess { value, field } => match value.as_ref() {
+            Expr::TableAccess { name } if name == table_name => Some(field),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The operator for swapped sides, `a < b` is `b > a`
+fn flip(op: BinaryOp) -> Option<BinaryOp> {
+    let op = match op {
+        BinaryOp::Eq(op) => BinaryOp::Eq(op),
+        BinaryOp::Compare(CompareOp::Less) => BinaryOp::Compare(CompareOp::Greater),
+        BinaryOp::Compare(CompareOp::LessEq) => BinaryOp::Compare(CompareOp::GreaterEq),
+        BinaryOp::Compare(CompareOp::Greater) => BinaryOp::Compare(CompareOp::Less),
+        BinaryOp::Compare(CompareOp::GreaterEq) => BinaryOp::Compare(CompareOp::LessEq),
+        _ => return None,
+    };
+
+    Some(op)
+}
+
+/// Whether the index returns exactly the records matching `condition`, so it is not checked again.
+///
+/// Stored `NaN`s are within every unbounded range of a float index and `-0.0` is within ranges around zero,
+/// but neither compares like its key, see `float_key`.
+fn is_exact(condition: &Condition) -> bool {
+    match condition.value {
+        FieldValue::F64(_) => condition.op == BinaryOp::Eq(EqOp::Eq),
+        _ => true,
+    }
+}
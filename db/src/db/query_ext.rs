@@ -1,123 +1,687 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    ops::Bound,
+    sync::Arc,
+};
 
-use crate::{Db, db::TableWithIdDef, error::DbError};
+use crate::{
+    Db,
+    db::{
+        TableWithIdDef,
+        check_ext::check_query,
+        plan_ext::{JoinLookup, JoinPlan, QueryPlan, plan_query},
+        table_ext::DbTables,
+    },
+    error::DbError,
+};
 
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use db_core::{
-    expr::EvalCtx,
-    query::{Query, QueryResult, QueryResultGroup, QueryResultRecords},
+    defs::{
+        index::IndexDef,
+        table::{TableData, TableFieldData},
+    },
+    expr::{EvalCtx, EvalErr, Expr, RecordSource, TyCtx},
+    named::Named,
+    query::{
+        OnError, OrderBy, Query, QueryResult, QueryResultGroup, QueryResultRecords, QueryResultRow,
+        QueryResultRows, SortDirection,
+    },
     record::RecordBytes,
+    ty::{FieldTy, Ty},
     value::{FieldValue, Value},
 };
-use redb::{ReadableDatabase, ReadableTable};
+use redb::{ReadTransaction, ReadableDatabase, ReadableTable};
 use ulid::Ulid;
 
+/// One record of the queried table, followed by one record of every joined table
+type Row = Vec<Arc<RecordBytes>>;
+
 impl Db {
     pub fn run_query(&self, query: &Query) -> Result<QueryResult, DbError> {
         let now = Utc::now();
 
         let tables = self.inner.tables.read().unwrap();
 
-        let Some(table) = tables.tables.get(&query.table_name) else {
-            return Err(DbError::TableDoesNotExist {
-                table: query.table_name.clone(),
-            });
+        // Ill-typed expressions would fail for every record
+        let ty_ctx = check_query(&tables, query)?;
+
+        let table_data = Arc::new(tables.tables[&query.table_name].clone());
+
+        let source = Arc::new(TxRecordSource {
+            db: self.clone(),
+            tx: self.inner.db.begin_read()?,
+            references: tables
+                .indices
+                .values()
+                .filter(|index| index.field_names.len() == 1)
+                .filter_map(|index| tables.index_fields(&index.index_name).ok())
+                .filter(|(_, fields)| {
+                    matches!(fields[0].ty.indexed().non_null(), FieldTy::RecordId { .. })
+                })
+                .map(|(index, fields)| (index.index_name.clone(), (index, fields)))
+                .collect(),
+        });
+        let tx = &source.tx;
+
+        let mut result_rows = Vec::new();
+
+        let mut eval = RecordEval {
+            table_names: std::iter::once(query.table_name.clone())
+                .chain(query.joins.iter().map(|join| join.table_name.clone()))
+                .collect(),
+            // Every table, so the records referenced by `RecordId` fields can be read
+            tables: ty_ctx.tables,
+            now,
+            source: source.clone(),
+            on_error: query.on_error,
         };
-        let table_data = Arc::new(table.clone());
 
-        let tx = self.inner.db.begin_read()?;
+        let plan = plan_query(&tables, query, now);
 
-        let mut result_records = Vec::new();
+        let offset = query.offset.unwrap_or(0);
 
-        let table_name = query.table_name.clone();
-        let mut tables_map = HashMap::from_iter([(table_name.clone(), table_data.clone())]);
+        // Without sorting the scan can stop as soon as enough rows passed the filter
+        let stop_after = query
+            .limit
+            .filter(|_| plan.order_by().is_empty())
+            .map(|limit| offset.saturating_add(limit));
 
         {
             let table = tx.open_table(TableWithIdDef::new(&query.table_name))?;
 
-            for entry in table.iter()? {
-                let (key, value) = entry?;
+            let records: Box<dyn Iterator<Item = Result<RecordBytes, DbError>>> = match &plan {
+                QueryPlan::FullScan { .. } => Box::new(table.iter()?.map(|entry| {
+                    let (key, value) = entry?;
 
-                let id = key.value();
-                let bytes = value.value();
+                    Ok(RecordBytes::new(
+                        Ulid::from(key.value()),
+                        value.value().to_owned(),
+                    ))
+                })),
+                QueryPlan::IndexScan {
+                    index_name,
+                    prefix,
+                    lower,
+                    upper,
+                    reverse,
+                    ..
+                } => {
+                    let (index, fields) = tables.index_fields(index_name)?;
 
-                let record = RecordBytes::new(Ulid::from(id), bytes.to_owned());
-                let record = Arc::new(record);
+                    let mut entries = self.index_range(
+                        tx,
+                        &index,
+                        &fields,
+                        prefix,
+                        lower.clone(),
+                        upper.clone(),
+                    )?;
 
-                let passes_filter = if let Some(filter) = &query.filter {
-                    let eval_ctx = EvalCtx {
-                        records: HashMap::from_iter([(table_name.clone(), record.clone())]),
-                        tables: tables_map,
-                        now,
-                    };
+                    if *reverse {
+                        entries.reverse();
+                    }
 
-                    let result = filter.eval(&eval_ctx);
+                    Box::new(entries.into_iter().map(|(_, id)| {
+                        let Some(value) = table.get(id.0)? else {
+                            return Err(DbError::CorruptRecord {
+                                table: query.table_name.clone(),
+                                record: id,
+                            });
+                        };
 
-                    drop(eval_ctx.records);
-                    tables_map = eval_ctx.tables;
+                        Ok(RecordBytes::new(id, value.value().to_owned()))
+                    }))
+                }
+            };
 
-                    dbg!(&result);
+            let mut joiner = Joiner {
+                db: self,
+                source: &source,
+                tables: &tables,
+                scanned: HashMap::new(),
+            };
 
-                    if let Ok(result) = result
-                        && result == Value::Field(FieldValue::Bool(true))
-                    {
-                        true
-                    } else {
-                        false
-                    }
-                } else {
-                    true
-                };
+            for record in records {
+                if stop_after.is_some_and(|stop_after| result_rows.len() >= stop_after) {
+                    break;
+                }
+
+                let rows = joiner.join(&mut eval, plan.joins(), vec![Arc::new(record?)])?;
 
-                if passes_filter {
-                    result_records.push(Arc::into_inner(record).unwrap());
+                for row in rows {
+                    let passes_filter = match plan.filter() {
+                        Some(filter) => eval.is_true(filter, &row)?,
+                        None => true,
+                    };
+
+                    if passes_filter {
+                        result_rows.push(row);
+                    }
                 }
             }
         }
 
+        if !plan.order_by().is_empty() {
+            let mut sorted = result_rows
+                .into_iter()
+                .map(|row| {
+                    // Keys skipped by `OnError::Skip` are sorted like null values
+                    let keys = plan
+                        .order_by()
+                        .iter()
+                        .map(|key| match eval.eval_row(&key.expr, &row)? {
+                            Some(Value::Field(value)) => Ok(Some(value)),
+                            _ => Ok(None),
+                        })
+                        .collect::<Result<Vec<_>, DbError>>()?;
+
+                    Ok((keys, row))
+                })
+                .collect::<Result<Vec<_>, DbError>>()?;
+
+            sorted.sort_by(|(a, _), (b, _)| compare_keys(plan.order_by(), a, b));
+
+            result_rows = sorted.into_iter().map(|(_, row)| row).collect();
+        }
+
+        let result_rows = result_rows
+            .into_iter()
+            .skip(offset)
+            .take(query.limit.unwrap_or(usize::MAX))
+            .collect::<Vec<_>>();
+
         match &query.group_by {
-            None => {
-                return Ok(QueryResult::Records(QueryResultRecords {
-                    records: result_records,
-                    format: table_data,
-                }));
-            }
+            None => eval.query_result(result_rows, &query.select, table_data),
             Some(group_by) => {
-                let mut groups = HashMap::<Value, Vec<RecordBytes>>::new();
+                // Groups are ordered by their first row
+                let mut groups = Vec::<(Value, Vec<Row>)>::new();
+                let mut group_indices = HashMap::<Value, usize>::new();
 
-                for record in result_records {
-                    let record = Arc::new(record);
-
-                    let eval_ctx = EvalCtx {
-                        records: HashMap::from_iter([(table_name.clone(), record.clone())]),
-                        tables: tables_map,
-                        now,
+                for row in result_rows {
+                    let Some(group) = eval.eval_row(group_by, &row)? else {
+                        continue;
                     };
 
-                    let group_value = group_by.eval(&eval_ctx);
+                    let idx = *group_indices.entry(group.clone()).or_insert_with(|| {
+                        groups.push((group, Vec::new()));
+                        groups.len() - 1
+                    });
+
+                    groups[idx].1.push(row);
+                }
+
+                let groups = groups
+                    .into_iter()
+                    .map(|(group, rows)| {
+                        let result = eval.query_result(rows, &query.select, table_data.clone())?;
+
+                        Ok(QueryResultGroup { group, result })
+                    })
+                    .collect::<Result<Vec<_>, DbError>>()?;
 
-                    drop(eval_ctx.records);
-                    tables_map = eval_ctx.tables;
+                Ok(QueryResult::Grouped { groups })
+            }
+        }
+    }
+}
+
+/// Reads the records referenced during the evaluation of a query from the transaction of the query
+struct TxRecordSource {
+    db: Db,
+    tx: ReadTransaction,
+    /// The indices of `RecordId` fields by index name
+    references: HashMap<Arc<str>, (IndexDef, Vec<TableFieldData>)>,
+}
 
-                    if let Ok(group) = group_value {
-                        let record = Arc::into_inner(record).unwrap();
+impl TxRecordSource {
+    fn get(&self, table_name: &str, id: Ulid) -> Result<Option<RecordBytes>, DbError> {
+        let table = self.tx.open_table(TableWithIdDef::new(table_name))?;
 
-                        let entries = groups.entry(group).or_default();
+        let record = table
+            .get(id.0)?
+            .map(|value| RecordBytes::new(id, value.value().to_owned()));
 
-                        entries.push(record);
+        Ok(record)
+    }
+}
+
+impl std::fmt::Debug for TxRecordSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TxRecordSource").finish_non_exhaustive()
+    }
+}
+
+impl RecordSource for TxRecordSource {
+    fn record(&self, table_name: &str, id: Ulid) -> Option<Arc<RecordBytes>> {
+        self.get(table_name, id).ok().flatten().map(Arc::new)
+    }
+
+    fn referencing(
+        &self,
+        table_name: &str,
+        field_name: &str,
+        referenced_table: &Arc<str>,
+        id: Ulid,
+    ) -> Option<Vec<Arc<RecordBytes>>> {
+        let index_name = IndexDef::name_for(table_name, &[field_name.into()]);
+        let (index, fields) = self.references.get(&index_name)?;
+
+        let value = FieldValue::RecordId {
+            id,
+            table_name: referenced_table.clone(),
+        };
+
+        let entries = self
+            .db
+            .index_range(
+                &self.tx,
+                index,
+                fields,
+                &[value],
+                Bound::Unbounded,
+                Bound::Unbounded,
+            )
+            .ok()?;
+
+        entries
+            .into_iter()
+            .map(|(_, id)| self.get(table_name, id).ok().flatten().map(Arc::new))
+            .collect()
+    }
+}
+
+/// Combines rows with the records of the joined tables
+struct Joiner<'a> {
+    db: &'a Db,
+    source: &'a TxRecordSource,
+    tables: &'a DbTables,
+    /// Records of the joined tables that are read by a full scan, read once per query
+    scanned: HashMap<Arc<str>, Vec<Arc<RecordBytes>>>,
+}
+
+impl Joiner<'_> {
+    /// Returns every combination of `row` with records of the `joins` that match their condition
+    fn join(
+        &mut self,
+        eval: &mut RecordEval,
+        joins: &[JoinPlan],
+        row: Row,
+    ) -> Result<Vec<Row>, DbError> {
+        let mut rows = vec![row];
+
+        for join in joins {
+            let mut joined_rows = Vec::new();
+
+            for row in rows {
+                for record in self.lookup(eval, join, &row)? {
+                    let mut joined_row = row.clone();
+                    joined_row.push(record);
+
+                    let passes_filter = match &join.filter {
+                        Some(filter) => eval.is_true(filter, &joined_row)?,
+                        None => true,
+                    };
+
+                    if passes_filter {
+                        joined_rows.push(joined_row);
                     }
                 }
+            }
+
+            rows = joined_rows;
+        }
+
+        Ok(rows)
+    }
+
+    /// The records of the joined table that can match `row`
+    fn lookup(
+        &mut self,
+        eval: &mut RecordEval,
+        join: &JoinPlan,
+        row: &Row,
+    ) -> Result<Vec<Arc<RecordBytes>>, DbError> {
+        match &join.lookup {
+            JoinLookup::Id { id } => {
+                let Some(Some(FieldValue::RecordId { id, table_name })) =
+                    eval.eval_row(id, row)?.map(Value::into_field_or_id)
+                else {
+                    return Ok(Vec::new());
+                };
+
+                if table_name != join.table_name {
+                    return Ok(Vec::new());
+                }
+
+                let record = self.source.get(&table_name, id)?;
+
+                Ok(record.into_iter().map(Arc::new).collect())
+            }
+            JoinLookup::Index {
+                index_name, value, ..
+            } => {
+                // Records are compared by their id
+                let Some(Some(value)) = eval.eval_row(value, row)?.map(Value::into_field_or_id)
+                else {
+                    return Ok(Vec::new());
+                };
 
-                return Ok(QueryResult::Grouped {
-                    groups: groups
-                        .into_iter()
-                        .map(|(group_value, records)| QueryResultGroup {
-                            group: group_value,
-                            result: QueryResult::Records(QueryResultRecords { records, format: table_data.clone() }),
+                let (index, fields) = self.tables.index_fields(index_name)?;
+
+                let Some(value) = as_numeric_ty(value, fields[0].ty.non_null()) else {
+                    return Ok(Vec::new());
+                };
+
+                let entries = match self.db.index_range(
+                    &self.source.tx,
+                    &index,
+                    &fields,
+                    &[value],
+                    Bound::Unbounded,
+                    Bound::Unbounded,
+                ) {
+                    Ok(entries) => entries,
+                    // Values of another type are never equal to the field
+                    Err(DbError::WrongType { .. }) => return Ok(Vec::new()),
+                    Err(err) => return Err(err),
+                };
+
+                entries
+                    .into_iter()
+                    .map(|(_, id)| match self.source.get(&join.table_name, id)? {
+                        Some(record) => Ok(Arc::new(record)),
+                        None => Err(DbError::CorruptRecord {
+                            table: join.table_name.clone(),
+                            record: id,
+                        }),
+                    })
+                    .collect()
+            }
+            JoinLookup::Scan => {
+                if let Some(records) = self.scanned.get(&join.table_name) {
+                    return Ok(records.clone());
+                }
+
+                let table = self
+                    .source
+                    .tx
+                    .open_table(TableWithIdDef::new(&join.table_name))?;
+
+                let records = table
+                    .iter()?
+                    .map(|entry| {
+                        let (key, value) = entry?;
+
+                        Ok(Arc::new(RecordBytes::new(
+                            Ulid::from(key.value()),
+                            value.value().to_owned(),
+                        )))
+                    })
+                    .collect::<Result<Vec<_>, DbError>>()?;
+
+                self.scanned
+                    .insert(join.table_name.clone(), records.clone());
+
+                Ok(records)
+            }
+        }
+    }
+}
+
+/// Evaluates expressions for rows of the queried and joined tables
+struct RecordEval {
+    /// The table of every record in a row
+    table_names: Vec<Arc<str>>,
+    /// Moved into the `EvalCtx` of every evaluation, so it is not cloned per row
+    tables: HashMap<Arc<str>, Arc<TableData>>,
+    now: DateTime<Utc>,
+    source: Arc<TxRecordSource>,
+    on_error: OnError,
+}
+
+impl RecordEval {
+    /// `row` can hold fewer records than there are tables, the remaining tables can not be accessed
+    fn eval(&mut self, expr: &Expr, row: &[Arc<RecordBytes>]) -> Result<Value, EvalErr> {
+        let eval_ctx = EvalCtx {
+            records: self
+                .table_names
+                .iter()
+                .cloned()
+                .zip(row.iter().cloned())
+                .collect(),
+            tables: std::mem::take(&mut self.tables),
+            now: self.now,
+            source: Some(self.source.clone()),
+        };
+
+        let result = expr.eval(&eval_ctx);
+
+        self.tables = eval_ctx.tables;
+
+        result
+    }
+
+    /// Evaluates `expr` for a row that is skipped on errors, see `OnError`
+    fn eval_row(
+        &mut self,
+        expr: &Expr,
+        row: &[Arc<RecordBytes>],
+    ) -> Result<Option<Value>, DbError> {
+        match self.eval(expr, row) {
+            Ok(value) => Ok(Some(value)),
+            Err(_) if self.on_error == OnError::Skip => Ok(None),
+            Err(err) => Err(DbError::Eval { err }),
+        }
+    }
+
+    /// Whether `expr` evaluates to `true`, skipped rows do not match
+    fn is_true(&mut self, expr: &Expr, row: &[Arc<RecordBytes>]) -> Result<bool, DbError> {
+        let result = self.eval_row(expr, row)?;
+
+        Ok(result == Some(Value::Field(FieldValue::Bool(true))))
+    }
+
+    fn ty_ctx(&self) -> TyCtx {
+        TyCtx {
+            tables: self.tables.clone(),
+            scope: self.table_names.iter().cloned().collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the records with the aggregates of `select`,
+    /// or a row for every row of the query if `select` has no aggregate functions.
+    fn query_result(
+        &mut self,
+        rows: Vec<Row>,
+        select: &[Named<Expr>],
+        format: Arc<TableData>,
+    ) -> Result<QueryResult, DbError> {
+        if select.is_empty() || select.iter().any(|expr| expr.value.is_aggregate()) {
+            let aggregates = select
+                .iter()
+                .map(|expr| {
+                    let value = self.eval_select(&expr.value, &rows)?;
+
+                    Ok(Named::new(expr.name.clone(), value))
+                })
+                .collect::<Result<Vec<_>, EvalErr>>()
+                .map_err(|err| DbError::Eval { err })?;
+
+            let mut ids = HashSet::new();
+
+            let records = rows
+                .into_iter()
+                .map(|mut row| row.swap_remove(0))
+                .filter(|record| ids.insert(record.id()))
+                .map(Arc::unwrap_or_clone)
+                .collect();
+
+            return Ok(QueryResult::Records(QueryResultRecords {
+                records,
+                format,
+                aggregates,
+            }));
+        }
+
+        let result_rows = rows
+            .iter()
+            .map(|row| {
+                let values = select
+                    .iter()
+                    .map(|expr| self.eval(&expr.value, row))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                Ok(QueryResultRow {
+                    id: row[0].id(),
+                    values,
+                })
+            })
+            .collect::<Result<Vec<_>, EvalErr>>()
+            .map_err(|err| DbError::Eval { err })?;
+
+        let ty_ctx = self.ty_ctx();
+
+        let columns = select
+            .iter()
+            .map(|expr| {
+                let ty = expr.value.ty(&ty_ctx)?;
+
+                Ok(Named::new(expr.name.clone(), ty))
+            })
+            .collect::<Result<Vec<_>, EvalErr>>()
+            .map_err(|err| DbError::Eval { err })?;
+
+        Ok(QueryResult::Rows(QueryResultRows {
+            columns,
+            rows: result_rows,
+        }))
+    }
+
+    /// Computes the aggregate functions in `expr` over `rows`,
+    /// the remaining expression is evaluated for the first row.
+    fn eval_select(&mut self, expr: &Expr, rows: &[Row]) -> Result<Value, EvalErr> {
+        let expr = self.resolve_aggregates(expr, rows)?;
+
+        self.eval(&expr, rows.first().map_or(&[], Vec::as_slice))
+    }
+
+    /// Replaces every aggregate function in `expr` by its value over `rows`.
+    fn resolve_aggregates(&mut self, expr: &Expr, rows: &[Row]) -> Result<Expr, EvalErr> {
+        let expr = match expr {
+            Expr::Literal(_) | Expr::TableAccess { .. } => expr.clone(),
+            Expr::BinaryOp { a, op, b } => Expr::BinaryOp {
+                a: Box::new(self.resolve_aggregates(a, rows)?),
+                op: *op,
+                b: Box::new(self.resolve_aggregates(b, rows)?),
+            },
+            Expr::UnaryOp { op, value } => Expr::UnaryOp {
+                op: *op,
+                value: Box::new(self.resolve_aggregates(value, rows)?),
+            },
+            Expr::FieldAccess { value, field } => Expr::FieldAccess {
+                value: Box::new(self.resolve_aggregates(value, rows)?),
+                field: field.clone(),
+            },
+            Expr::FnCall { name, args } => Expr::FnCall {
+                name: name.clone(),
+                args: args
+                    .iter()
+                    .map(|arg| self.resolve_aggregates(arg, rows))
+                    .collect::<Result<_, _>>()?,
+            },
+            Expr::Coalesce { args } => Expr::Coalesce {
+                args: args
+                    .iter()
+                    .map(|arg| self.resolve_aggregates(arg, rows))
+                    .collect::<Result<_, _>>()?,
+            },
+            // Aggregates over referencing records are computed for every row
+            Expr::Aggregate { .. } if !expr.is_aggregate() => expr.clone(),
+            Expr::ReferencedBy {
+                value,
+                table_name,
+                field_name,
+            } => Expr::ReferencedBy {
+                value: Box::new(self.resolve_aggregates(value, rows)?),
+                table_name: table_name.clone(),
+                field_name: field_name.clone(),
+            },
+            Expr::Any {
+                var,
+                list,
+                predicate,
+            } => Expr::Any {
+                var: var.clone(),
+                list: Box::new(self.resolve_aggregates(list, rows)?),
+                predicate: Box::new(self.resolve_aggregates(predicate, rows)?),
+            },
+            Expr::Aggregate { func, arg } => {
+                let values = match arg {
+                    Some(arg) => rows
+                        .iter()
+                        .map(|row| match self.eval(arg, row)? {
+                            Value::Field(value) => Ok(value),
+                            value => Err(EvalErr::MissmatchedTypes {
+                                found: value.ty(),
+                                expected: None,
+                            }),
                         })
-                        .collect(),
-                });
+                        .collect::<Result<Vec<_>, _>>()?,
+                    // `count()` counts every row
+                    None => vec![FieldValue::Bool(true); rows.len()],
+                };
+
+                let ty = match expr.ty(&self.ty_ctx()) {
+                    Ok(Ty::Field(ty)) => Some(ty),
+                    _ => None,
+                };
+
+                Expr::Literal(func.eval(values, ty.as_ref())?)
             }
+        };
+
+        Ok(expr)
+    }
+}
+
+/// A number of another numeric type than `ty` converted to `ty`, other values are returned as they are.
+///
+/// Returns `None` if no number of type `ty` is equal to `value`.
+fn as_numeric_ty(value: FieldValue, ty: &FieldTy) -> Option<FieldValue> {
+    let value_ty = value.ty();
+
+    if !value_ty.is_numeric() || !ty.is_numeric() || &value_ty == ty {
+        return Some(value);
+    }
+
+    let converted = value.cast(ty)?;
+
+    (converted.cast(&value_ty)? == value).then_some(converted)
+}
+
+fn compare_keys(
+    order_by: &[OrderBy],
+    a: &[Option<FieldValue>],
+    b: &[Option<FieldValue>],
+) -> Ordering {
+    for ((key, a), b) in order_by.iter().zip(a).zip(b) {
+        let ordering = match (a, b) {
+            (Some(a), Some(b)) => a.total_cmp(b),
+            (a, b) => a.is_some().cmp(&b.is_some()),
+        };
+
+        let ordering = match key.direction {
+            SortDirection::Asc => ordering,
+            SortDirection::Desc => ordering.reverse(),
+        };
+
+        if ordering.is_ne() {
+            return ordering;
         }
     }
+
+    Ordering::Equal
 }
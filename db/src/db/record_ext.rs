@@ -1,13 +1,65 @@
-use db_core::record::RecordBytes;
-use redb::{ReadableTable, Value};
+use bytepack::{BytePacker, PackFormat};
+use db_core::{named::Named, record::RecordBytes, value::FieldValue};
+use redb::{ReadableTable, Value, WriteTransaction};
 use ulid::Ulid;
 
 use crate::{Db, db::TableWithIdDef, error::DbError};
 
 impl Db {
     pub fn insert_record(&self, table_name: &str, record: &RecordBytes) -> Result<(), DbError> {
-        println!("Inserting {}:{}", table_name, record.id());
-        let tx = self.inner.db.begin_write()?;
+        self.write(|tx| tx.insert(table_name, record))
+    }
+
+    /// Replaces the bytes of the record `record_id` with the bytes of `record`.
+    ///
+    /// The id of `record` is ignored, the stored record keeps its id.
+    pub fn update_record(
+        &self,
+        table_name: &str,
+        record_id: Ulid,
+        record: &RecordBytes,
+    ) -> Result<(), DbError> {
+        self.write(|tx| tx.update(table_name, record_id, record))
+    }
+
+    /// Updates only the given fields of the record `record_id`, all other fields keep their value.
+    pub fn update_record_fields(
+        &self,
+        table_name: &str,
+        record_id: Ulid,
+        values: impl IntoIterator<Item = Named<FieldValue>>,
+    ) -> Result<(), DbError> {
+        self.write(|tx| tx.update_fields(table_name, record_id, values))
+    }
+
+    pub fn delete_record(&self, table_name: &str, record_id: Ulid) -> Result<(), DbError> {
+        self.write(|tx| tx.delete(table_name, record_id))
+    }
+
+    pub(super) fn replace_record(
+        &self,
+        table_name: &str,
+        record: &RecordBytes,
+        tx: &WriteTransaction,
+    ) -> Result<(), DbError> {
+        self.check_record(table_name, record)?;
+
+        // Checked before anything is written, so a missing record leaves no row or blob behind
+        let old_bytes = {
+            let table = tx.open_table(TableWithIdDef::new(table_name))?;
+
+            let Some(value) = table.get(record.id().0)? else {
+                return Err(DbError::RecordDoesNotExist {
+                    table: table_name.into(),
+                    record: record.id(),
+                });
+            };
+
+            value.value().to_owned()
+        };
+
+        let stored = self.store_large_blobs(table_name, record, tx)?;
+        let record = stored.as_ref().unwrap_or(record);
 
         {
             let mut table = tx.open_table(TableWithIdDef::new(table_name))?;
@@ -15,32 +67,94 @@ impl Db {
             table.insert(record.id().0, record.bytes())?;
         }
 
-        self.emit_insert(table_name, record, &tx)?;
+        let old_record = RecordBytes::new(record.id(), old_bytes);
 
-        tx.commit()?;
-
-        Ok(())
+        self.emit_update(table_name, &old_record, record, tx)
     }
 
-    pub fn delete_record(&self, table_name: &str, record_id: Ulid) -> Result<(), DbError> {
-        println!("Deleting {}:{}", table_name, record_id);
-        let tx = self.inner.db.begin_write()?;
+    pub(super) fn patch_record(
+        &self,
+        table_name: &str,
+        record_id: Ulid,
+        values: impl IntoIterator<Item = Named<FieldValue>>,
+        tx: &WriteTransaction,
+    ) -> Result<(), DbError> {
+        let table_data = self.require_table(table_name)?;
 
-        let bytes = {
-            let mut table = tx.open_table(TableWithIdDef::new(table_name))?;
+        let old_record = {
+            let table = tx.open_table(TableWithIdDef::new(table_name))?;
 
-            let Some(value) = table.remove(record_id.0)? else {
-                return Err(DbError::RecordDoesNotExist { table: table_name.into(), record: record_id })
+            let Some(value) = table.get(record_id.0)? else {
+                return Err(DbError::RecordDoesNotExist {
+                    table: table_name.into(),
+                    record: record_id,
+                });
             };
 
-            value.value().to_owned()
+            RecordBytes::new(record_id, value.value().to_owned())
         };
 
-        self.emit_delete(table_name, &RecordBytes::new(record_id, bytes), &tx)?;
+        let mut field_values = table_data
+            .fields()
+            .map(|field| {
+                let value = old_record.get_field(&field.value).ok_or_else(|| {
+                    DbError::CorruptRecord {
+                        table: table_name.into(),
+                        record: record_id,
+                    }
+                })?;
+
+                Ok(Named::new(field.name.clone(), value))
+            })
+            .collect::<Result<Vec<_>, DbError>>()?;
+
+        for Named { name, value } in values {
+            let Some(field_value) = field_values.iter_mut().find(|field| field.name == name) else {
+                return Err(DbError::FieldDoesNotExist {
+                    table: table_name.into(),
+                    field: name,
+                });
+            };
+
+            if let Some(field) = table_data.field(&name)
+                && !field.ty.accepts(&value)
+            {
+                return Err(DbError::WrongType {
+                    expected: field.ty.clone(),
+                });
+            }
 
-        tx.commit()?;
+            field_value.value = value;
+        }
+
+        let mut packer = BytePacker::new(table_data.fixed_byte_count());
+
+        for Named { name, value } in &field_values {
+            if let Some(field) = table_data.field(name) {
+                value.pack_as(&field.ty, field.offset, &mut packer);
+            }
+        }
+
+        let record = RecordBytes::new(record_id, packer.finish());
 
-        Ok(())
+        self.replace_record(table_name, &record, tx)
+    }
+
+    /// Checks that every field of `record` can be read with the layout of the table `table_name`.
+    pub(super) fn check_record(&self, table_name: &str, record: &RecordBytes) -> Result<(), DbError> {
+        let table_data = self.require_table(table_name)?;
+
+        if table_data
+            .fields()
+            .all(|field| record.get_field(&field.value).is_some())
+        {
+            Ok(())
+        } else {
+            Err(DbError::CorruptRecord {
+                table: table_name.into(),
+                record: record.id(),
+            })
+        }
     }
 
     pub(super) fn record_exists<V: Value + 'static>(
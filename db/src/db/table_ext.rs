@@ -1,23 +1,32 @@
 use std::{collections::BTreeMap, sync::Arc};
 
-use bytepack::{BytePacker, ByteUnpacker, PackFormat, Unpack};
+use bytepack::PackFormat;
 use db_core::{
     defs::{
-        index::IndexDef,
+        index::{IndexDef, IndexOnDelete},
         table::{TableData, TableDef, TableFieldData},
         trigger::DbTrigger,
     },
     named::Named,
+    ty::FieldTy,
+};
+use redb::{ReadableDatabase, ReadableTable, TableDefinition, TableError};
+
+use crate::{
+    Db,
+    db::{
+        TableWithIdDef,
+        blob_ext::{BlobTableDef, blob_table_name},
+        index_key::IndexTableDef,
+    },
+    error::DbError,
 };
-use redb::{ReadableDatabase, ReadableTable, TableDefinition};
-
-use crate::{Db, db::TableWithIdDef};
 
-const TABLE_DEF_TABLE: TableDefinition<'static, &str, &[u8]> = TableDefinition::new("$table");
+pub(super) const TABLE_DEF_TABLE: TableDefinition<'static, &str, &[u8]> = TableDefinition::new("$table");
 
 impl Db {
-    pub(super) fn update_table_map(&self) {
-        let tx = self.inner.db.begin_read().unwrap();
+    pub(super) fn update_table_map(&self) -> Result<(), DbError> {
+        let tx = self.inner.db.begin_read()?;
 
         let mut table_map = self.inner.tables.write().unwrap();
         *table_map = Default::default();
@@ -25,19 +34,20 @@ impl Db {
         let mut table_list = Vec::new();
 
         {
-            let Ok(tables) = tx.open_table(TABLE_DEF_TABLE) else {
-                return;
+            let tables = match tx.open_table(TABLE_DEF_TABLE) {
+                Ok(tables) => tables,
+                Err(TableError::TableDoesNotExist(_)) => return Ok(()),
+                Err(err) => return Err(err.into()),
             };
 
-            for table_field in tables.iter().unwrap() {
-                let (name, table_fields) = table_field.unwrap();
+            for table_field in tables.iter()? {
+                let (name, table_fields) = table_field?;
 
                 let name = name.value();
-                let value = {
-                    let bytes = table_fields.value();
-                    let unpacker = ByteUnpacker::new(bytes);
 
-                    TableDef::unpack(0, &unpacker).unwrap().clone()
+                let Some(value) = TableDef::from_stored(table_fields.value())
+                else {
+                    return Err(DbError::CorruptTableDef { table: name.into() });
                 };
 
                 table_list.push(Named {
@@ -48,70 +58,180 @@ impl Db {
         }
 
         table_map.register_tables(table_list);
+
+        Ok(())
     }
 
-    pub fn register_table(&self, table: Named<TableDef>) {
+    pub fn register_table(&self, table: Named<TableDef>) -> Result<(), DbError> {
         let mut tables = self.inner.tables.write().unwrap();
 
         let name = &table.name;
         let table_def = &table.value;
 
         if tables.tables.contains_key(name.as_ref()) {
-            panic!("Table already exists");
+            return Err(DbError::DuplicateTable { table: name.clone() });
         }
 
-        let tx = self.inner.db.begin_write().unwrap();
-
-        {
-            let mut tables: redb::Table<'_, &str, &[u8]> = tx.open_table(TABLE_DEF_TABLE).unwrap();
+        check_table_def(name, table_def)?;
 
-            let current_table = tables.get(name.as_ref()).unwrap();
+        let tx = self.inner.db.begin_write()?;
 
-            if let Some(current_table) = current_table {
-                let unpacker = ByteUnpacker::new(current_table.value());
+        {
+            let mut tables: redb::Table<'_, &str, &[u8]> = tx.open_table(TABLE_DEF_TABLE)?;
 
-                let current_table = TableDef::unpack(0, &unpacker).unwrap();
+            let current_table = tables
+                .get(name.as_ref())?
+                .map(|bytes| TableDef::from_stored(bytes.value()));
 
-                if &current_table == table_def {
-                    panic!("missmatched table definitions");
+            match current_table {
+                Some(Some(current_table)) => {
+                    if &current_table != table_def {
+                        return Err(DbError::SchemaMismatch { table: name.clone() });
+                    }
                 }
-            } else {
-                drop(current_table);
-
-                let bytes = BytePacker::pack_value(&table_def);
+                Some(None) => return Err(DbError::CorruptTableDef { table: name.clone() }),
+                None => {
+                    let bytes = table_def.to_stored();
 
-                tables.insert(name.as_ref(), &*bytes).unwrap();
-                tx.open_table(TableWithIdDef::new(name.as_ref())).unwrap();
+                    tables.insert(name.as_ref(), &*bytes)?;
+                    tx.open_table(TableWithIdDef::new(name.as_ref()))?;
+                }
             }
         }
 
-        tx.commit().unwrap();
+        tx.commit()?;
 
         tables.register_tables([table]);
+
+        Ok(())
     }
 
-    pub fn delete_table(&self, table_name: &str) {
+    pub fn delete_table(&self, table_name: &str) -> Result<(), DbError> {
         let mut table_map = self.inner.tables.write().unwrap();
 
-        if !table_map.remove_table(table_name) {
-            println!("Unknown table");
-            return;
+        let Some(table) = table_map.table(table_name) else {
+            return Err(DbError::TableDoesNotExist {
+                table: table_name.into(),
+            });
+        };
+
+        let indices = table.indices(&Arc::from(table_name));
+
+        let tx = self.inner.db.begin_write()?;
+
+        {
+            let mut tables = tx.open_table(TABLE_DEF_TABLE)?;
+
+            tables.remove(table_name)?;
+
+            tx.delete_table(TableWithIdDef::new(table_name))?;
+            tx.delete_table(BlobTableDef::new(&blob_table_name(table_name)))?;
+
+            for index in indices {
+                tx.delete_multimap_table(IndexTableDef::new(&index.index_name))?;
+            }
         }
 
-        let tx = self.inner.db.begin_write().unwrap();
+        tx.commit()?;
+
+        table_map.remove_table(table_name);
+
+        Ok(())
+    }
+
+    pub(super) fn require_table(&self, table_name: &str) -> Result<TableData, DbError> {
+        self.table(table_name).ok_or_else(|| DbError::TableDoesNotExist {
+            table: table_name.into(),
+        })
+    }
+}
+
+/// Checks the parts of `table_def` that the stored records can not violate.
+pub(super) fn check_table_def(table_name: &Arc<str>, table_def: &TableDef) -> Result<(), DbError> {
+    for Named { name, value: field } in &table_def.fields {
+        // Deleting the referenced record could not remove the reference
+        if field.on_delete == IndexOnDelete::SetNone
+            && !field.ty.is_nullable()
+            && field.ty.element().is_none()
+        {
+            return Err(DbError::InvalidOnDelete {
+                table: table_name.clone(),
+                field: name.clone(),
+            });
+        }
 
+        if let Some(variants) = enum_variants(&field.ty)
+            && !valid_variants(variants)
         {
-            let mut tables = tx.open_table(TABLE_DEF_TABLE).unwrap();
+            return Err(DbError::InvalidEnumVariants {
+                table: table_name.clone(),
+                field: name.clone(),
+            });
+        }
+
+        if field.unique {
+            check_index_fields(table_name, table_def, std::slice::from_ref(name))?;
+        }
+    }
+
+    for field_names in table_def.unique.iter().chain(&table_def.indices) {
+        check_index_fields(table_name, table_def, field_names)?;
+    }
 
-            tables.remove(table_name).unwrap();
+    Ok(())
+}
 
-            tx.delete_table(TableWithIdDef::new(table_name)).unwrap();
+/// Every field of an index must exist and have a type that index keys can encode, see `encode_key`.
+/// Only indices over a single field index lists by their elements.
+fn check_index_fields(
+    table_name: &Arc<str>,
+    table_def: &TableDef,
+    field_names: &[Arc<str>],
+) -> Result<(), DbError> {
+    for field_name in field_names {
+        let Some(field) = table_def
+            .fields
+            .iter()
+            .find(|field| &field.name == field_name)
+        else {
+            return Err(DbError::FieldDoesNotExist {
+                table: table_name.clone(),
+                field: field_name.clone(),
+            });
+        };
+
+        let ty = &field.value.ty;
+
+        if ty.indexed().non_null() == &FieldTy::Blob
+            || (field_names.len() > 1 && ty.element().is_some())
+        {
+            return Err(DbError::UnsupportedIndexType {
+                index: IndexDef::name_for(table_name, field_names),
+                ty: ty.clone(),
+            });
         }
+    }
+
+    Ok(())
+}
 
-        tx.commit().unwrap();
+fn enum_variants(ty: &FieldTy) -> Option<&[Arc<str>]> {
+    match ty {
+        FieldTy::Enum { variants } => Some(variants),
+        FieldTy::Nullable(ty) | FieldTy::List(ty) => enum_variants(ty),
+        _ => None,
     }
 }
 
+/// Values are stored as variant index and variant names are packed separated by `\0`,
+/// see `FieldTy::pack`
+fn valid_variants(variants: &[Arc<str>]) -> bool {
+    !variants.is_empty()
+        && variants.iter().enumerate().all(|(idx, variant)| {
+            !variant.is_empty() && !variant.contains('\0') && !variants[..idx].contains(variant)
+        })
+}
+
 impl Db {
     pub fn table_names(&self) -> Vec<Arc<str>> {
         let tables = self.inner.tables.read().unwrap();
@@ -154,15 +274,11 @@ impl DbTables {
         let mut triggers = Vec::new();
 
         for index in indices {
-            let Some(target_field) = self.table_field(&index.table_name, &index.field_name) else {
-                println!(
-                    "could not get field {} of table {} for index {}",
-                    index.field_name, index.table_name, index.index_name
-                );
+            let Some(target_field_tys) = self.index_field_tys(&index) else {
                 continue;
             };
 
-            let mut index_triggers = index.triggers(&target_field.ty);
+            let mut index_triggers = index.triggers(&target_field_tys);
 
             println!(
                 "registering index {} with {} triggers",
@@ -206,15 +322,11 @@ impl DbTables {
         let mut triggers = Vec::new();
 
         for index in indices {
-            let Some(target_field) = self.table_field(&index.table_name, &index.field_name) else {
-                println!(
-                    "could not get field {} of table {} for index {}",
-                    index.field_name, index.table_name, index.index_name
-                );
+            let Some(target_field_tys) = self.index_field_tys(&index) else {
                 continue;
             };
 
-            triggers.append(&mut index.triggers(&target_field.ty));
+            triggers.append(&mut index.triggers(&target_field_tys));
             self.indices.insert(index.index_name.clone(), index);
         }
 
@@ -223,6 +335,50 @@ impl DbTables {
         }
     }
 
+    fn index_field_tys(&self, index: &IndexDef) -> Option<Vec<FieldTy>> {
+        index
+            .field_names
+            .iter()
+            .map(|field_name| {
+                let Some(field) = self.table_field(&index.table_name, field_name) else {
+                    println!(
+                        "could not get field {} of table {} for index {}",
+                        field_name, index.table_name, index.index_name
+                    );
+                    return None;
+                };
+
+                Some(field.ty.clone())
+            })
+            .collect()
+    }
+
+    pub(super) fn index_fields(
+        &self,
+        index_name: &str,
+    ) -> Result<(IndexDef, Vec<TableFieldData>), DbError> {
+        let Some(index) = self.indices.get(index_name) else {
+            return Err(DbError::UnknownIndex {
+                index: index_name.into(),
+            });
+        };
+
+        let fields = index
+            .field_names
+            .iter()
+            .map(|field_name| {
+                self.table_field(&index.table_name, field_name)
+                    .cloned()
+                    .ok_or_else(|| DbError::FieldDoesNotExist {
+                        table: index.table_name.clone(),
+                        field: field_name.clone(),
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok((index.clone(), fields))
+    }
+
     pub fn table<'a>(&'a self, name: &str) -> Option<&'a TableData> {
         self.tables.get(name)
     }
@@ -0,0 +1,147 @@
+use std::cell::Cell;
+
+use db_core::{named::Named, record::RecordBytes, value::FieldValue};
+use redb::{ReadableTable, WriteTransaction};
+use ulid::Ulid;
+
+use crate::{Db, db::TableWithIdDef, error::DbError};
+
+/// A write transaction spanning multiple operations.
+///
+/// Created by [`Db::write`]. All operations run inside the same redb transaction,
+/// reads see the uncommitted writes of previous operations.
+pub struct DbTransaction<'db> {
+    pub(super) db: &'db Db,
+    pub(super) tx: WriteTransaction,
+    pub(super) schema_changed: Cell<bool>,
+}
+
+impl Db {
+    /// Runs `f` inside a single write transaction.
+    ///
+    /// The transaction is committed if `f` returns `Ok`, otherwise every write done inside `f` is rolled back.
+    pub fn write<T>(
+        &self,
+        f: impl FnOnce(&DbTransaction) -> Result<T, DbError>,
+    ) -> Result<T, DbError> {
+        let tx = DbTransaction {
+            db: self,
+            tx: self.inner.db.begin_write()?,
+            schema_changed: Cell::new(false),
+        };
+
+        let result = f(&tx);
+        let schema_changed = tx.schema_changed.get();
+
+        let result = match result {
+            Ok(value) => tx.tx.commit().map(|_| value).map_err(Into::into),
+            Err(err) => tx.tx.abort().map_err(Into::into).and(Err(err)),
+        };
+
+        // The in memory table map already contains the schema changes of the transaction.
+        // A map that no longer matches the database is worse than the failed transaction,
+        // so an error restoring it is returned instead.
+        if result.is_err() && schema_changed {
+            self.update_table_map()?;
+        }
+
+        result
+    }
+}
+
+impl DbTransaction<'_> {
+    pub fn get(&self, table_name: &str, id: Ulid) -> Result<Option<RecordBytes>, DbError> {
+        self.db.require_table(table_name)?;
+
+        let table = self.tx.open_table(TableWithIdDef::new(table_name))?;
+
+        let Some(value) = table.get(id.0)? else {
+            return Ok(None);
+        };
+
+        Ok(Some(RecordBytes::new(id, value.value().to_owned())))
+    }
+
+    pub fn get_all(&self, table_name: &str) -> Result<Vec<RecordBytes>, DbError> {
+        self.db.require_table(table_name)?;
+
+        let table = self.tx.open_table(TableWithIdDef::new(table_name))?;
+
+        let mut result = Vec::new();
+
+        for entry in table.iter()? {
+            let (id, value) = entry?;
+
+            result.push(RecordBytes::new(Ulid(id.value()), value.value().to_owned()));
+        }
+
+        Ok(result)
+    }
+
+    pub fn insert(&self, table_name: &str, record: &RecordBytes) -> Result<(), DbError> {
+        self.db.check_record(table_name, record)?;
+
+        // Overwriting would keep the index entries of the old record, see `update` instead
+        if self.get(table_name, record.id())?.is_some() {
+            return Err(DbError::RecordAlreadyExists {
+                table: table_name.into(),
+                record: record.id(),
+            });
+        }
+
+        let stored = self.db.store_large_blobs(table_name, record, &self.tx)?;
+        let record = stored.as_ref().unwrap_or(record);
+
+        {
+            let mut table = self.tx.open_table(TableWithIdDef::new(table_name))?;
+
+            table.insert(record.id().0, record.bytes())?;
+        }
+
+        self.db.emit_insert(table_name, record, &self.tx)
+    }
+
+    /// Replaces the bytes of the record `record_id` with the bytes of `record`.
+    ///
+    /// The id of `record` is ignored, the stored record keeps its id.
+    pub fn update(
+        &self,
+        table_name: &str,
+        record_id: Ulid,
+        record: &RecordBytes,
+    ) -> Result<(), DbError> {
+        let record = RecordBytes::new(record_id, record.bytes().to_owned());
+
+        self.db.replace_record(table_name, &record, &self.tx)
+    }
+
+    /// Updates only the given fields of the record `record_id`, all other fields keep their value.
+    pub fn update_fields(
+        &self,
+        table_name: &str,
+        record_id: Ulid,
+        values: impl IntoIterator<Item = Named<FieldValue>>,
+    ) -> Result<(), DbError> {
+        self.db.patch_record(table_name, record_id, values, &self.tx)
+    }
+
+    pub fn delete(&self, table_name: &str, record_id: Ulid) -> Result<(), DbError> {
+        self.db.require_table(table_name)?;
+
+        let bytes = {
+            let mut table = self.tx.open_table(TableWithIdDef::new(table_name))?;
+
+            let Some(value) = table.remove(record_id.0)? else {
+                return Err(DbError::RecordDoesNotExist {
+                    table: table_name.into(),
+                    record: record_id,
+                });
+            };
+
+            value.value().to_owned()
+        };
+
+        self.db
+            .emit_delete(table_name, &RecordBytes::new(record_id, bytes), &self.tx)
+    }
+}
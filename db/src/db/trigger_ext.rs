@@ -30,6 +30,28 @@ impl Db {
         Ok(())
     }
 
+    pub(super) fn emit_update(
+        &self,
+        table_name: &str,
+        old_record: &RecordBytes,
+        new_record: &RecordBytes,
+        tx: &WriteTransaction,
+    ) -> Result<(), DbError> {
+        self.delete_unused_blobs(table_name, old_record, Some(new_record), tx)?;
+
+        let Some(table_triggers) = self.get_triggers(table_name) else {
+            return Ok(());
+        };
+
+        for trigger in table_triggers {
+            if let DbTrigger::OnUpdate(action) = trigger {
+                self.run_update_trigger_action(table_name, old_record, new_record, action, tx)?;
+            }
+        }
+
+        Ok(())
+    }
+
     pub(super) fn emit_delete(
         &self,
         table_name: &str,
@@ -37,6 +59,8 @@ impl Db {
         tx: &WriteTransaction,
     ) -> Result<(), DbError> {
         println!("Emit delete for {}:{}", table_name, record.id());
+        self.delete_unused_blobs(table_name, record, None, tx)?;
+
         let Some(table_triggers) = self.get_triggers(table_name) else {
             println!("{} has no triggers", table_name);
             return Ok(());
@@ -85,6 +109,9 @@ impl Db {
             TriggerAction::InsertIntoIndex { index_name } => {
                 self.index_insert(tx, &index_name, record)
             }
+            TriggerAction::UpdateIndex { index_name } => {
+                self.index_update(tx, &index_name, record, record)
+            }
             TriggerAction::DeleteValueFromIndex { index_name } => {
                 self.index_delete_value(tx, &index_name, record)
             }
@@ -93,4 +120,20 @@ impl Db {
             }
         }
     }
+
+    fn run_update_trigger_action(
+        &self,
+        table_name: &str,
+        old_record: &RecordBytes,
+        new_record: &RecordBytes,
+        action: TriggerAction,
+        tx: &WriteTransaction,
+    ) -> Result<(), DbError> {
+        match action {
+            TriggerAction::UpdateIndex { index_name } => {
+                self.index_update(tx, &index_name, old_record, new_record)
+            }
+            action => self.run_trigger_action(table_name, new_record, action, tx),
+        }
+    }
 }
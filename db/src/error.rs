@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use db_core::ty::FieldTy;
+use db_core::{expr::EvalErr, ty::FieldTy};
 use ulid::Ulid;
 
 #[derive(thiserror::Error, Debug)]
@@ -9,10 +9,59 @@ pub enum DbError {
     Redb(redb::Error),
     #[error("Record {table}:{record} does not exist")]
     RecordDoesNotExist { table: Arc<str>, record: Ulid },
+    #[error("Record {table}:{record} already exists")]
+    RecordAlreadyExists { table: Arc<str>, record: Ulid },
     #[error("Expected type '{expected:?}'")]
     WrongType { expected: FieldTy },
     #[error("Table {table} does not exist")]
     TableDoesNotExist { table: Arc<str> },
+    #[error("Field {field} does not exist on table {table}")]
+    FieldDoesNotExist { table: Arc<str>, field: Arc<str> },
+    #[error("Record {table}:{record} can not be deleted, it is still referenced by {referenced_by_table}:{referenced_by}")]
+    RecordIsReferenced {
+        table: Arc<str>,
+        record: Ulid,
+        referenced_by_table: Arc<str>,
+        referenced_by: Ulid,
+    },
+    #[error("Field {field} already exists on table {table}")]
+    FieldAlreadyExists { table: Arc<str>, field: Arc<str> },
+    #[error("Field {field} of table {table} can only be set to none on delete if it is nullable or a list")]
+    InvalidOnDelete { table: Arc<str>, field: Arc<str> },
+    #[error("Enum field {field} of table {table} needs at least one variant, names must be distinct, non-empty and without null characters")]
+    InvalidEnumVariants { table: Arc<str>, field: Arc<str> },
+    #[error("Table {table} already exists")]
+    DuplicateTable { table: Arc<str> },
+    #[error("Table {table} is used more than once in the query")]
+    DuplicateQueryTable { table: Arc<str> },
+    #[error("Index {index} does not exist")]
+    UnknownIndex { index: Arc<str> },
+    #[error("Index {index} has {expected} fields, but the query uses {found}")]
+    IndexFieldCount {
+        index: Arc<str>,
+        expected: usize,
+        found: usize,
+    },
+    #[error("Index {index} does not support fields of type '{ty:?}'")]
+    UnsupportedIndexType { index: Arc<str>, ty: FieldTy },
+    #[error("Record {table}:{record} does not match the layout of its table")]
+    CorruptRecord { table: Arc<str>, record: Ulid },
+    #[error("The stored definition of table {table} can not be read")]
+    CorruptTableDef { table: Arc<str> },
+    #[error("Table {table} does not match its stored definition")]
+    SchemaMismatch { table: Arc<str> },
+    #[error("Invalid {clause} in query: {err}")]
+    InvalidQuery { clause: Arc<str>, err: EvalErr },
+    #[error("Query can not be evaluated: {err}")]
+    Eval { err: EvalErr },
+    #[error("{field} of table {table} must be unique, the value is already used by {table}:{record}")]
+    UniqueViolation {
+        table: Arc<str>,
+        field: Arc<str>,
+        record: Ulid,
+    },
+    #[error("Blob content can not be read: {err}")]
+    BlobIo { err: std::io::Error },
 }
 
 impl<T: Into<redb::Error>> From<T> for DbError {
@@ -2,7 +2,7 @@ mod db;
 // mod field_value;
 mod error;
 
-pub use db::Db;
+pub use db::{BlobReader, Db, DbTransaction, JoinLookup, JoinPlan, QueryPlan};
 // pub use field_value::*;
 pub use ulid::Ulid;
 pub use error::DbError;
\ No newline at end of file
@@ -0,0 +1,126 @@
+//! Binary attachments of `FieldTy::Blob` fields, like receipts or screenshots.
+
+use std::{fmt::Display, sync::Arc};
+
+use bytepack::{BytePacker, ByteUnpacker, Pack, PackPointer, Unpack};
+use ulid::Ulid;
+
+use crate::inline_pointer::{InlinePointerPack, InlinePointerUnpack};
+
+/// Raw bytes with the MIME type describing them, e.g. `image/png`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Blob {
+    pub mime: Arc<str>,
+    pub content: BlobContent,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum BlobContent {
+    /// The bytes stored in the record itself
+    Inline(Vec<u8>),
+    /// `len` bytes stored outside of the record, in chunks keyed by the record id and `id`.
+    ///
+    /// Only valid in the record they were written for, see `Db::write_blob`.
+    External { id: Ulid, len: u64 },
+}
+
+impl Blob {
+    /// The number of bytes of the content
+    pub fn len(&self) -> u64 {
+        match &self.content {
+            BlobContent::Inline(bytes) => bytes.len() as u64,
+            BlobContent::External { len, .. } => *len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Display for Blob {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<{}, {} bytes>", self.mime, self.len())
+    }
+}
+
+const INLINE_TAG: [u8; 4] = *b"inln";
+const EXTERNAL_TAG: [u8; 4] = *b"extn";
+
+/// The MIME type followed by the tagged content, both behind a pointer
+impl Pack for Blob {
+    const PACK_BYTES: u32 = PackPointer::PACK_BYTES;
+
+    fn pack(&self, offset: u32, packer: &mut BytePacker) {
+        let pointer =
+            packer.reserve_dynamic_bytes(Arc::<str>::PACK_BYTES + BlobContent::PACK_BYTES);
+        pointer.pack(offset, packer);
+
+        self.mime.pack(pointer.offset, packer);
+        self.content
+            .pack(pointer.offset + Arc::<str>::PACK_BYTES, packer);
+    }
+}
+
+impl<'b> Unpack<'b> for Blob {
+    fn unpack(offset: u32, unpacker: &ByteUnpacker<'b>) -> Option<Self> {
+        let pointer = PackPointer::unpack(offset, unpacker)?;
+
+        if pointer.len != Arc::<str>::PACK_BYTES + BlobContent::PACK_BYTES {
+            return None;
+        }
+
+        Some(Self {
+            mime: Arc::unpack(pointer.offset, unpacker)?,
+            content: BlobContent::unpack(pointer.offset + Arc::<str>::PACK_BYTES, unpacker)?,
+        })
+    }
+}
+
+impl Pack for BlobContent {
+    const PACK_BYTES: u32 = InlinePointerPack::<BlobContent>::PACK_BYTES;
+
+    fn pack(&self, offset: u32, packer: &mut BytePacker) {
+        let external;
+
+        let pointer: InlinePointerPack<'_, Self> = match self {
+            BlobContent::Inline(bytes) => InlinePointerPack::Indirect {
+                tag: INLINE_TAG,
+                value: bytes,
+            },
+            BlobContent::External { id, len } => {
+                external = [id.0.to_be_bytes().as_slice(), &len.to_be_bytes()].concat();
+
+                InlinePointerPack::Indirect {
+                    tag: EXTERNAL_TAG,
+                    value: &external,
+                }
+            }
+        };
+
+        pointer.pack(offset, packer);
+    }
+}
+
+impl<'b> Unpack<'b> for BlobContent {
+    fn unpack(offset: u32, unpacker: &ByteUnpacker<'b>) -> Option<Self> {
+        let InlinePointerUnpack::Indirect { tag, value, .. } =
+            InlinePointerUnpack::unpack(offset, unpacker)?
+        else {
+            return None;
+        };
+
+        match tag {
+            INLINE_TAG => Some(BlobContent::Inline(value.to_owned())),
+            EXTERNAL_TAG => {
+                let (id, len) = value.split_first_chunk::<16>()?;
+
+                Some(BlobContent::External {
+                    id: Ulid(u128::from_be_bytes(*id)),
+                    len: u64::from_be_bytes(len.try_into().ok()?),
+                })
+            }
+            _ => None,
+        }
+    }
+}
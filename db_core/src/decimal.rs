@@ -0,0 +1,154 @@
+//! Fixed-point numbers for amounts that must not be rounded like floats, e.g. money.
+
+use std::{fmt::Display, str::FromStr};
+
+use bytepack::{BytePacker, ByteUnpacker, Pack, Unpack};
+
+/// A number with `Decimal::SCALE` decimal places, stored as a multiple of `10^-SCALE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Decimal {
+    units: i64,
+}
+
+impl Decimal {
+    /// The number of decimal places
+    pub const SCALE: u32 = 4;
+
+    const ONE: i64 = 10i64.pow(Self::SCALE);
+
+    /// The decimal `units * 10^-SCALE`
+    pub const fn from_units(units: i64) -> Self {
+        Self { units }
+    }
+
+    pub const fn units(self) -> i64 {
+        self.units
+    }
+
+    /// Returns `None` if `value` is out of range
+    pub fn from_int(value: i64) -> Option<Self> {
+        value.checked_mul(Self::ONE).map(Self::from_units)
+    }
+
+    /// Rounds to `SCALE` decimal places, returns `None` if `value` is out of range or not finite
+    pub fn from_f64(value: f64) -> Option<Self> {
+        let units = (value * Self::ONE as f64).round();
+
+        // `i64::MAX as f64` is rounded up to 2^63, which is out of range
+        (units >= i64::MIN as f64 && units < i64::MAX as f64)
+            .then(|| Self::from_units(units as i64))
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.units as f64 / Self::ONE as f64
+    }
+
+    /// The integer part, rounded towards zero
+    pub fn trunc(self) -> i64 {
+        self.units / Self::ONE
+    }
+
+    /// Returns `None` on overflow
+    pub fn checked_add(self, rhs: Self) -> Option<Self> {
+        self.units.checked_add(rhs.units).map(Self::from_units)
+    }
+
+    /// Returns `None` on overflow
+    pub fn checked_sub(self, rhs: Self) -> Option<Self> {
+        self.units.checked_sub(rhs.units).map(Self::from_units)
+    }
+
+    /// Rounds towards zero to `SCALE` decimal places, returns `None` on overflow
+    pub fn checked_mul(self, rhs: Self) -> Option<Self> {
+        let units = self.units as i128 * rhs.units as i128 / Self::ONE as i128;
+
+        units.try_into().ok().map(Self::from_units)
+    }
+
+    /// Rounds towards zero to `SCALE` decimal places,
+    /// returns `None` on overflow or if `rhs` is zero
+    pub fn checked_div(self, rhs: Self) -> Option<Self> {
+        let units = (self.units as i128 * Self::ONE as i128).checked_div(rhs.units as i128)?;
+
+        units.try_into().ok().map(Self::from_units)
+    }
+
+    /// Returns `None` on overflow
+    pub fn checked_neg(self) -> Option<Self> {
+        self.units.checked_neg().map(Self::from_units)
+    }
+}
+
+/// Parses numbers like `12`, `-0.5` or `3.1415`, with at most `SCALE` decimal places
+impl FromStr for Decimal {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, s),
+        };
+
+        let (int, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+
+        let is_digits = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+
+        if int.is_empty() || !is_digits(int) || !is_digits(fraction) {
+            return Err(());
+        }
+
+        if fraction.len() > Self::SCALE as usize {
+            return Err(());
+        }
+
+        let int = int.parse::<i64>().map_err(|_| ())?;
+        let fraction = format!("{fraction:0<width$}", width = Self::SCALE as usize)
+            .parse::<i64>()
+            .map_err(|_| ())?;
+
+        let units = int
+            .checked_mul(Self::ONE)
+            .and_then(|units| units.checked_add(fraction))
+            .ok_or(())?;
+
+        Ok(Self::from_units(if negative { -units } else { units }))
+    }
+}
+
+/// Formats the number without trailing zeros, so `2.5000` is `2.5`
+impl Display for Decimal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.units < 0 {
+            f.write_str("-")?;
+        }
+
+        let units = self.units.unsigned_abs();
+        let one = Self::ONE.unsigned_abs();
+
+        write!(f, "{}", units / one)?;
+
+        let fraction = units % one;
+
+        if fraction != 0 {
+            let fraction = format!("{fraction:0width$}", width = Self::SCALE as usize);
+
+            write!(f, ".{}", fraction.trim_end_matches('0'))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Pack for Decimal {
+    const PACK_BYTES: u32 = i64::PACK_BYTES;
+
+    fn pack(&self, offset: u32, packer: &mut BytePacker) {
+        self.units.pack(offset, packer);
+    }
+}
+
+impl<'b> Unpack<'b> for Decimal {
+    fn unpack(offset: u32, unpacker: &ByteUnpacker<'b>) -> Option<Self> {
+        i64::unpack(offset, unpacker).map(Self::from_units)
+    }
+}
@@ -1,24 +1,69 @@
 use std::sync::Arc;
 
+use bytepack::{Pack, Unpack};
+
 use crate::{defs::trigger::{DbTrigger, TriggerAction}, ty::FieldTy};
 
 #[derive(Debug, Clone)]
 pub struct IndexDef {
     pub index_name: Arc<str>,
     pub table_name: Arc<str>,
-    pub field_name: Arc<str>,
+    /// The indexed fields, keys are ordered by the first field, then by the second and so on
+    pub field_names: Vec<Arc<str>>,
     pub on_delete: IndexOnDelete,
+    /// No two records can have the same key
+    pub unique: bool,
 }
 
-#[derive(Debug, Clone, Copy)]
+/// What happens to a record when the record it references is deleted
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
 pub enum IndexOnDelete {
+    /// The reference is kept as is
     None,
+    /// The referencing record is deleted as well
     Cascase,
+    /// The reference is set to null, only valid for nullable fields.
+    ///
+    /// References in a list are removed from it instead.
     SetNone,
+    /// The delete fails as long as the record is referenced
+    Restrict,
+}
+
+impl Pack for IndexOnDelete {
+    const PACK_BYTES: u32 = u8::PACK_BYTES;
+
+    fn pack(&self, offset: u32, packer: &mut bytepack::BytePacker) {
+        let value: u8 = match self {
+            IndexOnDelete::None => 0,
+            IndexOnDelete::Cascase => 1,
+            IndexOnDelete::SetNone => 2,
+            IndexOnDelete::Restrict => 3,
+        };
+
+        value.pack(offset, packer);
+    }
+}
+
+impl<'b> Unpack<'b> for IndexOnDelete {
+    fn unpack(offset: u32, unpacker: &bytepack::ByteUnpacker<'b>) -> Option<Self> {
+        match u8::unpack(offset, unpacker)? {
+            0 => Some(IndexOnDelete::None),
+            1 => Some(IndexOnDelete::Cascase),
+            2 => Some(IndexOnDelete::SetNone),
+            3 => Some(IndexOnDelete::Restrict),
+            _ => None,
+        }
+    }
 }
 
 impl IndexDef {
-    pub fn triggers(&self, target_field_ty: &FieldTy) -> Vec<(Arc<str>, DbTrigger)> {
+    /// `#table:field` for single field indices, `#table:first,second` for indices over multiple fields
+    pub fn name_for(table_name: &str, field_names: &[Arc<str>]) -> Arc<str> {
+        format!("#{}:{}", table_name, field_names.join(",")).into()
+    }
+
+    pub fn triggers(&self, target_field_tys: &[FieldTy]) -> Vec<(Arc<str>, DbTrigger)> {
         let mut result = vec![
             (
                 self.table_name.clone(),
@@ -26,6 +71,12 @@ impl IndexDef {
                     index_name: self.index_name.clone(),
                 }),
             ),
+            (
+                self.table_name.clone(),
+                DbTrigger::OnUpdate(TriggerAction::UpdateIndex {
+                    index_name: self.index_name.clone(),
+                }),
+            ),
             (
                 self.table_name.clone(),
                 DbTrigger::OnDelete(TriggerAction::DeleteValueFromIndex {
@@ -37,7 +88,9 @@ impl IndexDef {
         // let source_table = get_table(&self.table_name)?;
         // let field_ty = &source_table.fields.get(&self.field_name)?.ty;
 
-        if let FieldTy::RecordId { table_name } = &target_field_ty {
+        if let [target_field_ty] = target_field_tys
+            && let FieldTy::RecordId { table_name } = target_field_ty.indexed().non_null()
+        {
             result.push((
                 table_name.clone(),
                 DbTrigger::OnDelete(TriggerAction::DeleteKeyFromIndex {
@@ -1,19 +1,121 @@
-use std::{cell::LazyCell, sync::Arc};
+use std::sync::Arc;
 
-use bytepack::{Pack, PackField, PackFormat, Unpack};
+use bytepack::{BytePacker, ByteUnpacker, Pack, PackField, PackFormat, Unpack};
 
-use crate::{defs::index::IndexDef, named::Named, ty::FieldTy};
+use crate::{defs::index::{IndexDef, IndexOnDelete}, named::Named, ty::FieldTy, value::FieldValue};
 
 #[derive(Debug, PartialEq, Eq, Clone, Pack, Unpack)]
 pub struct TableDef {
     pub fields: Vec<Named<TableFieldDef>>,
     pub main_display_field: Option<u32>,
+    /// Sets of fields whose combined values must be unique within the table
+    pub unique: Vec<Vec<Arc<str>>>,
+    /// Indices over multiple fields, ordered by the first field, then by the second and so on
+    pub indices: Vec<Vec<Arc<str>>>,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Pack, Unpack)]
 pub struct TableFieldDef {
     pub ty: FieldTy,
     pub has_index: bool,
+    /// Only used by `RecordId` fields
+    pub on_delete: IndexOnDelete,
+    /// No two records can have the same value in this field
+    pub unique: bool,
+}
+
+/// Written before the packed `TableDef`, see `TableDef::to_stored`
+const TABLE_DEF_VERSION: [u8; 4] = *b"tdf1";
+
+impl TableDef {
+    /// The bytes of the definition stored in the database, starting with the layout version
+    pub fn to_stored(&self) -> Vec<u8> {
+        [TABLE_DEF_VERSION.as_slice(), &BytePacker::pack_value(self)].concat()
+    }
+
+    /// Reads the bytes written by `to_stored`, or the bytes of the first layout without a version.
+    ///
+    /// Those start with the pointer to their fields, which never points as far as the version bytes.
+    pub fn from_stored(bytes: &[u8]) -> Option<Self> {
+        match bytes.strip_prefix(TABLE_DEF_VERSION.as_slice()) {
+            Some(bytes) => Self::unpack(0, &ByteUnpacker::new(bytes)),
+            None => TableDefV0::unpack(0, &ByteUnpacker::new(bytes)).map(Into::into),
+        }
+    }
+}
+
+/// The first layout of `TableDef`, before on delete behaviour, unique constraints and composite indices
+#[derive(Pack, Unpack)]
+struct TableDefV0 {
+    fields: Vec<Named<TableFieldDefV0>>,
+    main_display_field: Option<u32>,
+}
+
+#[derive(Pack, Unpack)]
+struct TableFieldDefV0 {
+    ty: FieldTy,
+    has_index: bool,
+}
+
+impl From<TableDefV0> for TableDef {
+    fn from(value: TableDefV0) -> Self {
+        let fields = value
+            .fields
+            .into_iter()
+            .map(|Named { name, value: field }| {
+                Named::new(
+                    name,
+                    TableFieldDef {
+                        ty: field.ty,
+                        has_index: field.has_index,
+                        // Referencing records were always deleted with the referenced record
+                        on_delete: IndexOnDelete::Cascase,
+                        unique: false,
+                    },
+                )
+            })
+            .collect();
+
+        Self {
+            fields,
+            main_display_field: value.main_display_field,
+            unique: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+}
+
+/// A single schema change of an existing table, see `Db::alter_table`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum AlterTableOp {
+    /// Appends a new field, existing records get `default` as value.
+    AddField {
+        name: Arc<str>,
+        field: TableFieldDef,
+        default: FieldValue,
+    },
+    DropField {
+        name: Arc<str>,
+    },
+    RenameField {
+        name: Arc<str>,
+        new_name: Arc<str>,
+    },
+    /// Changes the type of a field. Existing values are cast to the new type,
+    /// values that can not be cast are replaced by `default`.
+    RetypeField {
+        name: Arc<str>,
+        ty: FieldTy,
+        default: FieldValue,
+    },
+    /// Moves a field to position `index`, or to the end if `index` is out of range.
+    MoveField {
+        name: Arc<str>,
+        index: usize,
+    },
+    SetMainDisplayField {
+        name: Option<Arc<str>>,
+    },
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
@@ -21,6 +123,8 @@ pub struct TableData {
     fields: Vec<Named<TableFieldData>>,
     index: Vec<(Arc<str>, usize)>,
     main_display_field: Option<usize>,
+    unique: Vec<Vec<Arc<str>>>,
+    composite_indices: Vec<Vec<Arc<str>>>,
     fixed_byte_count: u32,
 }
 
@@ -29,6 +133,8 @@ pub struct TableFieldData {
     pub ty: FieldTy,
     pub offset: u32,
     pub has_index: bool,
+    pub on_delete: IndexOnDelete,
+    pub unique: bool,
 }
 
 impl From<TableDef> for TableData {
@@ -41,6 +147,8 @@ impl From<TableDef> for TableData {
                 offset,
                 ty: field.ty,
                 has_index: field.has_index,
+                on_delete: field.on_delete,
+                unique: field.unique,
             };
 
             offset += field.ty.byte_count();
@@ -72,6 +180,8 @@ impl From<TableDef> for TableData {
             fields: fields_vec,
             index,
             main_display_field,
+            unique: value.unique,
+            composite_indices: value.indices,
             fixed_byte_count: offset,
         }
     }
@@ -79,42 +189,71 @@ impl From<TableDef> for TableData {
 
 impl TableData {
     pub fn indices(&self, table_name: &Arc<str>) -> Vec<IndexDef> {
-        let mut result = Vec::with_capacity(2);
+        let mut result = Vec::<IndexDef>::with_capacity(2);
 
         for Named {
             name: field_name,
             value: field,
         } in &self.fields
         {
-            let index_name =
-                LazyCell::new(|| Arc::<str>::from(format!("#{}:{}", table_name, field_name)));
-
-            match field.ty {
-                FieldTy::RecordId { .. } => {
-                    result.push(IndexDef {
-                        index_name: index_name.clone(),
-                        table_name: table_name.clone(),
-                        field_name: field_name.clone(),
-                        on_delete: crate::defs::index::IndexOnDelete::Cascase,
-                    });
-                    continue;
-                }
-                _ => (),
+            let on_delete = match field.ty.indexed().non_null() {
+                FieldTy::RecordId { .. } => field.on_delete,
+                _ if field.has_index || field.unique => IndexOnDelete::None,
+                _ => continue,
+            };
+
+            let field_names = vec![field_name.clone()];
+
+            result.push(IndexDef {
+                index_name: IndexDef::name_for(table_name, &field_names),
+                table_name: table_name.clone(),
+                field_names,
+                on_delete,
+                unique: field.unique,
+            });
+        }
+
+        let composite_indices = self
+            .composite_indices
+            .iter()
+            .map(|field_names| (field_names, false))
+            .chain(self.unique.iter().map(|field_names| (field_names, true)));
+
+        for (field_names, unique) in composite_indices {
+            if field_names.is_empty() {
+                continue;
             }
 
-            if field.has_index {
-                result.push(IndexDef {
-                    index_name: index_name.clone(),
-                    table_name: table_name.clone(),
-                    field_name: field_name.clone(),
-                    on_delete: super::index::IndexOnDelete::None,
-                });
+            if let Some(index) = result
+                .iter_mut()
+                .find(|index| &index.field_names == field_names)
+            {
+                index.unique |= unique;
+                continue;
             }
+
+            result.push(IndexDef {
+                index_name: IndexDef::name_for(table_name, field_names),
+                table_name: table_name.clone(),
+                field_names: field_names.clone(),
+                on_delete: IndexOnDelete::None,
+                unique,
+            });
         }
 
         result
     }
 
+    /// Sets of fields whose combined values must be unique within the table
+    pub fn unique(&self) -> &[Vec<Arc<str>>] {
+        &self.unique
+    }
+
+    /// Indices over multiple fields
+    pub fn composite_indices(&self) -> &[Vec<Arc<str>>] {
+        &self.composite_indices
+    }
+
     pub fn main_display_field(&self) -> Option<&Named<TableFieldData>> {
         match self.main_display_field {
             Some(idx) => Some(&self.fields[idx]),
@@ -4,6 +4,7 @@ use std::sync::Arc;
 #[derive(Debug, Clone)]
 pub enum DbTrigger {
     OnInsert(TriggerAction),
+    OnUpdate(TriggerAction),
     OnDelete(TriggerAction),
 }
 
@@ -15,10 +16,13 @@ pub enum TriggerAction {
     InsertIntoIndex {
         index_name: Arc<str>,
     },
+    UpdateIndex {
+        index_name: Arc<str>,
+    },
     DeleteValueFromIndex {
         index_name: Arc<str>,
     },
     DeleteKeyFromIndex {
         index_name: Arc<str>,
     },
-}
\ No newline at end of file
+}
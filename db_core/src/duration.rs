@@ -0,0 +1,68 @@
+//! The text format of durations: amounts with the units `d`, `h`, `m` and `s`, like `1d2h30m`.
+//!
+//! Used by duration literals and by casts between durations and text.
+
+use std::fmt::Write;
+
+use chrono::TimeDelta;
+
+const UNITS: [(char, i64); 4] = [('d', 86_400), ('h', 3_600), ('m', 60), ('s', 1)];
+
+/// Parses a duration like `2h`, `1h30m` or `-15m`
+pub fn parse(input: &str) -> Option<TimeDelta> {
+    let (negative, mut rest) = match input.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, input),
+    };
+
+    if rest.is_empty() {
+        return None;
+    }
+
+    let mut seconds = 0i64;
+
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let amount = rest[..digits].parse::<i64>().ok()?;
+
+        let mut chars = rest[digits..].chars();
+        let unit = chars.next()?;
+        let (_, unit_seconds) = UNITS.iter().find(|(name, _)| *name == unit)?;
+
+        seconds = seconds.checked_add(amount.checked_mul(*unit_seconds)?)?;
+        rest = chars.as_str();
+    }
+
+    TimeDelta::try_seconds(if negative { -seconds } else { seconds })
+}
+
+/// Formats the whole seconds of `duration`, so that `parse` returns them again
+pub fn format(duration: &TimeDelta) -> String {
+    let seconds = duration.num_seconds();
+
+    if seconds == 0 {
+        return "0s".into();
+    }
+
+    let mut text = String::new();
+
+    if seconds < 0 {
+        text.push('-');
+    }
+
+    let mut rest = seconds.unsigned_abs();
+
+    for (unit, unit_seconds) in UNITS {
+        let unit_seconds = unit_seconds.unsigned_abs();
+        let amount = rest / unit_seconds;
+
+        if amount > 0 {
+            let _ = write!(text, "{amount}{unit}");
+            rest %= unit_seconds;
+        }
+    }
+
+    text
+}
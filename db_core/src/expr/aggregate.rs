@@ -0,0 +1,116 @@
+use std::fmt::Display;
+
+use crate::{
+    expr::{BinaryOp, EvalErr, MathOp},
+    ty::FieldTy,
+    value::FieldValue,
+};
+
+/// A function computing one value over all records of a query or group, see `Query::select`,
+/// or over the referencing records of `Expr::ReferencedBy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateFn {
+    Count,
+    Sum,
+    Min,
+    Max,
+    Avg,
+    First,
+    Last,
+}
+
+impl AggregateFn {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "count" => Some(Self::Count),
+            "sum" => Some(Self::Sum),
+            "min" => Some(Self::Min),
+            "max" => Some(Self::Max),
+            "avg" => Some(Self::Avg),
+            "first" => Some(Self::First),
+            "last" => Some(Self::Last),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Count => "count",
+            Self::Sum => "sum",
+            Self::Min => "min",
+            Self::Max => "max",
+            Self::Avg => "avg",
+            Self::First => "first",
+            Self::Last => "last",
+        }
+    }
+
+    /// The result type for an argument of type `arg`, `count` is the only function without argument.
+    pub fn ty(&self, arg: Option<&FieldTy>) -> Option<FieldTy> {
+        match (self, arg) {
+            (Self::Count, _) => Some(FieldTy::IntI32),
+            (Self::Sum, Some(ty)) => BinaryOp::Math(MathOp::Add).ty(ty.non_null(), ty.non_null()),
+            (Self::Avg, Some(ty)) => {
+                BinaryOp::Math(MathOp::Div).ty(ty.non_null(), &FieldTy::IntI32)
+            }
+            (Self::Min | Self::Max | Self::First | Self::Last, Some(ty)) => Some(ty.clone()),
+            (_, None) => None,
+        }
+    }
+
+    /// Computes the aggregate over the argument values of every record, in the order of the query.
+    ///
+    /// Null values are ignored by everything but `first` and `last`.
+    /// Without any values the result is null of type `ty`.
+    pub fn eval(
+        &self,
+        values: Vec<FieldValue>,
+        ty: Option<&FieldTy>,
+    ) -> Result<FieldValue, EvalErr> {
+        let empty = || match ty {
+            Some(ty) => Ok(FieldValue::Null(ty.non_null().clone())),
+            None => Err(EvalErr::EmptyAggregate { func: *self }),
+        };
+
+        let non_null = values
+            .iter()
+            .filter(|value| !matches!(value, FieldValue::Null(_)))
+            .cloned();
+
+        match self {
+            Self::Count => Ok(FieldValue::Int(non_null.count() as i32)),
+            Self::Sum => sum(non_null).map_or_else(empty, |(sum, _)| sum),
+            Self::Avg => sum(non_null).map_or_else(empty, |(sum, count)| {
+                BinaryOp::Math(MathOp::Div).eval(sum?.into(), FieldValue::Int(count).into())
+            }),
+            Self::Min => non_null
+                .min_by(FieldValue::total_cmp)
+                .map_or_else(empty, Ok),
+            Self::Max => non_null
+                .max_by(FieldValue::total_cmp)
+                .map_or_else(empty, Ok),
+            Self::First => values.into_iter().next().map_or_else(empty, Ok),
+            Self::Last => values.into_iter().last().map_or_else(empty, Ok),
+        }
+    }
+}
+
+/// The sum and the number of `values`, or `None` if there are no values.
+fn sum(mut values: impl Iterator<Item = FieldValue>) -> Option<(Result<FieldValue, EvalErr>, i32)> {
+    let first = values.next()?;
+
+    let mut count = 1;
+
+    let sum = values.try_fold(first, |sum, value| {
+        count += 1;
+        BinaryOp::Math(MathOp::Add).eval(sum.into(), value.into())
+    });
+
+    Some((sum, count))
+}
+
+impl Display for AggregateFn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
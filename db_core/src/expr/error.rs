@@ -1,6 +1,13 @@
 use std::{fmt::Display, sync::Arc};
 
-use crate::{expr::{BinaryOp, UnaryOp}, ty::Ty};
+use ulid::Ulid;
+
+use crate::{
+    defs::table::TableData,
+    expr::{AggregateFn, BinaryOp, UnaryOp},
+    ty::{FieldTy, Ty},
+    value::FieldValue,
+};
 
 #[derive(Debug, thiserror::Error)]
 pub enum EvalErr {
@@ -24,6 +31,40 @@ pub enum EvalErr {
     UnknownFunction { name: Arc<str> },
     #[error("Invalid arg count for function '{name}': found: {found}, expected: {expected}")]
     InvalidFunctionArgCount { name: Arc<str>, found: usize, expected: usize },
+    #[error("Aggregate function '{func}' can only be used in select")]
+    AggregateOutsideSelect { func: AggregateFn },
+    #[error("Aggregate function '{func}' has no values and its type is unknown")]
+    EmptyAggregate { func: AggregateFn },
+    #[error("Record '{table_name}:{id}' does not exist")]
+    RecordNotFound { table_name: Arc<str>, id: Ulid },
+    #[error("Field '{field_name}' of table '{table_name}' is not a reference")]
+    NotAReference {
+        table_name: Arc<str>,
+        field_name: Arc<str>,
+    },
+    #[error("Records of table '{table_name}' can not be read")]
+    RecordsUnavailable { table_name: Arc<str> },
+    /// The operands are boxed to keep the error small
+    #[error("Division by zero in {a} / {b}")]
+    DivisionByZero {
+        a: Box<FieldValue>,
+        b: Box<FieldValue>,
+    },
+    #[error("{a} {op} {b} overflows")]
+    Overflow {
+        op: BinaryOp,
+        a: Box<FieldValue>,
+        b: Box<FieldValue>,
+    },
+    #[error("Value {value} can not be converted to {ty:?}")]
+    InvalidCast { value: FieldValue, ty: FieldTy },
+    #[error("'{variant}' is not one of the variants {variants:?}")]
+    UnknownVariant {
+        variant: String,
+        variants: Arc<[Arc<str>]>,
+    },
+    #[error("Unknown date unit '{unit}', expected year, month, week, day, hour or minute")]
+    UnknownDateUnit { unit: Arc<str> },
     #[error("Bytepack Error")]
     Bytepack,
 }
@@ -41,6 +82,55 @@ pub enum DidYouMeanHint {
     },
 }
 
+impl DidYouMeanHint {
+    /// A hint for the unknown table `name`: a field of the accessible tables with that name,
+    /// or an accessible table with a similar name
+    pub fn for_table<'a>(
+        name: &str,
+        scope: impl Iterator<Item = (&'a Arc<str>, &'a TableData)> + Clone,
+    ) -> Self {
+        if let Some((table_name, _)) = scope
+            .clone()
+            .filter(|(_, table)| table.has_field(name))
+            .min_by_key(|(table_name, _)| *table_name)
+        {
+            return DidYouMeanHint::TableWithField {
+                table_name: table_name.clone(),
+                field_name: name.into(),
+            };
+        }
+
+        scope
+            .map(|(table_name, _)| (edit_distance(name, table_name), table_name))
+            .filter(|(distance, _)| *distance <= 2)
+            .min()
+            .map_or(DidYouMeanHint::None, |(_, table_name)| {
+                DidYouMeanHint::Table {
+                    name: table_name.clone(),
+                }
+            })
+    }
+}
+
+/// The number of inserted, removed or replaced characters to turn `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, a) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+
+        for (j, b) in b.iter().enumerate() {
+            let replaced = prev + (a != *b) as usize;
+            prev = row[j + 1];
+            row[j + 1] = replaced.min(row[j] + 1).min(prev + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
 impl Display for DidYouMeanHint {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
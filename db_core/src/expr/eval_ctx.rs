@@ -1,6 +1,7 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, fmt::Debug, sync::Arc};
 
 use chrono::{DateTime, Utc};
+use ulid::Ulid;
 
 use crate::{defs::table::TableData, record::RecordBytes};
 
@@ -8,5 +9,23 @@ use crate::{defs::table::TableData, record::RecordBytes};
 pub struct EvalCtx {
     pub records: HashMap<Arc<str>, Arc<RecordBytes>>,
     pub tables: HashMap<Arc<str>, Arc<TableData>>,
-    pub now: DateTime<Utc>
+    pub now: DateTime<Utc>,
+    /// Loads the records referenced by `RecordId` values, which can not be dereferenced without it
+    pub source: Option<Arc<dyn RecordSource>>,
+}
+
+/// Access to records that are not part of `EvalCtx::records`
+pub trait RecordSource: Debug + Send + Sync {
+    /// Returns `None` if the record does not exist
+    fn record(&self, table_name: &str, id: Ulid) -> Option<Arc<RecordBytes>>;
+
+    /// Returns the records of `table_name` whose field `field_name` references the record `id`
+    /// of `referenced_table`, or `None` if they can not be read
+    fn referencing(
+        &self,
+        table_name: &str,
+        field_name: &str,
+        referenced_table: &Arc<str>,
+        id: Ulid,
+    ) -> Option<Vec<Arc<RecordBytes>>>;
 }
@@ -1,14 +1,17 @@
-use std::sync::Arc;
+use std::{collections::HashMap, fmt::Display, sync::Arc};
 
 use bytepack::PackFormat;
+use ulid::Ulid;
 
 use crate::{
+    defs::table::{TableData, TableFieldData},
     expr::{
-        DidYouMeanHint, EvalCtx,
+        AggregateFn, DidYouMeanHint, EvalCtx, Function,
         error::EvalErr,
-        op::{BinaryOp, UnaryOp},
+        op::{BinaryOp, UnaryOp, text_variant},
         ty_ctx::TyCtx,
     },
+    json::Json,
     named::Named,
     ty::{FieldTy, Ty},
     value::{FieldValue, Value},
@@ -37,51 +40,466 @@ pub enum Expr {
         name: Arc<str>,
         args: Vec<Self>,
     },
+    /// The first argument that is not null, or null if every argument is
+    Coalesce {
+        args: Vec<Self>,
+    },
+    /// Only valid in `Query::select`, where it is computed over all records of the result or group.
+    ///
+    /// If `arg` contains `ReferencedBy`, the aggregate is valid everywhere and computed
+    /// over the referencing records instead, with `arg` evaluated for each of them.
+    Aggregate {
+        func: AggregateFn,
+        arg: Option<Box<Self>>,
+    },
+    /// The records of `table_name` whose `field_name` references the record `value`,
+    /// found through the index of the field
+    ReferencedBy {
+        value: Box<Self>,
+        table_name: Arc<str>,
+        field_name: Arc<str>,
+    },
+    /// Whether `predicate` is true for any element of `list`, with the element accessed as `var`
+    Any {
+        var: Arc<str>,
+        list: Box<Self>,
+        predicate: Box<Self>,
+    },
 }
 
 impl Expr {
-    pub fn ty(&self, ctx: &TyCtx) -> Option<Ty> {
+    /// The type of the value `eval` returns, or the error that makes the expression invalid.
+    ///
+    /// Nullable operands are checked with their inner type and make the result nullable.
+    pub fn ty(&self, ctx: &TyCtx) -> Result<Ty, EvalErr> {
         match self {
-            Expr::Literal(value) => Some(Ty::Field(value.ty())),
-            Expr::BinaryOp { a, op, b } => {
-                let a = a.ty(ctx)?;
-                let b = b.ty(ctx)?;
+            Expr::Literal(value) => Ok(Ty::Field(value.ty())),
+            Expr::BinaryOp {
+                a: a_expr,
+                op,
+                b: b_expr,
+            } => {
+                let a = a_expr.ty(ctx)?;
+                let b = b_expr.ty(ctx)?;
 
-                match (a, b) {
-                    (Ty::Field(a), Ty::Field(b)) => Some(Ty::Field(op.ty(&a, &b)?)),
+                let fields = match (&a, &b) {
+                    (Ty::Field(a), Ty::Field(b)) => Some((a.clone(), b.clone())),
+                    // Records are equal to their id
+                    _ if matches!(op, BinaryOp::Eq(_)) => a
+                        .clone()
+                        .into_field_or_id()
+                        .zip(b.clone().into_field_or_id()),
                     _ => None,
+                };
+
+                let Some(ty) = fields.as_ref().and_then(|(a, b)| {
+                    let ty = op.ty(a.non_null(), b.non_null())?;
+
+                    Some(nullable_if(ty, a.is_nullable() || b.is_nullable()))
+                }) else {
+                    return Err(EvalErr::InvalidTypeForBinaryOp { op: *op, a, b });
+                };
+
+                // Text literals compared with an enum must name one of its variants
+                if let Some((a, b)) = &fields {
+                    for (ty, other) in [(a.non_null(), b_expr), (b.non_null(), a_expr)] {
+                        if let (FieldTy::Enum { .. }, Expr::Literal(FieldValue::Text(text))) =
+                            (ty, other.as_ref())
+                        {
+                            text_variant(ty, text.clone())?;
+                        }
+                    }
                 }
+
+                Ok(Ty::Field(ty))
             }
             Expr::UnaryOp { op, value } => {
                 let value = value.ty(ctx)?;
 
-                match value {
-                    Ty::Field(value) => Some(Ty::Field(op.ty(&value)?)),
+                let ty = match (op, &value) {
+                    // Null checks are never null themselves
+                    (UnaryOp::IsNull | UnaryOp::IsNotNull, Ty::Field(_)) => Some(FieldTy::Bool),
+                    (op, Ty::Field(ty)) => op
+                        .ty(ty.non_null())
+                        .map(|result| nullable_if(result, ty.is_nullable())),
                     _ => None,
+                };
+
+                match ty {
+                    Some(ty) => Ok(Ty::Field(ty)),
+                    None => Err(EvalErr::InvalidTypeForUnaryOp { op: *op, ty: value }),
                 }
             }
             Expr::FieldAccess { value, field } => {
-                let value = value.ty(ctx)?;
+                let (table_name, nullable) = match value.ty(ctx)? {
+                    Ty::Table(table) => (table.name, false),
+                    // Referenced records are dereferenced, fields of a missing record are null
+                    Ty::Field(ty) => match ty.non_null() {
+                        FieldTy::RecordId { table_name } => (table_name.clone(), ty.is_nullable()),
+                        // Keys may be missing from JSON objects, so their values are nullable
+                        FieldTy::Json => {
+                            return Ok(Ty::Field(FieldTy::Nullable(Box::new(FieldTy::Json))));
+                        }
+                        _ => {
+                            return Err(EvalErr::MissmatchedTypes {
+                                found: Ty::Field(ty),
+                                expected: None,
+                            });
+                        }
+                    },
+                    ty => {
+                        return Err(EvalErr::MissmatchedTypes {
+                            found: ty,
+                            expected: None,
+                        });
+                    }
+                };
 
-                if let Ty::Table(table) = value {
-                    let field = table.value.field(field.as_ref())?;
+                let table = known_table(&ctx.tables, &table_name)?;
+                let field = table_field(&table_name, table, field)?;
 
-                    Some(Ty::Field(field.ty.clone()))
-                } else {
-                    None
-                }
+                Ok(Ty::Field(nullable_if(field.ty.clone(), nullable)))
             }
-            Expr::TableAccess { name } => {
-                let table = ctx.tables.get(name.as_ref())?;
-
-                Some(Ty::Table(Named {
+            // Variables shadow tables of the same name
+            Expr::TableAccess { name } if ctx.vars.contains_key(name) => {
+                Ok(Ty::Field(ctx.vars[name].clone()))
+            }
+            Expr::TableAccess { name } => match ctx.tables.get(name) {
+                Some(table) if ctx.scope.contains(name) => Ok(Ty::Table(Named {
                     name: name.clone(),
                     value: table.clone(),
-                }))
+                })),
+                _ => Err(EvalErr::UnknownTable {
+                    name: name.clone(),
+                    did_you_mean_hint: DidYouMeanHint::for_table(
+                        name,
+                        ctx.tables
+                            .iter()
+                            .filter(|(table_name, _)| ctx.scope.contains(*table_name))
+                            .map(|(table_name, table)| (table_name, table.as_ref())),
+                    ),
+                }),
+            },
+            Expr::FnCall { name, args } => {
+                let args = args
+                    .iter()
+                    .map(|arg| arg.ty(ctx))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                known_function(name, &args)?.ty(&args).map(Ty::Field)
+            }
+            Expr::Coalesce { args } => {
+                let mut result: Option<FieldTy> = None;
+
+                for arg in args {
+                    let found = arg.ty(ctx)?;
+
+                    let Ty::Field(ty) = &found else {
+                        return Err(EvalErr::MissmatchedTypes {
+                            found,
+                            expected: result.map(Ty::Field),
+                        });
+                    };
+
+                    // Every argument has the type of the first, the result is only null if all are
+                    result = match result {
+                        None => Some(ty.clone()),
+                        Some(result) if result.non_null() == ty.non_null() => Some(nullable_if(
+                            ty.non_null().clone(),
+                            result.is_nullable() && ty.is_nullable(),
+                        )),
+                        Some(result) => {
+                            return Err(EvalErr::MissmatchedTypes {
+                                found,
+                                expected: Some(Ty::Field(result)),
+                            });
+                        }
+                    };
+                }
+
+                result
+                    .map(Ty::Field)
+                    .ok_or(EvalErr::InvalidFunctionArgCount {
+                        name: "coalesce".into(),
+                        found: 0,
+                        expected: 1,
+                    })
+            }
+            Expr::Aggregate { func, arg } => {
+                let arg = match arg.as_deref() {
+                    Some(arg) => {
+                        let ty = match arg.relation() {
+                            Some(relation) => {
+                                let table = match relation.ty(ctx)? {
+                                    Ty::Records(table) => table,
+                                    found => {
+                                        return Err(EvalErr::MissmatchedTypes {
+                                            found,
+                                            expected: None,
+                                        });
+                                    }
+                                };
+
+                                // The argument is typed for a single referencing record
+                                let record_id = Expr::Literal(FieldValue::RecordId {
+                                    id: Ulid::nil(),
+                                    table_name: table.name,
+                                });
+
+                                arg.replace(relation, &record_id).ty(ctx)?
+                            }
+                            None => arg.ty(ctx)?,
+                        };
+
+                        match ty.clone().into_field_or_id() {
+                            Some(field_ty) => Some((field_ty, ty)),
+                            None => {
+                                return Err(EvalErr::MissmatchedTypes {
+                                    found: ty,
+                                    expected: None,
+                                });
+                            }
+                        }
+                    }
+                    None => None,
+                };
+
+                match (func.ty(arg.as_ref().map(|(ty, _)| ty)), arg) {
+                    (Some(ty), _) => Ok(Ty::Field(ty)),
+                    (None, Some((_, found))) => Err(EvalErr::MissmatchedTypes {
+                        found,
+                        expected: None,
+                    }),
+                    (None, None) => Err(EvalErr::InvalidFunctionArgCount {
+                        name: func.name().into(),
+                        found: 0,
+                        expected: 1,
+                    }),
+                }
             }
-            Expr::FnCall { name, args } => match name.as_ref() {
-                "now" if args.is_empty() => Some(Ty::Field(FieldTy::Timestamp)),
-                _ => None,
+            Expr::ReferencedBy {
+                value,
+                table_name,
+                field_name,
+            } => {
+                let table = known_table(&ctx.tables, table_name)?;
+                let field = table_field(table_name, table, field_name)?;
+
+                // Lists of references reference every record in them
+                let FieldTy::RecordId {
+                    table_name: referenced_table,
+                } = field.ty.indexed().non_null()
+                else {
+                    return Err(EvalErr::NotAReference {
+                        table_name: table_name.clone(),
+                        field_name: field_name.clone(),
+                    });
+                };
+
+                let expected = FieldTy::RecordId {
+                    table_name: referenced_table.clone(),
+                };
+
+                let found = value.ty(ctx)?;
+
+                match found.clone().into_field_or_id() {
+                    Some(ty) if ty.non_null() == &expected => {
+                        Ok(Ty::Records(Named::new(table_name.clone(), table.clone())))
+                    }
+                    _ => Err(EvalErr::MissmatchedTypes {
+                        found,
+                        expected: Some(Ty::Field(expected)),
+                    }),
+                }
+            }
+            Expr::Any {
+                var,
+                list,
+                predicate,
+            } => {
+                let found = list.ty(ctx)?;
+
+                let Some((list_ty, element)) = list_element_ty(&found) else {
+                    return Err(EvalErr::MissmatchedTypes {
+                        found,
+                        expected: None,
+                    });
+                };
+
+                let mut vars = ctx.vars.clone();
+                vars.insert(var.clone(), element.clone());
+
+                let predicate_ctx = TyCtx {
+                    tables: ctx.tables.clone(),
+                    scope: ctx.scope.clone(),
+                    vars,
+                };
+
+                // A null list or a null predicate result without a match make the result null
+                match predicate.ty(&predicate_ctx)? {
+                    Ty::Field(ty) if ty.non_null() == &FieldTy::Bool => Ok(Ty::Field(nullable_if(
+                        FieldTy::Bool,
+                        list_ty.is_nullable() || ty.is_nullable(),
+                    ))),
+                    found => Err(EvalErr::MissmatchedTypes {
+                        found,
+                        expected: Some(Ty::Field(FieldTy::Bool)),
+                    }),
+                }
+            }
+        }
+    }
+
+    /// Whether the expression contains an aggregate function
+    pub fn is_aggregate(&self) -> bool {
+        self.aggregate().is_some()
+    }
+
+    /// The first aggregate function that is computed over the records of a query
+    pub fn aggregate(&self) -> Option<AggregateFn> {
+        match self {
+            Expr::Literal(_) | Expr::TableAccess { .. } => None,
+            Expr::BinaryOp { a, b, .. } => a.aggregate().or_else(|| b.aggregate()),
+            Expr::UnaryOp { value, .. }
+            | Expr::FieldAccess { value, .. }
+            | Expr::ReferencedBy { value, .. } => value.aggregate(),
+            Expr::FnCall { args, .. } | Expr::Coalesce { args } => {
+                args.iter().find_map(Expr::aggregate)
+            }
+            Expr::Any {
+                list, predicate, ..
+            } => list.aggregate().or_else(|| predicate.aggregate()),
+            // Aggregates over referencing records are computed for every record
+            Expr::Aggregate { func, arg } => match arg.as_deref() {
+                Some(arg) if arg.relation().is_some() => None,
+                _ => Some(*func),
+            },
+        }
+    }
+
+    /// The first `ReferencedBy` expression, outside of nested aggregate functions
+    pub fn relation(&self) -> Option<&Expr> {
+        match self {
+            Expr::Literal(_) | Expr::TableAccess { .. } | Expr::Aggregate { .. } => None,
+            Expr::BinaryOp { a, b, .. } => a.relation().or_else(|| b.relation()),
+            Expr::UnaryOp { value, .. } | Expr::FieldAccess { value, .. } => value.relation(),
+            Expr::FnCall { args, .. } | Expr::Coalesce { args } => {
+                args.iter().find_map(Expr::relation)
+            }
+            Expr::Any {
+                list, predicate, ..
+            } => list.relation().or_else(|| predicate.relation()),
+            Expr::ReferencedBy { .. } => Some(self),
+        }
+    }
+
+    /// Returns a copy with the subexpression `target`, compared by address, replaced by `with`
+    pub fn replace(&self, target: &Expr, with: &Expr) -> Expr {
+        if std::ptr::eq(self, target) {
+            return with.clone();
+        }
+
+        match self {
+            Expr::Literal(_) | Expr::TableAccess { .. } => self.clone(),
+            Expr::BinaryOp { a, op, b } => Expr::BinaryOp {
+                a: Box::new(a.replace(target, with)),
+                op: *op,
+                b: Box::new(b.replace(target, with)),
+            },
+            Expr::UnaryOp { op, value } => Expr::UnaryOp {
+                op: *op,
+                value: Box::new(value.replace(target, with)),
+            },
+            Expr::FieldAccess { value, field } => Expr::FieldAccess {
+                value: Box::new(value.replace(target, with)),
+                field: field.clone(),
+            },
+            Expr::FnCall { name, args } => Expr::FnCall {
+                name: name.clone(),
+                args: args.iter().map(|arg| arg.replace(target, with)).collect(),
+            },
+            Expr::Coalesce { args } => Expr::Coalesce {
+                args: args.iter().map(|arg| arg.replace(target, with)).collect(),
+            },
+            Expr::Aggregate { func, arg } => Expr::Aggregate {
+                func: *func,
+                arg: arg.as_ref().map(|arg| Box::new(arg.replace(target, with))),
+            },
+            Expr::ReferencedBy {
+                value,
+                table_name,
+                field_name,
+            } => Expr::ReferencedBy {
+                value: Box::new(value.replace(target, with)),
+                table_name: table_name.clone(),
+                field_name: field_name.clone(),
+            },
+            Expr::Any {
+                var,
+                list,
+                predicate,
+            } => Expr::Any {
+                var: var.clone(),
+                list: Box::new(list.replace(target, with)),
+                predicate: Box::new(predicate.replace(target, with)),
+            },
+        }
+    }
+
+    /// Returns a copy with every access of the variable `var` replaced by `value`
+    pub fn bind(&self, var: &str, value: &FieldValue) -> Expr {
+        match self {
+            Expr::TableAccess { name } if name.as_ref() == var => Expr::Literal(value.clone()),
+            Expr::Literal(_) | Expr::TableAccess { .. } => self.clone(),
+            Expr::BinaryOp { a, op, b } => Expr::BinaryOp {
+                a: Box::new(a.bind(var, value)),
+                op: *op,
+                b: Box::new(b.bind(var, value)),
+            },
+            Expr::UnaryOp { op, value: operand } => Expr::UnaryOp {
+                op: *op,
+                value: Box::new(operand.bind(var, value)),
+            },
+            Expr::FieldAccess {
+                value: operand,
+                field,
+            } => Expr::FieldAccess {
+                value: Box::new(operand.bind(var, value)),
+                field: field.clone(),
+            },
+            Expr::FnCall { name, args } => Expr::FnCall {
+                name: name.clone(),
+                args: args.iter().map(|arg| arg.bind(var, value)).collect(),
+            },
+            Expr::Coalesce { args } => Expr::Coalesce {
+                args: args.iter().map(|arg| arg.bind(var, value)).collect(),
+            },
+            Expr::Aggregate { func, arg } => Expr::Aggregate {
+                func: *func,
+                arg: arg.as_ref().map(|arg| Box::new(arg.bind(var, value))),
+            },
+            Expr::ReferencedBy {
+                value: operand,
+                table_name,
+                field_name,
+            } => Expr::ReferencedBy {
+                value: Box::new(operand.bind(var, value)),
+                table_name: table_name.clone(),
+                field_name: field_name.clone(),
+            },
+            // A nested `any` with the same variable shadows it
+            Expr::Any {
+                var: inner_var,
+                list,
+                predicate,
+            } => Expr::Any {
+                var: inner_var.clone(),
+                list: Box::new(list.bind(var, value)),
+                predicate: Box::new(if inner_var.as_ref() == var {
+                    predicate.as_ref().clone()
+                } else {
+                    predicate.bind(var, value)
+                }),
             },
         }
     }
@@ -105,20 +523,49 @@ impl Expr {
 
                 match value {
                     Value::Record { table, record } => {
-                        let field =
-                            table
-                                .value
-                                .field(field)
-                                .ok_or_else(|| EvalErr::UnknownField {
-                                    name: field.clone(),
-                                    table_name: table.name.clone(),
-                                })?;
+                        let field = table_field(&table.name, &table.value, field)?;
 
                         record
                             .get_field(field)
                             .map(Into::into)
                             .ok_or(EvalErr::Bytepack)
                     }
+                    // Referenced records are loaded, so `a.b.c` reads `c` of the record `a.b` points to
+                    Value::Field(FieldValue::RecordId { id, table_name }) => {
+                        let Some(source) = &ctx.source else {
+                            return Err(EvalErr::MissmatchedTypes {
+                                found: Ty::Field(FieldTy::RecordId { table_name }),
+                                expected: None,
+                            });
+                        };
+
+                        let table = known_table(&ctx.tables, &table_name)?;
+                        let field = table_field(&table_name, table, field)?;
+
+                        let Some(record) = source.record(&table_name, id) else {
+                            return Err(EvalErr::RecordNotFound { table_name, id });
+                        };
+
+                        record
+                            .get_field(field)
+                            .map(Into::into)
+                            .ok_or(EvalErr::Bytepack)
+                    }
+                    // Fields of a missing record are null
+                    Value::Field(FieldValue::Null(FieldTy::RecordId { table_name })) => {
+                        let table = known_table(&ctx.tables, &table_name)?;
+                        let field = table_field(&table_name, table, field)?;
+
+                        Ok(FieldValue::Null(field.ty.non_null().clone()).into())
+                    }
+                    // Missing keys and JSON nulls are both null, so `a.b.c` is null if `a.b` is
+                    Value::Field(FieldValue::Json(json)) => match json.get(field) {
+                        None | Some(Json::Null) => Ok(FieldValue::Null(FieldTy::Json).into()),
+                        Some(value) => Ok(FieldValue::Json(value.clone()).into()),
+                    },
+                    Value::Field(FieldValue::Null(FieldTy::Json)) => {
+                        Ok(FieldValue::Null(FieldTy::Json).into())
+                    }
                     v => Err(EvalErr::MissmatchedTypes {
                         found: v.ty(),
                         expected: None,
@@ -128,29 +575,15 @@ impl Expr {
             Expr::TableAccess { name } => {
                 let (Some(record), Some(table)) = (ctx.records.get(name), ctx.tables.get(name))
                 else {
-                    let table_with_field_name = ctx
-                        .tables
-                        .iter()
-                        .filter_map(|(table_name, table)| {
-                            if ctx.records.contains_key(table_name) && table.has_field(name) {
-                                Some(table_name.clone())
-                            } else {
-                                None
-                            }
-                        })
-                        .next();
-
-                    let hint = match table_with_field_name {
-                        Some(table_name) => DidYouMeanHint::TableWithField {
-                            table_name,
-                            field_name: name.clone(),
-                        },
-                        None => DidYouMeanHint::None,
-                    };
-
                     return Err(EvalErr::UnknownTable {
                         name: name.clone(),
-                        did_you_mean_hint: hint,
+                        did_you_mean_hint: DidYouMeanHint::for_table(
+                            name,
+                            ctx.tables
+                                .iter()
+                                .filter(|(table_name, _)| ctx.records.contains_key(*table_name))
+                                .map(|(table_name, table)| (table_name, table.as_ref())),
+                        ),
                     });
                 };
 
@@ -162,29 +595,309 @@ impl Expr {
                     record: record.clone(),
                 })
             }
-            Expr::FnCall { name, args } => match name.as_ref() {
-                "now" if args.is_empty() => Ok(FieldValue::Timestamp(ctx.now).into()),
-                "str_len" => {
-                    if args.len() != 1 {
-                        return Err(EvalErr::InvalidFunctionArgCount {
-                            name: name.clone(),
-                            found: args.len(),
-                            expected: 1,
+            Expr::FnCall { name, args } => {
+                let args = args
+                    .iter()
+                    .map(|arg| arg.eval(ctx))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let tys = args.iter().map(Value::ty).collect::<Vec<_>>();
+
+                known_function(name, &tys)?.call(ctx, args).map(Into::into)
+            }
+            Expr::Coalesce { args } => {
+                let mut result = None;
+
+                for arg in args {
+                    let value = arg.eval(ctx)?;
+
+                    let Value::Field(value) = value else {
+                        return Err(EvalErr::MissmatchedTypes {
+                            found: value.ty(),
+                            expected: None,
                         });
+                    };
+
+                    if !value.is_null() {
+                        return Ok(Value::Field(value));
                     }
-                    let arg = args[0].eval(ctx)?;
 
-                    let Value::Field(FieldValue::Text(input)) = arg else {
-                        return Err(EvalErr::MissmatchedTypes {
-                            found: arg.ty(),
-                            expected: Some(FieldTy::Text.into()),
+                    result = Some(value);
+                }
+
+                result
+                    .map(Value::Field)
+                    .ok_or(EvalErr::InvalidFunctionArgCount {
+                        name: "coalesce".into(),
+                        found: 0,
+                        expected: 1,
+                    })
+            }
+            Expr::Aggregate { func, arg } => {
+                let Some((arg, relation)) =
+                    arg.as_deref().and_then(|arg| Some((arg, arg.relation()?)))
+                else {
+                    return Err(EvalErr::AggregateOutsideSelect { func: *func });
+                };
+
+                let relation_value = relation.eval(ctx)?;
+
+                let Value::Records { table, records } = relation_value else {
+                    return Err(EvalErr::MissmatchedTypes {
+                        found: relation_value.ty(),
+                        expected: None,
+                    });
+                };
+
+                // Every record is passed by id, so the argument can read its fields
+                let values = records
+                    .iter()
+                    .map(|record| {
+                        let record_id = Expr::Literal(FieldValue::RecordId {
+                            id: record.id(),
+                            table_name: table.name.clone(),
                         });
+
+                        let value = arg.replace(relation, &record_id).eval(ctx)?;
+                        let found = value.ty();
+
+                        value.into_field_or_id().ok_or(EvalErr::MissmatchedTypes {
+                            found,
+                            expected: None,
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                // The type is only needed for the result without any values
+                let ty = if values.is_empty() {
+                    let ty_ctx = TyCtx {
+                        tables: ctx.tables.clone(),
+                        scope: ctx.records.keys().cloned().collect(),
+                        ..Default::default()
                     };
 
-                    return Ok(FieldValue::Int(input.len() as _).into());
+                    match self.ty(&ty_ctx) {
+                        Ok(Ty::Field(ty)) => Some(ty),
+                        _ => None,
+                    }
+                } else {
+                    None
+                };
+
+                func.eval(values, ty.as_ref()).map(Into::into)
+            }
+            Expr::ReferencedBy {
+                value,
+                table_name,
+                field_name,
+            } => {
+                let table = known_table(&ctx.tables, table_name)?;
+                let field = table_field(table_name, table, field_name)?;
+
+                let FieldTy::RecordId {
+                    table_name: referenced_table,
+                } = field.ty.indexed().non_null()
+                else {
+                    return Err(EvalErr::NotAReference {
+                        table_name: table_name.clone(),
+                        field_name: field_name.clone(),
+                    });
+                };
+
+                let value = value.eval(ctx)?;
+                let found = value.ty();
+
+                let records = match value.into_field_or_id() {
+                    Some(FieldValue::RecordId {
+                        id,
+                        table_name: value_table,
+                    }) if &value_table == referenced_table => ctx
+                        .source
+                        .as_ref()
+                        .and_then(|source| {
+                            source.referencing(table_name, field_name, referenced_table, id)
+                        })
+                        .ok_or_else(|| EvalErr::RecordsUnavailable {
+                            table_name: table_name.clone(),
+                        })?,
+                    // A missing record is not referenced by anything
+                    Some(FieldValue::Null(FieldTy::RecordId {
+                        table_name: value_table,
+                    })) if &value_table == referenced_table => Vec::new(),
+                    _ => {
+                        return Err(EvalErr::MissmatchedTypes {
+                            found,
+                            expected: Some(Ty::Field(FieldTy::RecordId {
+                                table_name: referenced_table.clone(),
+                            })),
+                        });
+                    }
+                };
+
+                Ok(Value::Records {
+                    table: Named::new(table_name.clone(), table.clone()),
+                    records,
+                })
+            }
+            Expr::Any {
+                var,
+                list,
+                predicate,
+            } => {
+                let value = list.eval(ctx)?;
+
+                let values = match value {
+                    Value::Field(FieldValue::List { values, .. }) => values,
+                    Value::Field(FieldValue::Null(FieldTy::List(_))) => {
+                        return Ok(Value::Field(FieldValue::Null(FieldTy::Bool)));
+                    }
+                    value => {
+                        return Err(EvalErr::MissmatchedTypes {
+                            found: value.ty(),
+                            expected: None,
+                        });
+                    }
+                };
+
+                // Like `||`, a null result only decides the outcome if no element matches
+                let mut saw_null = false;
+
+                // The variable is replaced by each element, like records passed to aggregates by id
+                for element in &values {
+                    match predicate.bind(var, element).eval(ctx)? {
+                        Value::Field(FieldValue::Bool(true)) => {
+                            return Ok(Value::Field(FieldValue::Bool(true)));
+                        }
+                        Value::Field(FieldValue::Bool(false)) => {}
+                        Value::Field(FieldValue::Null(FieldTy::Bool)) => saw_null = true,
+                        value => {
+                            return Err(EvalErr::MissmatchedTypes {
+                                found: value.ty(),
+                                expected: Some(Ty::Field(FieldTy::Bool)),
+                            });
+                        }
+                    }
                 }
-                _ => Err(EvalErr::UnknownFunction { name: name.clone() }),
+
+                if saw_null {
+                    Ok(Value::Field(FieldValue::Null(FieldTy::Bool)))
+                } else {
+                    Ok(Value::Field(FieldValue::Bool(false)))
+                }
+            }
+        }
+    }
+}
+
+fn known_table<'a>(
+    tables: &'a HashMap<Arc<str>, Arc<TableData>>,
+    table_name: &Arc<str>,
+) -> Result<&'a Arc<TableData>, EvalErr> {
+    tables.get(table_name).ok_or_else(|| EvalErr::UnknownTable {
+        name: table_name.clone(),
+        did_you_mean_hint: DidYouMeanHint::None,
+    })
+}
+
+fn known_function(name: &Arc<str>, args: &[Ty]) -> Result<&'static Function, EvalErr> {
+    Function::get(name, args).ok_or_else(|| EvalErr::UnknownFunction { name: name.clone() })
+}
+
+/// The type of a list and its element type, nullable elements stay nullable
+fn list_element_ty(ty: &Ty) -> Option<(&FieldTy, &FieldTy)> {
+    match ty {
+        Ty::Field(ty) => Some((ty, ty.element()?)),
+        _ => None,
+    }
+}
+
+/// `ty`, made nullable if `nullable` is set
+pub(super) fn nullable_if(ty: FieldTy, nullable: bool) -> FieldTy {
+    if nullable && !ty.is_nullable() {
+        FieldTy::Nullable(Box::new(ty))
+    } else {
+        ty
+    }
+}
+
+fn table_field<'a>(
+    table_name: &Arc<str>,
+    table: &'a TableData,
+    field: &Arc<str>,
+) -> Result<&'a TableFieldData, EvalErr> {
+    table.field(field).ok_or_else(|| EvalErr::UnknownField {
+        name: field.clone(),
+        table_name: table_name.clone(),
+    })
+}
+
+/// Formats the expression in the syntax of the query language.
+impl Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expr::Literal(value) => write!(f, "{value}"),
+            Expr::BinaryOp { a, op, b } => {
+                write_operand(f, a)?;
+                write!(f, " {op} ")?;
+                write_operand(f, b)
+            }
+            Expr::UnaryOp {
+                op: op @ (UnaryOp::IsNull | UnaryOp::IsNotNull),
+                value,
+            } => {
+                write_operand(f, value)?;
+                write!(f, " {op}")
+            }
+            Expr::UnaryOp { op, value } => {
+                write!(f, "{op}")?;
+                write_operand(f, value)
+            }
+            Expr::FieldAccess { value, field } => {
+                write_operand(f, value)?;
+                write!(f, ".{field}")
+            }
+            Expr::TableAccess { name } => write!(f, "{name}"),
+            Expr::FnCall { name, args } => write_call(f, name, args),
+            Expr::Coalesce { args } => write_call(f, "coalesce", args),
+            Expr::Aggregate { func, arg } => match arg {
+                Some(arg) => write!(f, "{func}({arg})"),
+                None => write!(f, "{func}()"),
             },
+            Expr::ReferencedBy {
+                value,
+                table_name,
+                field_name,
+            } => {
+                write_operand(f, value)?;
+                write!(f, ".referenced_by({table_name}.{field_name})")
+            }
+            Expr::Any {
+                var,
+                list,
+                predicate,
+            } => write!(f, "any({var} in {list}, {predicate})"),
+        }
+    }
+}
+
+fn write_call(f: &mut std::fmt::Formatter<'_>, name: &str, args: &[Expr]) -> std::fmt::Result {
+    write!(f, "{name}(")?;
+
+    for (idx, arg) in args.iter().enumerate() {
+        if idx > 0 {
+            f.write_str(", ")?;
         }
+
+        write!(f, "{arg}")?;
+    }
+
+    f.write_str(")")
+}
+
+/// Operators are wrapped in parentheses, so the output does not depend on operator precedence.
+fn write_operand(f: &mut std::fmt::Formatter<'_>, expr: &Expr) -> std::fmt::Result {
+    match expr {
+        Expr::BinaryOp { .. } | Expr::UnaryOp { .. } => write!(f, "({expr})"),
+        expr => write!(f, "{expr}"),
     }
 }
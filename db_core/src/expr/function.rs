@@ -0,0 +1,591 @@
+use chrono::{DateTime, Datelike, Days, NaiveTime, TimeDelta, Timelike, Utc};
+
+use crate::{
+    expr::{BinaryOp, EqOp, EvalCtx, EvalErr, expr::nullable_if},
+    json::Json,
+    ty::{FieldTy, Ty},
+    value::{FieldValue, Value},
+};
+
+/// A built-in function, called with `Expr::FnCall`.
+///
+/// Functions can share a name if they take different arguments, see `Function::get`.
+#[derive(Debug)]
+pub struct Function {
+    pub name: &'static str,
+    /// The types of the arguments, a nullable argument makes the result nullable
+    pub args: &'static [ArgTy],
+    pub ret: FieldTy,
+    /// Computes the result from non-null arguments of the types in `args`
+    pub eval: fn(&EvalCtx, &[FieldValue]) -> Result<FieldValue, EvalErr>,
+}
+
+#[derive(Debug)]
+pub enum ArgTy {
+    Is(FieldTy),
+    /// Any field type, the function checks the value itself
+    Any,
+    /// A list of any element type
+    List,
+    /// A value that can be compared with the elements of the list passed as first argument
+    Element,
+}
+
+/// All built-in functions
+pub static FUNCTIONS: &[Function] = &[
+    Function {
+        name: "now",
+        args: &[],
+        ret: FieldTy::Timestamp,
+        eval: |ctx, _| Ok(FieldValue::Timestamp(ctx.now)),
+    },
+    Function {
+        name: "str_len",
+        args: &[ArgTy::Is(FieldTy::Text)],
+        ret: FieldTy::IntI32,
+        eval: |_, args| match args {
+            [FieldValue::Text(input)] => Ok(FieldValue::Int(input.chars().count() as _)),
+            args => Err(unexpected_args(args)),
+        },
+    },
+    Function {
+        name: "lower",
+        args: &[ArgTy::Is(FieldTy::Text)],
+        ret: FieldTy::Text,
+        eval: |_, args| match args {
+            [FieldValue::Text(input)] => Ok(FieldValue::Text(input.to_lowercase())),
+            args => Err(unexpected_args(args)),
+        },
+    },
+    Function {
+        name: "upper",
+        args: &[ArgTy::Is(FieldTy::Text)],
+        ret: FieldTy::Text,
+        eval: |_, args| match args {
+            [FieldValue::Text(input)] => Ok(FieldValue::Text(input.to_uppercase())),
+            args => Err(unexpected_args(args)),
+        },
+    },
+    Function {
+        name: "trim",
+        args: &[ArgTy::Is(FieldTy::Text)],
+        ret: FieldTy::Text,
+        eval: |_, args| match args {
+            [FieldValue::Text(input)] => Ok(FieldValue::Text(input.trim().into())),
+            args => Err(unexpected_args(args)),
+        },
+    },
+    Function {
+        name: "contains",
+        args: &[ArgTy::Is(FieldTy::Text), ArgTy::Is(FieldTy::Text)],
+        ret: FieldTy::Bool,
+        eval: |_, args| match args {
+            [FieldValue::Text(input), FieldValue::Text(part)] => {
+                Ok(FieldValue::Bool(input.contains(part.as_str())))
+            }
+            args => Err(unexpected_args(args)),
+        },
+    },
+    // `contains(list, value)`: whether any element is equal to `value`
+    Function {
+        name: "contains",
+        args: &[ArgTy::List, ArgTy::Element],
+        ret: FieldTy::Bool,
+        eval: |_, args| match args {
+            [FieldValue::List { values, .. }, value] => {
+                for element in values {
+                    let equal = BinaryOp::Eq(EqOp::Eq)
+                        .eval(element.clone().into(), value.clone().into())?;
+
+                    if equal == FieldValue::Bool(true) {
+                        return Ok(FieldValue::Bool(true));
+                    }
+                }
+
+                Ok(FieldValue::Bool(false))
+            }
+            args => Err(unexpected_args(args)),
+        },
+    },
+    Function {
+        name: "len",
+        args: &[ArgTy::List],
+        ret: FieldTy::IntI32,
+        eval: |_, args| match args {
+            [FieldValue::List { values, .. }] => Ok(FieldValue::Int(values.len() as _)),
+            args => Err(unexpected_args(args)),
+        },
+    },
+    Function {
+        name: "starts_with",
+        args: &[ArgTy::Is(FieldTy::Text), ArgTy::Is(FieldTy::Text)],
+        ret: FieldTy::Bool,
+        eval: |_, args| match args {
+            [FieldValue::Text(input), FieldValue::Text(prefix)] => {
+                Ok(FieldValue::Bool(input.starts_with(prefix.as_str())))
+            }
+            args => Err(unexpected_args(args)),
+        },
+    },
+    Function {
+        name: "ends_with",
+        args: &[ArgTy::Is(FieldTy::Text), ArgTy::Is(FieldTy::Text)],
+        ret: FieldTy::Bool,
+        eval: |_, args| match args {
+            [FieldValue::Text(input), FieldValue::Text(suffix)] => {
+                Ok(FieldValue::Bool(input.ends_with(suffix.as_str())))
+            }
+            args => Err(unexpected_args(args)),
+        },
+    },
+    // `substr(text, start, count)`: `count` characters from the 1-based position `start`
+    Function {
+        name: "substr",
+        args: &[
+            ArgTy::Is(FieldTy::Text),
+            ArgTy::Is(FieldTy::IntI32),
+            ArgTy::Is(FieldTy::IntI32),
+        ],
+        ret: FieldTy::Text,
+        eval: |_, args| match args {
+            [
+                FieldValue::Text(input),
+                FieldValue::Int(start),
+                FieldValue::Int(count),
+            ] => Ok(FieldValue::Text(
+                input
+                    .chars()
+                    .skip(start.saturating_sub(1).max(0) as usize)
+                    .take((*count).max(0) as usize)
+                    .collect(),
+            )),
+            args => Err(unexpected_args(args)),
+        },
+    },
+    // `replace(text, from, to)`: every occurrence of `from` replaced with `to`
+    Function {
+        name: "replace",
+        args: &[
+            ArgTy::Is(FieldTy::Text),
+            ArgTy::Is(FieldTy::Text),
+            ArgTy::Is(FieldTy::Text),
+        ],
+        ret: FieldTy::Text,
+        eval: |_, args| match args {
+            [
+                FieldValue::Text(input),
+                FieldValue::Text(from),
+                FieldValue::Text(to),
+            ] => Ok(FieldValue::Text(if from.is_empty() {
+                input.clone()
+            } else {
+                input.replace(from.as_str(), to)
+            })),
+            args => Err(unexpected_args(args)),
+        },
+    },
+    Function {
+        name: "concat",
+        args: &[ArgTy::Is(FieldTy::Text), ArgTy::Is(FieldTy::Text)],
+        ret: FieldTy::Text,
+        eval: |_, args| match args {
+            [FieldValue::Text(a), FieldValue::Text(b)] => Ok(FieldValue::Text(format!("{a}{b}"))),
+            args => Err(unexpected_args(args)),
+        },
+    },
+    // `split_part(text, delimiter, n)`: the 1-based `n`th part of `text` split at `delimiter`,
+    // empty if there is no such part
+    Function {
+        name: "split_part",
+        args: &[
+            ArgTy::Is(FieldTy::Text),
+            ArgTy::Is(FieldTy::Text),
+            ArgTy::Is(FieldTy::IntI32),
+        ],
+        ret: FieldTy::Text,
+        eval: |_, args| match args {
+            [
+                FieldValue::Text(input),
+                FieldValue::Text(delimiter),
+                FieldValue::Int(n),
+            ] => {
+                let part = match (*n).checked_sub(1).and_then(|n| usize::try_from(n).ok()) {
+                    Some(_) if delimiter.is_empty() => Some(input.as_str()).filter(|_| *n == 1),
+                    Some(n) => input.split(delimiter.as_str()).nth(n),
+                    None => None,
+                };
+
+                Ok(FieldValue::Text(part.unwrap_or_default().into()))
+            }
+            args => Err(unexpected_args(args)),
+        },
+    },
+    // `like(text, pattern)`: matches the whole text case-insensitively,
+    // `%` in the pattern matches any number of characters and `_` a single character
+    Function {
+        name: "like",
+        args: &[ArgTy::Is(FieldTy::Text), ArgTy::Is(FieldTy::Text)],
+        ret: FieldTy::Bool,
+        eval: |_, args| match args {
+            [FieldValue::Text(input), FieldValue::Text(pattern)] => {
+                Ok(FieldValue::Bool(like(input, pattern)))
+            }
+            args => Err(unexpected_args(args)),
+        },
+    },
+    // `date_trunc(unit, timestamp)`: the start of the year, month, week, day, hour or minute
+    // containing `timestamp`
+    Function {
+        name: "date_trunc",
+        args: &[ArgTy::Is(FieldTy::Text), ArgTy::Is(FieldTy::Timestamp)],
+        ret: FieldTy::Timestamp,
+        eval: |_, args| match args {
+            [FieldValue::Text(unit), FieldValue::Timestamp(value)] => {
+                date_trunc(unit, value).map(FieldValue::Timestamp)
+            }
+            args => Err(unexpected_args(args)),
+        },
+    },
+    Function {
+        name: "year",
+        args: &[ArgTy::Is(FieldTy::Timestamp)],
+        ret: FieldTy::IntI32,
+        eval: |_, args| match args {
+            [FieldValue::Timestamp(value)] => Ok(FieldValue::Int(value.year())),
+            args => Err(unexpected_args(args)),
+        },
+    },
+    Function {
+        name: "month",
+        args: &[ArgTy::Is(FieldTy::Timestamp)],
+        ret: FieldTy::IntI32,
+        eval: |_, args| match args {
+            [FieldValue::Timestamp(value)] => Ok(FieldValue::Int(value.month() as _)),
+            args => Err(unexpected_args(args)),
+        },
+    },
+    Function {
+        name: "day",
+        args: &[ArgTy::Is(FieldTy::Timestamp)],
+        ret: FieldTy::IntI32,
+        eval: |_, args| match args {
+            [FieldValue::Timestamp(value)] => Ok(FieldValue::Int(value.day() as _)),
+            args => Err(unexpected_args(args)),
+        },
+    },
+    // `weekday(timestamp)`: 1 for monday to 7 for sunday
+    Function {
+        name: "weekday",
+        args: &[ArgTy::Is(FieldTy::Timestamp)],
+        ret: FieldTy::IntI32,
+        eval: |_, args| match args {
+            [FieldValue::Timestamp(value)] => {
+                Ok(FieldValue::Int(value.weekday().number_from_monday() as _))
+            }
+            args => Err(unexpected_args(args)),
+        },
+    },
+    // `hours(duration)`: the number of whole hours
+    Function {
+        name: "hours",
+        args: &[ArgTy::Is(FieldTy::Duration)],
+        ret: FieldTy::IntI32,
+        eval: |_, args| whole_units(args, TimeDelta::num_hours),
+    },
+    // `minutes(duration)`: the number of whole minutes
+    Function {
+        name: "minutes",
+        args: &[ArgTy::Is(FieldTy::Duration)],
+        ret: FieldTy::IntI32,
+        eval: |_, args| whole_units(args, TimeDelta::num_minutes),
+    },
+    // Explicit conversions, see `FieldValue::cast`
+    Function {
+        name: "to_i32",
+        args: &[ArgTy::Any],
+        ret: FieldTy::IntI32,
+        eval: |_, args| cast(args, FieldTy::IntI32),
+    },
+    Function {
+        name: "to_i64",
+        args: &[ArgTy::Any],
+        ret: FieldTy::IntI64,
+        eval: |_, args| cast(args, FieldTy::IntI64),
+    },
+    Function {
+        name: "to_f64",
+        args: &[ArgTy::Any],
+        ret: FieldTy::F64,
+        eval: |_, args| cast(args, FieldTy::F64),
+    },
+    Function {
+        name: "to_decimal",
+        args: &[ArgTy::Any],
+        ret: FieldTy::Decimal,
+        eval: |_, args| cast(args, FieldTy::Decimal),
+    },
+    Function {
+        name: "to_text",
+        args: &[ArgTy::Any],
+        ret: FieldTy::Text,
+        eval: |_, args| cast(args, FieldTy::Text),
+    },
+    Function {
+        name: "to_json",
+        args: &[ArgTy::Any],
+        ret: FieldTy::Json,
+        eval: |_, args| cast(args, FieldTy::Json),
+    },
+    // Typed extraction of JSON values, see `json_value`
+    Function {
+        name: "json_text",
+        args: &[ArgTy::Is(FieldTy::Json)],
+        ret: FieldTy::Text,
+        eval: |_, args| json_value(args, FieldTy::Text),
+    },
+    Function {
+        name: "json_i64",
+        args: &[ArgTy::Is(FieldTy::Json)],
+        ret: FieldTy::IntI64,
+        eval: |_, args| json_value(args, FieldTy::IntI64),
+    },
+    Function {
+        name: "json_f64",
+        args: &[ArgTy::Is(FieldTy::Json)],
+        ret: FieldTy::F64,
+        eval: |_, args| json_value(args, FieldTy::F64),
+    },
+    Function {
+        name: "json_bool",
+        args: &[ArgTy::Is(FieldTy::Json)],
+        ret: FieldTy::Bool,
+        eval: |_, args| json_value(args, FieldTy::Bool),
+    },
+];
+
+impl Function {
+    /// The function `name` that accepts arguments of the types `args`.
+    ///
+    /// If no function of that name accepts them, the first one is returned, so calling it reports the mismatch.
+    pub fn get(name: &str, args: &[Ty]) -> Option<&'static Function> {
+        let mut functions = FUNCTIONS.iter().filter(|function| function.name == name);
+
+        functions
+            .clone()
+            .find(|function| function.ty(args).is_ok())
+            .or_else(|| functions.next())
+    }
+
+    /// The result type for arguments of the types `args`
+    pub fn ty(&self, args: &[Ty]) -> Result<FieldTy, EvalErr> {
+        self.check_arg_count(args.len())?;
+
+        let first = match args.first() {
+            Some(Ty::Field(ty)) => Some(ty.non_null()),
+            _ => None,
+        };
+
+        let mut nullable = false;
+
+        for (arg, expected) in args.iter().zip(self.args) {
+            match arg {
+                Ty::Field(ty) if expected.accepts(ty.non_null(), first) => {
+                    nullable |= ty.is_nullable()
+                }
+                found => {
+                    return Err(EvalErr::MissmatchedTypes {
+                        found: found.clone(),
+                        expected: expected.ty(),
+                    });
+                }
+            }
+        }
+
+        Ok(nullable_if(self.ret.clone(), nullable))
+    }
+
+    /// Checks the arguments and computes the result, which is null if any argument is null.
+    pub fn call(&self, ctx: &EvalCtx, args: Vec<Value>) -> Result<FieldValue, EvalErr> {
+        self.check_arg_count(args.len())?;
+
+        let first = match args.first() {
+            Some(Value::Field(FieldValue::Null(ty))) => Some(ty.clone()),
+            Some(Value::Field(value)) => Some(value.ty()),
+            _ => None,
+        };
+
+        let mut values = Vec::with_capacity(args.len());
+
+        for (arg, expected) in args.into_iter().zip(self.args) {
+            match arg {
+                Value::Field(FieldValue::Null(ty)) if expected.accepts(&ty, first.as_ref()) => {
+                    return Ok(FieldValue::Null(self.ret.clone()));
+                }
+                Value::Field(value) if expected.accepts(&value.ty(), first.as_ref()) => {
+                    values.push(value)
+                }
+                arg => {
+                    return Err(EvalErr::MissmatchedTypes {
+                        found: arg.ty(),
+                        expected: expected.ty(),
+                    });
+                }
+            }
+        }
+
+        (self.eval)(ctx, &values)
+    }
+
+    fn check_arg_count(&self, found: usize) -> Result<(), EvalErr> {
+        if found != self.args.len() {
+            return Err(EvalErr::InvalidFunctionArgCount {
+                name: self.name.into(),
+                found,
+                expected: self.args.len(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl ArgTy {
+    /// Whether a non-null argument of type `ty` is accepted, `first` is the non-null type of the first argument
+    fn accepts(&self, ty: &FieldTy, first: Option<&FieldTy>) -> bool {
+        match self {
+            ArgTy::Is(expected) => expected == ty,
+            ArgTy::Any => true,
+            ArgTy::List => matches!(ty, FieldTy::List(_)),
+            ArgTy::Element => first
+                .and_then(FieldTy::element)
+                .is_some_and(|element| BinaryOp::Eq(EqOp::Eq).ty(element.non_null(), ty).is_some()),
+        }
+    }
+
+    fn ty(&self) -> Option<Ty> {
+        match self {
+            ArgTy::Is(ty) => Some(ty.clone().into()),
+            ArgTy::Any | ArgTy::List | ArgTy::Element => None,
+        }
+    }
+}
+
+/// The error for arguments that do not match `Function::args`, which `Function::call` rules out
+fn unexpected_args(args: &[FieldValue]) -> EvalErr {
+    EvalErr::MissmatchedTypes {
+        found: Ty::Field(args.first().map_or(FieldTy::Bool, FieldValue::ty)),
+        expected: None,
+    }
+}
+
+fn cast(args: &[FieldValue], ty: FieldTy) -> Result<FieldValue, EvalErr> {
+    match args {
+        [value] => value.cast(&ty).ok_or_else(|| EvalErr::InvalidCast {
+            value: value.clone(),
+            ty,
+        }),
+        args => Err(unexpected_args(args)),
+    }
+}
+
+/// The number of whole units of a duration, counted by `count`.
+///
+/// Fails if the number does not fit into `IntI32`, like a cast to it.
+fn whole_units(args: &[FieldValue], count: fn(&TimeDelta) -> i64) -> Result<FieldValue, EvalErr> {
+    let [value @ FieldValue::Duration(duration)] = args else {
+        return Err(unexpected_args(args));
+    };
+
+    i32::try_from(count(duration))
+        .map(FieldValue::Int)
+        .map_err(|_| EvalErr::InvalidCast {
+            value: value.clone(),
+            ty: FieldTy::IntI32,
+        })
+}
+
+/// A JSON string, number or boolean as `ty`, which must match the kind of the value.
+///
+/// Unlike casts this does not convert between kinds, so `json_text` of a number is an error.
+fn json_value(args: &[FieldValue], ty: FieldTy) -> Result<FieldValue, EvalErr> {
+    let [value @ FieldValue::Json(json)] = args else {
+        return Err(unexpected_args(args));
+    };
+
+    let result = match (json, &ty) {
+        (Json::String(text), FieldTy::Text) => Some(FieldValue::Text(text.clone())),
+        (Json::Number(number), FieldTy::F64) => Some(FieldValue::F64(*number)),
+        // Numbers with a fractional part are not rounded
+        (Json::Number(number), FieldTy::IntI64) if number.0.fract() == 0.0 => {
+            FieldValue::F64(*number).cast(&ty)
+        }
+        (Json::Bool(value), FieldTy::Bool) => Some(FieldValue::Bool(*value)),
+        _ => None,
+    };
+
+    result.ok_or_else(|| EvalErr::InvalidCast {
+        value: value.clone(),
+        ty,
+    })
+}
+
+fn date_trunc(unit: &str, value: &DateTime<Utc>) -> Result<DateTime<Utc>, EvalErr> {
+    let date = value.date_naive();
+
+    let (date, time) = match unit {
+        "year" => (date.with_ordinal(1), Some(NaiveTime::MIN)),
+        "month" => (date.with_day(1), Some(NaiveTime::MIN)),
+        "week" => (
+            date.checked_sub_days(Days::new(date.weekday().num_days_from_monday() as _)),
+            Some(NaiveTime::MIN),
+        ),
+        "day" => (Some(date), Some(NaiveTime::MIN)),
+        "hour" => (Some(date), NaiveTime::from_hms_opt(value.hour(), 0, 0)),
+        "minute" => (
+            Some(date),
+            NaiveTime::from_hms_opt(value.hour(), value.minute(), 0),
+        ),
+        _ => return Err(EvalErr::UnknownDateUnit { unit: unit.into() }),
+    };
+
+    let time = time.unwrap_or_default();
+
+    // Only the week of the first supported date starts before it
+    Ok(date.map_or(DateTime::<Utc>::MIN_UTC, |date| {
+        date.and_time(time).and_utc()
+    }))
+}
+
+/// Case-insensitive wildcard matching for the `like` function
+fn like(input: &str, pattern: &str) -> bool {
+    let input = input.to_lowercase().chars().collect::<Vec<_>>();
+    let pattern = pattern.to_lowercase().chars().collect::<Vec<_>>();
+
+    let (mut i, mut p) = (0, 0);
+    // The position after the last `%` and the input position it currently matches up to
+    let mut backtrack = None;
+
+    while i < input.len() {
+        match pattern.get(p) {
+            Some('%') => {
+                p += 1;
+                backtrack = Some((p, i));
+            }
+            Some(&c) if c == '_' || c == input[i] => {
+                i += 1;
+                p += 1;
+            }
+            // Let the last `%` match one more character
+            _ => match backtrack {
+                Some((pattern_pos, input_pos)) => {
+                    p = pattern_pos;
+                    i = input_pos + 1;
+                    backtrack = Some((pattern_pos, i));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '%')
+}
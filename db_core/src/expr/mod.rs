@@ -3,10 +3,14 @@ mod expr;
 mod eval_ctx;
 mod ty_ctx;
 mod error;
+mod aggregate;
+mod function;
 
 
 pub use expr::*;
 pub use op::*;
 pub use eval_ctx::*;
 pub use ty_ctx::*;
-pub use error::*;
\ No newline at end of file
+pub use error::*;
+pub use aggregate::*;
+pub use function::*;
\ No newline at end of file
@@ -1,5 +1,9 @@
+use std::fmt::Display;
+
 use crate::{
+    decimal::Decimal,
     expr::EvalErr,
+    float::Float,
     ty::{FieldTy, Ty},
     value::{FieldValue, Value},
 };
@@ -8,6 +12,10 @@ use crate::{
 pub enum UnaryOp {
     Negate,
     LogicNot,
+    /// Written after the value, `value is null`
+    IsNull,
+    /// Written after the value, `value is not null`
+    IsNotNull,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -49,11 +57,21 @@ pub enum EqOp {
 impl BinaryOp {
     pub fn ty(&self, a: &FieldTy, b: &FieldTy) -> Option<FieldTy> {
         match self {
-            BinaryOp::Math(_) => {
-                if a == &FieldTy::IntI32 && b == &FieldTy::IntI32 {
-                    Some(FieldTy::IntI32)
-                } else {
-                    None
+            BinaryOp::Math(math_op) => {
+                use FieldTy::{Duration, IntI32, Timestamp};
+
+                if let Some(ty) = a.common_numeric(b) {
+                    return Some(ty);
+                }
+
+                match (a, math_op, b) {
+                    (Timestamp, MathOp::Sub, Timestamp) => Some(Duration),
+                    (Timestamp, MathOp::Add | MathOp::Sub, Duration)
+                    | (Duration, MathOp::Add, Timestamp) => Some(Timestamp),
+                    (Duration, MathOp::Add | MathOp::Sub, Duration)
+                    | (Duration, MathOp::Mul | MathOp::Div, IntI32)
+                    | (IntI32, MathOp::Mul, Duration) => Some(Duration),
+                    _ => None,
                 }
             }
             BinaryOp::Logic(_) => {
@@ -64,16 +82,25 @@ impl BinaryOp {
                 }
             }
             BinaryOp::Compare(_) => {
-                if a == &FieldTy::IntI32 && b == &FieldTy::IntI32
-                    || a == &FieldTy::Timestamp && b == &FieldTy::Timestamp
+                if a == b
+                    && matches!(
+                        a,
+                        FieldTy::Timestamp | FieldTy::Duration | FieldTy::Enum { .. }
+                    )
+                    || a.common_numeric(b).is_some()
+                    || is_enum_and_text(a, b)
                 {
                     Some(FieldTy::Bool)
                 } else {
                     None
                 }
             }
+            // Blobs are not compared, their content may not be loaded
             BinaryOp::Eq(_) => {
-                if a == b {
+                if a == b && a != &FieldTy::Blob
+                    || a.common_numeric(b).is_some()
+                    || is_enum_and_text(a, b)
+                {
                     Some(FieldTy::Bool)
                 } else {
                     None
@@ -86,61 +113,84 @@ impl BinaryOp {
         let (a, b) = match (a, b) {
             (Value::Field(a), Value::Field(b)) => (a, b),
             (a, b) => {
-                return Err(EvalErr::InvalidTypeForBinaryOp {
-                    op: self.clone(),
-                    a: a.ty(),
-                    b: b.ty(),
-                });
+                let (a_ty, b_ty) = (a.ty(), b.ty());
+
+                match (self, a.into_field_or_id(), b.into_field_or_id()) {
+                    // Records are equal to their id
+                    (BinaryOp::Eq(_), Some(a), Some(b)) => (a, b),
+                    _ => {
+                        return Err(EvalErr::InvalidTypeForBinaryOp {
+                            op: self.clone(),
+                            a: a_ty,
+                            b: b_ty,
+                        });
+                    }
+                }
             }
         };
 
-        match (self, a, b) {
-            (BinaryOp::Math(math_op), FieldValue::Int(a), FieldValue::Int(b)) => {
-                let result = match math_op {
-                    MathOp::Add => a + b,
-                    MathOp::Sub => a - b,
-                    MathOp::Mul => a * b,
-                    MathOp::Div => a / b,
-                };
+        // Operations with a null operand are null, only `&&` and `||` can be decided by the other operand
+        if !matches!(self, BinaryOp::Logic(_)) && (a.is_null() || b.is_null()) {
+            let (a, b) = (a.ty(), b.ty());
 
-                Ok(FieldValue::Int(result))
-            }
-            (BinaryOp::Math(_), a, b) => Err(EvalErr::InvalidTypeForBinaryOp {
-                op: self.clone(),
-                a: Ty::Field(a.ty()),
-                b: Ty::Field(b.ty()),
-            }),
-            (BinaryOp::Logic(logic_op), FieldValue::Bool(a), FieldValue::Bool(b)) => {
-                let result = match logic_op {
-                    LogicOp::And => a && b,
-                    LogicOp::Or => a || b,
-                };
+            return match self.ty(a.non_null(), b.non_null()) {
+                Some(ty) => Ok(FieldValue::Null(ty)),
+                None => Err(EvalErr::InvalidTypeForBinaryOp {
+                    op: *self,
+                    a: Ty::Field(a),
+                    b: Ty::Field(b),
+                }),
+            };
+        }
 
-                Ok(FieldValue::Bool(result))
+        // Numbers of different types are converted to the wider type
+        let (a, b) = match a.ty().common_numeric(&b.ty()) {
+            Some(ty) => (a.cast(&ty).unwrap_or(a), b.cast(&ty).unwrap_or(b)),
+            None => (a, b),
+        };
+
+        // Text is compared as the variant of the same name
+        let (a, b) = match (a, b) {
+            (a @ FieldValue::Enum { .. }, FieldValue::Text(b)) => {
+                let b = text_variant(&a.ty(), b)?;
+                (a, b)
             }
-            (BinaryOp::Logic(_), a, b) => Err(EvalErr::InvalidTypeForBinaryOp {
-                op: self.clone(),
-                a: Ty::Field(a.ty()),
-                b: Ty::Field(b.ty()),
-            }),
+            (FieldValue::Text(a), b @ FieldValue::Enum { .. }) => (text_variant(&b.ty(), a)?, b),
+            (a, b) => (a, b),
+        };
+
+        match (self, a, b) {
+            (BinaryOp::Math(math_op), a, b) => math_op.eval_values(a, b),
+            (BinaryOp::Logic(logic_op), a, b) => logic_op.eval_values(a, b),
             (BinaryOp::Compare(compare_op), FieldValue::Int(a), FieldValue::Int(b)) => {
-                let result = match compare_op {
-                    CompareOp::Less => a < b,
-                    CompareOp::LessEq => a <= b,
-                    CompareOp::Greater => a > b,
-                    CompareOp::GreaterEq => a >= b,
-                };
-                Ok(FieldValue::Bool(result))
+                Ok(FieldValue::Bool(compare_op.eval(&a, &b)))
+            }
+            (BinaryOp::Compare(compare_op), FieldValue::I64(a), FieldValue::I64(b)) => {
+                Ok(FieldValue::Bool(compare_op.eval(&a, &b)))
+            }
+            (BinaryOp::Compare(compare_op), FieldValue::F64(a), FieldValue::F64(b)) => {
+                Ok(FieldValue::Bool(compare_op.eval(&a.0, &b.0)))
+            }
+            (BinaryOp::Compare(compare_op), FieldValue::Decimal(a), FieldValue::Decimal(b)) => {
+                Ok(FieldValue::Bool(compare_op.eval(&a, &b)))
             }
             (BinaryOp::Compare(compare_op), FieldValue::Timestamp(a), FieldValue::Timestamp(b)) => {
-                let result = match compare_op {
-                    CompareOp::Less => a < b,
-                    CompareOp::LessEq => a <= b,
-                    CompareOp::Greater => a > b,
-                    CompareOp::GreaterEq => a >= b,
-                };
-                Ok(FieldValue::Bool(result))
+                Ok(FieldValue::Bool(compare_op.eval(&a, &b)))
             }
+            (BinaryOp::Compare(compare_op), FieldValue::Duration(a), FieldValue::Duration(b)) => {
+                Ok(FieldValue::Bool(compare_op.eval(&a, &b)))
+            }
+            (
+                BinaryOp::Compare(compare_op),
+                FieldValue::Enum {
+                    index: a,
+                    variants: a_variants,
+                },
+                FieldValue::Enum {
+                    index: b,
+                    variants: b_variants,
+                },
+            ) if a_variants == b_variants => Ok(FieldValue::Bool(compare_op.eval(&a, &b))),
             (BinaryOp::Compare(_), a, b) => Err(EvalErr::InvalidTypeForBinaryOp {
                 op: self.clone(),
                 a: Ty::Field(a.ty()),
@@ -149,15 +199,46 @@ impl BinaryOp {
             (BinaryOp::Eq(eq_op), FieldValue::Int(a), FieldValue::Int(b)) => {
                 Ok(FieldValue::Bool(eq_op.eval(&a, &b)))
             }
+            (BinaryOp::Eq(eq_op), FieldValue::I64(a), FieldValue::I64(b)) => {
+                Ok(FieldValue::Bool(eq_op.eval(&a, &b)))
+            }
+            (BinaryOp::Eq(eq_op), FieldValue::F64(a), FieldValue::F64(b)) => {
+                Ok(FieldValue::Bool(eq_op.eval(&a.0, &b.0)))
+            }
+            (BinaryOp::Eq(eq_op), FieldValue::Decimal(a), FieldValue::Decimal(b)) => {
+                Ok(FieldValue::Bool(eq_op.eval(&a, &b)))
+            }
             (BinaryOp::Eq(eq_op), FieldValue::Bool(a), FieldValue::Bool(b)) => {
                 Ok(FieldValue::Bool(eq_op.eval(&a, &b)))
             }
             (BinaryOp::Eq(eq_op), FieldValue::Timestamp(a), FieldValue::Timestamp(b)) => {
                 Ok(FieldValue::Bool(eq_op.eval(&a, &b)))
             }
+            (BinaryOp::Eq(eq_op), FieldValue::Duration(a), FieldValue::Duration(b)) => {
+                Ok(FieldValue::Bool(eq_op.eval(&a, &b)))
+            }
             (BinaryOp::Eq(eq_op), FieldValue::Text(a), FieldValue::Text(b)) => {
                 Ok(FieldValue::Bool(eq_op.eval(&a, &b)))
             }
+            (
+                BinaryOp::Eq(eq_op),
+                a @ FieldValue::RecordId { .. },
+                b @ FieldValue::RecordId { .. },
+            ) => Ok(FieldValue::Bool(eq_op.eval(&a, &b))),
+            (BinaryOp::Eq(eq_op), a @ FieldValue::Enum { .. }, b @ FieldValue::Enum { .. })
+                if a.ty() == b.ty() =>
+            {
+                Ok(FieldValue::Bool(eq_op.eval(&a, &b)))
+            }
+            // Lists are equal if all of their elements are
+            (BinaryOp::Eq(eq_op), a @ FieldValue::List { .. }, b @ FieldValue::List { .. })
+                if a.ty() == b.ty() =>
+            {
+                Ok(FieldValue::Bool(eq_op.eval(&a, &b)))
+            }
+            (BinaryOp::Eq(eq_op), a @ FieldValue::Json(_), b @ FieldValue::Json(_)) => {
+                Ok(FieldValue::Bool(eq_op.eval(&a, &b)))
+            }
             (BinaryOp::Eq(_), a, b) => Err(EvalErr::InvalidTypeForBinaryOp {
                 op: self.clone(),
                 a: Ty::Field(a.ty()),
@@ -167,6 +248,251 @@ impl BinaryOp {
     }
 }
 
+impl Display for BinaryOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let op = match self {
+            BinaryOp::Math(MathOp::Add) => "+",
+            BinaryOp::Math(MathOp::Sub) => "-",
+            BinaryOp::Math(MathOp::Mul) => "*",
+            BinaryOp::Math(MathOp::Div) => "/",
+            BinaryOp::Logic(LogicOp::And) => "&&",
+            BinaryOp::Logic(LogicOp::Or) => "||",
+            BinaryOp::Compare(CompareOp::Less) => "<",
+            BinaryOp::Compare(CompareOp::LessEq) => "<=",
+            BinaryOp::Compare(CompareOp::Greater) => ">",
+            BinaryOp::Compare(CompareOp::GreaterEq) => ">=",
+            BinaryOp::Eq(EqOp::Eq) => "==",
+            BinaryOp::Eq(EqOp::Neq) => "!=",
+        };
+
+        f.write_str(op)
+    }
+}
+
+impl Display for UnaryOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnaryOp::Negate => f.write_str("-"),
+            UnaryOp::LogicNot => f.write_str("!"),
+            UnaryOp::IsNull => f.write_str("is null"),
+            UnaryOp::IsNotNull => f.write_str("is not null"),
+        }
+    }
+}
+
+impl MathOp {
+    /// Returns `None` on overflow or division by zero
+    fn eval<T: CheckedMath>(&self, a: T, b: T) -> Option<T> {
+        match self {
+            MathOp::Add => a.checked_add(b),
+            MathOp::Sub => a.checked_sub(b),
+            MathOp::Mul => a.checked_mul(b),
+            MathOp::Div => a.checked_div(b),
+        }
+    }
+
+    /// Fails with `EvalErr::Overflow` or `EvalErr::DivisionByZero` instead of panicking
+    fn eval_values(self, a: FieldValue, b: FieldValue) -> Result<FieldValue, EvalErr> {
+        let result = match (self, &a, &b) {
+            (math_op, FieldValue::Int(x), FieldValue::Int(y)) => {
+                math_op.eval(*x, *y).map(FieldValue::Int)
+            }
+            (math_op, FieldValue::I64(x), FieldValue::I64(y)) => {
+                math_op.eval(*x, *y).map(FieldValue::I64)
+            }
+            (math_op, FieldValue::F64(x), FieldValue::F64(y)) => math_op
+                .eval(x.0, y.0)
+                .map(|value| FieldValue::F64(Float(value))),
+            (math_op, FieldValue::Decimal(x), FieldValue::Decimal(y)) => {
+                math_op.eval(*x, *y).map(FieldValue::Decimal)
+            }
+            (MathOp::Sub, FieldValue::Timestamp(x), FieldValue::Timestamp(y)) => {
+                Some(FieldValue::Duration(x.signed_duration_since(y)))
+            }
+            (MathOp::Add, FieldValue::Timestamp(x), FieldValue::Duration(y))
+            | (MathOp::Add, FieldValue::Duration(y), FieldValue::Timestamp(x)) => {
+                x.checked_add_signed(*y).map(FieldValue::Timestamp)
+            }
+            (MathOp::Sub, FieldValue::Timestamp(x), FieldValue::Duration(y)) => {
+                x.checked_sub_signed(*y).map(FieldValue::Timestamp)
+            }
+            (MathOp::Add, FieldValue::Duration(x), FieldValue::Duration(y)) => {
+                x.checked_add(y).map(FieldValue::Duration)
+            }
+            (MathOp::Sub, FieldValue::Duration(x), FieldValue::Duration(y)) => {
+                x.checked_sub(y).map(FieldValue::Duration)
+            }
+            (MathOp::Mul, FieldValue::Duration(x), FieldValue::Int(y))
+            | (MathOp::Mul, FieldValue::Int(y), FieldValue::Duration(x)) => {
+                x.checked_mul(*y).map(FieldValue::Duration)
+            }
+            (MathOp::Div, FieldValue::Duration(x), FieldValue::Int(y)) => {
+                x.checked_div(*y).map(FieldValue::Duration)
+            }
+            _ => {
+                return Err(EvalErr::InvalidTypeForBinaryOp {
+                    op: BinaryOp::Math(self),
+                    a: Ty::Field(a.ty()),
+                    b: Ty::Field(b.ty()),
+                });
+            }
+        };
+
+        result.ok_or_else(|| {
+            let (a, b) = (Box::new(a), Box::new(b));
+
+            if self == MathOp::Div && is_zero(&b) {
+                EvalErr::DivisionByZero { a, b }
+            } else {
+                EvalErr::Overflow {
+                    op: BinaryOp::Math(self),
+                    a,
+                    b,
+                }
+            }
+        })
+    }
+}
+
+fn is_zero(value: &FieldValue) -> bool {
+    match value {
+        FieldValue::Int(value) => *value == 0,
+        FieldValue::I64(value) => *value == 0,
+        FieldValue::F64(value) => value.0 == 0.0,
+        FieldValue::Decimal(value) => value.units() == 0,
+        _ => false,
+    }
+}
+
+/// Arithmetic that returns `None` instead of overflowing or dividing by zero
+trait CheckedMath: Sized {
+    fn checked_add(self, rhs: Self) -> Option<Self>;
+    fn checked_sub(self, rhs: Self) -> Option<Self>;
+    fn checked_mul(self, rhs: Self) -> Option<Self>;
+    fn checked_div(self, rhs: Self) -> Option<Self>;
+}
+
+macro_rules! checked_math {
+    ($ty:ty) => {
+        impl CheckedMath for $ty {
+            fn checked_add(self, rhs: Self) -> Option<Self> {
+                <$ty>::checked_add(self, rhs)
+            }
+
+            fn checked_sub(self, rhs: Self) -> Option<Self> {
+                <$ty>::checked_sub(self, rhs)
+            }
+
+            fn checked_mul(self, rhs: Self) -> Option<Self> {
+                <$ty>::checked_mul(self, rhs)
+            }
+
+            fn checked_div(self, rhs: Self) -> Option<Self> {
+                <$ty>::checked_div(self, rhs)
+            }
+        }
+    };
+}
+
+checked_math!(i32);
+checked_math!(i64);
+checked_math!(Decimal);
+
+/// Results that are not finite overflowed, unless an operand already was not finite
+impl CheckedMath for f64 {
+    fn checked_add(self, rhs: Self) -> Option<Self> {
+        finite(self, rhs, self + rhs)
+    }
+
+    fn checked_sub(self, rhs: Self) -> Option<Self> {
+        finite(self, rhs, self - rhs)
+    }
+
+    fn checked_mul(self, rhs: Self) -> Option<Self> {
+        finite(self, rhs, self * rhs)
+    }
+
+    fn checked_div(self, rhs: Self) -> Option<Self> {
+        if rhs == 0.0 {
+            return None;
+        }
+
+        finite(self, rhs, self / rhs)
+    }
+}
+
+fn finite(a: f64, b: f64, result: f64) -> Option<f64> {
+    (result.is_finite() || !a.is_finite() || !b.is_finite()).then_some(result)
+}
+
+fn is_enum_and_text(a: &FieldTy, b: &FieldTy) -> bool {
+    matches!(
+        (a, b),
+        (FieldTy::Enum { .. }, FieldTy::Text) | (FieldTy::Text, FieldTy::Enum { .. })
+    )
+}
+
+/// The variant `text` of the enum type `ty`
+pub(crate) fn text_variant(ty: &FieldTy, text: String) -> Result<FieldValue, EvalErr> {
+    match (ty.variant(&text), ty) {
+        (Some(value), _) => Ok(value),
+        (None, FieldTy::Enum { variants }) => Err(EvalErr::UnknownVariant {
+            variant: text,
+            variants: variants.clone(),
+        }),
+        (None, _) => Err(EvalErr::InvalidCast {
+            value: FieldValue::Text(text),
+            ty: ty.clone(),
+        }),
+    }
+}
+
+impl LogicOp {
+    /// Three-valued logic, null is an unknown truth value: `false && null` is `false`,
+    /// but `true && null` is null
+    fn eval_values(self, a: FieldValue, b: FieldValue) -> Result<FieldValue, EvalErr> {
+        let truth = |value: &FieldValue| match value {
+            FieldValue::Bool(value) => Some(Some(*value)),
+            FieldValue::Null(FieldTy::Bool) => Some(None),
+            _ => None,
+        };
+
+        let (Some(x), Some(y)) = (truth(&a), truth(&b)) else {
+            return Err(EvalErr::InvalidTypeForBinaryOp {
+                op: BinaryOp::Logic(self),
+                a: Ty::Field(a.ty()),
+                b: Ty::Field(b.ty()),
+            });
+        };
+
+        let result = match self {
+            LogicOp::And => match (x, y) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            },
+            LogicOp::Or => match (x, y) {
+                (Some(true), _) | (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None,
+            },
+        };
+
+        Ok(result.map_or(FieldValue::Null(FieldTy::Bool), FieldValue::Bool))
+    }
+}
+
+impl CompareOp {
+    fn eval<T: PartialOrd>(&self, a: &T, b: &T) -> bool {
+        match self {
+            CompareOp::Less => a < b,
+            CompareOp::LessEq => a <= b,
+            CompareOp::Greater => a > b,
+            CompareOp::GreaterEq => a >= b,
+        }
+    }
+}
+
 impl EqOp {
     fn eval<T: PartialEq>(&self, a: &T, b: &T) -> bool {
         match self {
@@ -180,8 +506,8 @@ impl UnaryOp {
     pub fn ty(&self, value: &FieldTy) -> Option<FieldTy> {
         match self {
             UnaryOp::Negate => {
-                if value == &FieldTy::IntI32 {
-                    Some(FieldTy::IntI32)
+                if value.is_numeric() || value == &FieldTy::Duration {
+                    Some(value.clone())
                 } else {
                     None
                 }
@@ -193,6 +519,7 @@ impl UnaryOp {
                     None
                 }
             }
+            UnaryOp::IsNull | UnaryOp::IsNotNull => Some(FieldTy::Bool),
         }
     }
 
@@ -208,7 +535,34 @@ impl UnaryOp {
         };
 
         match (self, value) {
-            (UnaryOp::Negate, FieldValue::Int(value)) => Ok(FieldValue::Int(-value).into()),
+            (UnaryOp::IsNull, value) => Ok(FieldValue::Bool(value.is_null()).into()),
+            (UnaryOp::IsNotNull, value) => Ok(FieldValue::Bool(!value.is_null()).into()),
+            (op, FieldValue::Null(ty)) => match op.ty(&ty) {
+                Some(ty) => Ok(FieldValue::Null(ty).into()),
+                None => Err(EvalErr::InvalidTypeForUnaryOp {
+                    op: *op,
+                    ty: Ty::Field(FieldTy::Nullable(Box::new(ty))),
+                }),
+            },
+            // Integers fail like `0 - value` when the negation overflows
+            (UnaryOp::Negate, FieldValue::Int(value)) => MathOp::Sub
+                .eval_values(FieldValue::Int(0), FieldValue::Int(value))
+                .map(Value::Field),
+            (UnaryOp::Negate, FieldValue::I64(value)) => MathOp::Sub
+                .eval_values(FieldValue::I64(0), FieldValue::I64(value))
+                .map(Value::Field),
+            (UnaryOp::Negate, FieldValue::F64(value)) => {
+                Ok(FieldValue::F64(Float(-value.0)).into())
+            }
+            (UnaryOp::Negate, FieldValue::Decimal(value)) => MathOp::Sub
+                .eval_values(
+                    FieldValue::Decimal(Decimal::default()),
+                    FieldValue::Decimal(value),
+                )
+                .map(Value::Field),
+            (UnaryOp::Negate, FieldValue::Duration(value)) => {
+                Ok(FieldValue::Duration(-value).into())
+            }
             (UnaryOp::LogicNot, FieldValue::Bool(value)) => Ok(FieldValue::Bool(!value).into()),
             (_, value) => {
                 return Err(EvalErr::InvalidTypeForUnaryOp {
@@ -1,9 +1,15 @@
-use std::{collections::HashMap, sync::Arc};
-
-use crate::defs::table::TableData;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
+use crate::{defs::table::TableData, ty::FieldTy};
 
 #[derive(Debug, Default)]
 pub struct TyCtx {
-    pub tables: HashMap<Arc<str>, Arc<TableData>>
-}
\ No newline at end of file
+    pub tables: HashMap<Arc<str>, Arc<TableData>>,
+    /// Tables whose record can be accessed by name, like `EvalCtx::records`
+    pub scope: HashSet<Arc<str>>,
+    /// Variables bound by `Expr::Any`, accessed like tables
+    pub vars: HashMap<Arc<str>, FieldTy>,
+}
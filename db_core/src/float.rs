@@ -0,0 +1,62 @@
+//! A `f64` that can be compared and hashed like the other field values.
+
+use std::{
+    cmp::Ordering,
+    fmt::Display,
+    hash::{Hash, Hasher},
+};
+
+use bytepack::{BytePacker, ByteUnpacker, Pack, Unpack};
+
+/// Equality and order follow `f64::total_cmp`, so `NaN` equals itself and `-0.0` is less than `0.0`.
+///
+/// Comparisons in expressions use the `f64` values instead, see `BinaryOp::eval`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Float(pub f64);
+
+impl PartialEq for Float {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Float {}
+
+impl PartialOrd for Float {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Float {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl Hash for Float {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
+    }
+}
+
+/// Always includes a decimal point, so the text is not mistaken for an integer
+impl Display for Float {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
+impl Pack for Float {
+    const PACK_BYTES: u32 = f64::PACK_BYTES;
+
+    fn pack(&self, offset: u32, packer: &mut BytePacker) {
+        self.0.pack(offset, packer);
+    }
+}
+
+impl<'b> Unpack<'b> for Float {
+    fn unpack(offset: u32, unpacker: &ByteUnpacker<'b>) -> Option<Self> {
+        f64::unpack(offset, unpacker).map(Float)
+    }
+}
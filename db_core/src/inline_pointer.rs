@@ -60,10 +60,10 @@ impl<'b> Unpack<'b> for InlinePointerUnpack<'b> {
             let tag = pointer.len.to_be_bytes();
             Some(Self::Inline { tag })
         } else {
-            let value = unpacker.read_bytes(pointer);
+            let value = unpacker.read_bytes(pointer)?;
 
-            let tag = value[0..4].try_into().ok()?;
-            let value = &value[4..];
+            let tag = value.get(0..4)?.try_into().ok()?;
+            let value = value.get(4..)?;
 
             Some(Self::Indirect {
                 tag,
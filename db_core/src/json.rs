@@ -0,0 +1,353 @@
+//! Semi-structured values of `FieldTy::Json` fields, with the JSON text format used by casts.
+
+use std::fmt::{Display, Write};
+
+use bytepack::{BytePacker, ByteUnpacker, Pack, Unpack};
+
+use crate::{
+    float::Float,
+    inline_pointer::{InlinePointerPack, InlinePointerUnpack},
+};
+
+/// A JSON value, objects keep the order of their keys
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(Float),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+/// Deeper nesting is rejected by `Json::parse`, so parsing and packing can not overflow the stack
+const MAX_DEPTH: usize = 128;
+
+impl Json {
+    /// The value of `key` in an object, `None` for other values
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(entries) => entries
+                .iter()
+                .find(|(entry_key, _)| entry_key == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    /// Parses JSON text, returns `None` if it is invalid
+    pub fn parse(input: &str) -> Option<Json> {
+        let mut parser = Parser {
+            rest: input.trim_start(),
+        };
+
+        let value = parser.value(0)?;
+
+        parser.rest.trim_start().is_empty().then_some(value)
+    }
+}
+
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+impl Parser<'_> {
+    fn value(&mut self, depth: usize) -> Option<Json> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+
+        self.skip_whitespace();
+
+        match self.rest.chars().next()? {
+            'n' => self.keyword("null", Json::Null),
+            't' => self.keyword("true", Json::Bool(true)),
+            'f' => self.keyword("false", Json::Bool(false)),
+            '"' => self.string().map(Json::String),
+            '[' => {
+                self.rest = &self.rest[1..];
+
+                let mut values = Vec::new();
+
+                if !self.eat(']') {
+                    loop {
+                        values.push(self.value(depth + 1)?);
+
+                        if self.eat(']') {
+                            break;
+                        }
+
+                        self.expect(',')?;
+                    }
+                }
+
+                Some(Json::Array(values))
+            }
+            '{' => {
+                self.rest = &self.rest[1..];
+
+                let mut entries = Vec::new();
+
+                if !self.eat('}') {
+                    loop {
+                        self.skip_whitespace();
+                        let key = self.string()?;
+                        self.expect(':')?;
+                        entries.push((key, self.value(depth + 1)?));
+
+                        if self.eat('}') {
+                            break;
+                        }
+
+                        self.expect(',')?;
+                    }
+                }
+
+                Some(Json::Object(entries))
+            }
+            _ => self.number(),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.rest = self.rest.trim_start();
+    }
+
+    /// Consumes `c` after optional whitespace if it comes next
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+
+        match self.rest.strip_prefix(c) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Option<()> {
+        self.eat(c).then_some(())
+    }
+
+    fn keyword(&mut self, keyword: &str, value: Json) -> Option<Json> {
+        self.rest = self.rest.strip_prefix(keyword)?;
+
+        Some(value)
+    }
+
+    fn number(&mut self) -> Option<Json> {
+        let len = self
+            .rest
+            .find(|c: char| !matches!(c, '0'..='9' | '-' | '+' | '.' | 'e' | 'E'))
+            .unwrap_or(self.rest.len());
+
+        let (number, rest) = self.rest.split_at(len);
+
+        // Rust also accepts `inf`, `NaN` and a leading `+`, which are not JSON
+        if !number
+            .trim_start_matches('-')
+            .starts_with(|c: char| c.is_ascii_digit())
+        {
+            return None;
+        }
+
+        self.rest = rest;
+
+        number
+            .parse()
+            .ok()
+            .map(|number| Json::Number(Float(number)))
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let mut chars = self.rest.strip_prefix('"')?.chars();
+        let mut value = String::new();
+
+        loop {
+            match chars.next()? {
+                '"' => break,
+                '\\' => {
+                    let escaped = match chars.next()? {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'u' => {
+                            let mut code = hex_code(&mut chars)?;
+
+                            // Characters outside the basic plane are escaped as surrogate pairs
+                            if (0xD800..0xDC00).contains(&code) {
+                                let rest = chars.as_str().strip_prefix("\\u")?;
+                                chars = rest.chars();
+
+                                let low = hex_code(&mut chars)?;
+                                code =
+                                    0x10000 + ((code - 0xD800) << 10) + low.checked_sub(0xDC00)?;
+                            }
+
+                            char::from_u32(code)?
+                        }
+                        c @ ('"' | '\\' | '/') => c,
+                        _ => return None,
+                    };
+
+                    value.push(escaped);
+                }
+                c => value.push(c),
+            }
+        }
+
+        self.rest = chars.as_str();
+
+        Some(value)
+    }
+}
+
+fn hex_code(chars: &mut std::str::Chars) -> Option<u32> {
+    let hex = chars.as_str().get(..4)?;
+    let code = u32::from_str_radix(hex, 16).ok()?;
+
+    *chars = chars.as_str()[4..].chars();
+
+    Some(code)
+}
+
+fn write_string(f: &mut std::fmt::Formatter<'_>, value: &str) -> std::fmt::Result {
+    f.write_char('"')?;
+
+    for c in value.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\t' => f.write_str("\\t")?,
+            '\r' => f.write_str("\\r")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+
+    f.write_char('"')
+}
+
+/// Compact JSON text that `Json::parse` reads again
+impl Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(value) => write!(f, "{value}"),
+            // Without exponent and decimal point for integral numbers, the shortest text of the same `f64`
+            Json::Number(Float(value)) if value.is_finite() => write!(f, "{value}"),
+            Json::Number(_) => f.write_str("null"),
+            Json::String(value) => write_string(f, value),
+            Json::Array(values) => {
+                f.write_char('[')?;
+
+                for (idx, value) in values.iter().enumerate() {
+                    if idx > 0 {
+                        f.write_char(',')?;
+                    }
+
+                    write!(f, "{value}")?;
+                }
+
+                f.write_char(']')
+            }
+            Json::Object(entries) => {
+                f.write_char('{')?;
+
+                for (idx, (key, value)) in entries.iter().enumerate() {
+                    if idx > 0 {
+                        f.write_char(',')?;
+                    }
+
+                    write_string(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+const NULL_TAG: [u8; 4] = *b"null";
+const TRUE_TAG: [u8; 4] = *b"true";
+const FALSE_TAG: [u8; 4] = *b"fals";
+const NUMBER_TAG: [u8; 4] = *b"num ";
+const STRING_TAG: [u8; 4] = *b"str ";
+const ARRAY_TAG: [u8; 4] = *b"arry";
+const OBJECT_TAG: [u8; 4] = *b"objc";
+
+/// Null and booleans are stored in the pointer itself, other values behind it after their tag.
+///
+/// The elements of arrays and objects are packed like `Vec`s of values and key value pairs.
+impl Pack for Json {
+    const PACK_BYTES: u32 = InlinePointerPack::<Json>::PACK_BYTES;
+
+    fn pack(&self, offset: u32, packer: &mut BytePacker) {
+        let number;
+
+        let pointer: InlinePointerPack<'_, Json> = match self {
+            Json::Null => InlinePointerPack::Inline { tag: NULL_TAG },
+            Json::Bool(true) => InlinePointerPack::Inline { tag: TRUE_TAG },
+            Json::Bool(false) => InlinePointerPack::Inline { tag: FALSE_TAG },
+            Json::Number(value) => {
+                number = value.0.to_be_bytes();
+
+                InlinePointerPack::Indirect {
+                    tag: NUMBER_TAG,
+                    value: &number,
+                }
+            }
+            Json::String(value) => InlinePointerPack::Indirect {
+                tag: STRING_TAG,
+                value: value.as_bytes(),
+            },
+            Json::Array(values) => {
+                return InlinePointerPack::Nested {
+                    tag: ARRAY_TAG,
+                    value: values,
+                }
+                .pack(offset, packer);
+            }
+            Json::Object(entries) => {
+                return InlinePointerPack::Nested {
+                    tag: OBJECT_TAG,
+                    value: entries,
+                }
+                .pack(offset, packer);
+            }
+        };
+
+        pointer.pack(offset, packer);
+    }
+}
+
+impl<'b> Unpack<'b> for Json {
+    fn unpack(offset: u32, unpacker: &ByteUnpacker<'b>) -> Option<Self> {
+        match InlinePointerUnpack::unpack(offset, unpacker)? {
+            InlinePointerUnpack::Inline { tag } => match tag {
+                NULL_TAG => Some(Json::Null),
+                TRUE_TAG => Some(Json::Bool(true)),
+                FALSE_TAG => Some(Json::Bool(false)),
+                _ => None,
+            },
+            InlinePointerUnpack::Indirect {
+                tag,
+                value,
+                value_offset,
+            } => match tag {
+                NUMBER_TAG => Some(Json::Number(Float(f64::from_be_bytes(
+                    value.try_into().ok()?,
+                )))),
+                STRING_TAG => Some(Json::String(str::from_utf8(value).ok()?.into())),
+                ARRAY_TAG => Some(Json::Array(Vec::unpack(value_offset, unpacker)?)),
+                OBJECT_TAG => Some(Json::Object(Vec::unpack(value_offset, unpacker)?)),
+                _ => None,
+            },
+        }
+    }
+}
@@ -5,4 +5,9 @@ pub mod ty;
 pub mod defs;
 pub mod named;
 pub mod record;
-pub mod inline_pointer;
\ No newline at end of file
+pub mod inline_pointer;
+pub mod duration;
+pub mod decimal;
+pub mod float;
+pub mod json;
+pub mod blob;
\ No newline at end of file
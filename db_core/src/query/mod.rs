@@ -2,14 +2,56 @@ mod result;
 
 use std::sync::Arc;
 
-use crate::expr::Expr;
+use crate::{expr::Expr, named::Named};
 
 pub use result::*;
 
+/// Sorting, `offset` and `limit` are applied to the filtered records before they are grouped.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Query {
     pub table_name: Arc<str>,
+    /// Tables whose records are combined with every record of the queried table
+    pub joins: Vec<Join>,
     pub filter: Option<Expr>,
     pub group_by: Option<Expr>,
+    /// Expressions computed for every record, or once over the records of the result or of every group
+    /// if any of them contains an aggregate function
+    pub select: Vec<Named<Expr>>,
+    /// Sort keys, later keys only decide between records with equal earlier keys
+    pub order_by: Vec<OrderBy>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+    pub on_error: OnError,
 }
 
+/// What happens to a row whose filter, join condition, group or order can not be evaluated,
+/// e.g. because of `EvalErr::DivisionByZero`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnError {
+    /// The row is treated as not matching, an order key as null
+    #[default]
+    Skip,
+    /// The query fails with the error
+    Fail,
+}
+
+/// An inner join: every combination of records for which `on` is true is part of the result.
+/// Filters and other expressions of the query can read the joined records by their table name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Join {
+    pub table_name: Arc<str>,
+    pub on: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderBy {
+    pub expr: Expr,
+    pub direction: SortDirection,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortDirection {
+    #[default]
+    Asc,
+    Desc,
+}
@@ -2,18 +2,39 @@ use std::sync::Arc;
 
 use dioxus_stores::Store;
 
-use crate::{defs::table::TableData, record::RecordBytes, value::Value};
+use ulid::Ulid;
+
+use crate::{defs::table::TableData, named::Named, record::RecordBytes, ty::Ty, value::Value};
 
 #[derive(Debug, Clone, Store)]
 pub enum QueryResult {
     Records(QueryResultRecords),
+    Rows(QueryResultRows),
     Grouped { groups: Vec<QueryResultGroup> },
 }
 
 #[derive(Debug, Clone, Store)]
 pub struct QueryResultRecords {
+    /// Records of the queried table, a record combined with several joined records is included once
     pub records: Vec<RecordBytes>,
     pub format: Arc<TableData>,
+    /// The values of `Query::select` over `records`, if it contains aggregate functions
+    pub aggregates: Vec<Named<Value>>,
+}
+
+/// The result of a query selecting expressions without aggregate functions
+#[derive(Debug, Clone, Store)]
+pub struct QueryResultRows {
+    /// Name and type of every selected expression
+    pub columns: Vec<Named<Ty>>,
+    pub rows: Vec<QueryResultRow>,
+}
+
+#[derive(Debug, Clone, Store)]
+pub struct QueryResultRow {
+    /// The record of the queried table the row is computed from
+    pub id: Ulid,
+    pub values: Vec<Value>,
 }
 
 #[derive(Debug, Clone, Store)]
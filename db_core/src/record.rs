@@ -1,4 +1,4 @@
-use bytepack::{ByteUnpacker, Unpack};
+use bytepack::{ByteUnpacker, PackPointer, Unpack};
 use ulid::Ulid;
 
 use crate::{defs::table::TableFieldData, ty::FieldTy, value::FieldValue};
@@ -30,12 +30,61 @@ impl RecordBytes {
     }
 
     pub fn get_field(&self, field: &TableFieldData) -> Option<FieldValue> {
-        let value = match &field.ty {
-            FieldTy::IntI32 => FieldValue::Int(self.unpack(field.offset)?),
-            FieldTy::Bool => FieldValue::Bool(self.unpack(field.offset)?),
-            FieldTy::Timestamp => FieldValue::Timestamp(self.unpack(field.offset)?),
-            FieldTy::Text => FieldValue::Text(self.unpack(field.offset)?),
-            FieldTy::RecordId { table_name } => FieldValue::RecordId { id: self.unpack(field.offset)?, table_name: table_name.clone() },
+        self.get_value(&field.ty, field.offset)
+    }
+
+    fn get_value(&self, ty: &FieldTy, offset: u32) -> Option<FieldValue> {
+        let value = match ty {
+            FieldTy::IntI32 => FieldValue::Int(self.unpack(offset)?),
+            FieldTy::IntI64 => FieldValue::I64(self.unpack(offset)?),
+            FieldTy::F64 => FieldValue::F64(self.unpack(offset)?),
+            FieldTy::Decimal => FieldValue::Decimal(self.unpack(offset)?),
+            FieldTy::Bool => FieldValue::Bool(self.unpack(offset)?),
+            FieldTy::Timestamp => FieldValue::Timestamp(self.unpack(offset)?),
+            FieldTy::Duration => FieldValue::Duration(self.unpack(offset)?),
+            FieldTy::Text => FieldValue::Text(self.unpack(offset)?),
+            FieldTy::Json => FieldValue::Json(self.unpack(offset)?),
+            FieldTy::Blob => FieldValue::Blob(self.unpack(offset)?),
+            FieldTy::RecordId { table_name } => FieldValue::RecordId { id: self.unpack(offset)?, table_name: table_name.clone() },
+            FieldTy::Nullable(ty) => {
+                let pointer = self.unpack::<PackPointer>(offset)?;
+
+                if pointer == PackPointer::NULL {
+                    FieldValue::Null(ty.as_ref().clone())
+                } else {
+                    self.get_value(ty, pointer.offset)?
+                }
+            }
+            FieldTy::Enum { variants } => {
+                let index: u32 = self.unpack(offset)?;
+
+                // An index without variant is a corrupt value
+                if index as usize >= variants.len() {
+                    return None;
+                }
+
+                FieldValue::Enum {
+                    index,
+                    variants: variants.clone(),
+                }
+            }
+            FieldTy::List(ty) => {
+                let pointer = self.unpack::<PackPointer>(offset)?;
+                let byte_count = ty.byte_count();
+
+                if pointer.len % byte_count != 0 {
+                    return None;
+                }
+
+                let values = (0..pointer.len / byte_count)
+                    .map(|idx| self.get_value(ty, pointer.offset + idx * byte_count))
+                    .collect::<Option<_>>()?;
+
+                FieldValue::List {
+                    ty: ty.clone(),
+                    values,
+                }
+            }
         };
 
         Some(value)
@@ -1,32 +1,134 @@
 use std::sync::Arc;
 
-use bytepack::{Pack, Unpack};
-use chrono::{DateTime, Utc};
+use bytepack::{Pack, PackPointer, Unpack};
+use chrono::{DateTime, TimeDelta, Utc};
 use ulid::Ulid;
 
 use crate::{
+    blob::Blob,
+    decimal::Decimal,
     defs::table::TableData,
+    float::Float,
     inline_pointer::{InlinePointerPack, InlinePointerUnpack},
+    json::Json,
     named::Named,
+    value::FieldValue,
 };
 
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
 pub enum FieldTy {
     IntI32,
+    IntI64,
+    F64,
+    Decimal,
     Bool,
     Timestamp,
+    Duration,
     Text,
     RecordId { table_name: Arc<str> },
+    Nullable(Box<FieldTy>),
+    /// One of a fixed list of variants, compared with text by the name of the variant.
+    ///
+    /// Values are stored as the index of their variant, so they are ordered like `variants`.
+    Enum { variants: Arc<[Arc<str>]> },
+    /// Any number of values of the element type, indices over a list field have an entry for every element
+    List(Box<FieldTy>),
+    /// Semi-structured data, fields of JSON objects are accessed like fields of records
+    Json,
+    /// Raw bytes with a MIME type, large contents are stored outside of the record
+    Blob,
 }
 
 impl FieldTy {
     pub fn byte_count(&self) -> u32 {
         match self {
             Self::IntI32 => i32::PACK_BYTES,
+            Self::IntI64 => i64::PACK_BYTES,
+            Self::F64 => Float::PACK_BYTES,
+            Self::Decimal => Decimal::PACK_BYTES,
             Self::Bool => bool::PACK_BYTES,
             Self::Timestamp => DateTime::<Utc>::PACK_BYTES,
+            Self::Duration => TimeDelta::PACK_BYTES,
             Self::Text => String::PACK_BYTES,
             Self::RecordId { .. } => Ulid::PACK_BYTES,
+            Self::Nullable(_) | Self::List(_) => PackPointer::PACK_BYTES,
+            Self::Enum { .. } => u32::PACK_BYTES,
+            Self::Json => Json::PACK_BYTES,
+            Self::Blob => Blob::PACK_BYTES,
+        }
+    }
+
+    /// The type without nullability
+    pub fn non_null(&self) -> &FieldTy {
+        match self {
+            Self::Nullable(ty) => ty.non_null(),
+            ty => ty,
+        }
+    }
+
+    pub fn is_nullable(&self) -> bool {
+        matches!(self, Self::Nullable(_))
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, Self::IntI32 | Self::IntI64 | Self::F64 | Self::Decimal)
+    }
+
+    /// The type numbers of both types are converted to for binary operations, the wider type
+    pub fn common_numeric(&self, other: &FieldTy) -> Option<FieldTy> {
+        let rank = |ty: &FieldTy| match ty {
+            Self::IntI32 => Some(0),
+            Self::IntI64 => Some(1),
+            Self::Decimal => Some(2),
+            Self::F64 => Some(3),
+            _ => None,
+        };
+
+        if rank(self)? >= rank(other)? {
+            Some(self.clone())
+        } else {
+            Some(other.clone())
+        }
+    }
+
+    /// The element type of a list type, which may be nullable
+    pub fn element(&self) -> Option<&FieldTy> {
+        match self.non_null() {
+            Self::List(ty) => Some(ty),
+            _ => None,
+        }
+    }
+
+    /// The type of the values an index over a field of this type stores, the element type for lists
+    pub fn indexed(&self) -> &FieldTy {
+        self.element().unwrap_or(self)
+    }
+
+    /// The value of the variant `name` of an enum type
+    pub fn variant(&self, name: &str) -> Option<FieldValue> {
+        let Self::Enum { variants } = self else {
+            return None;
+        };
+
+        let index = variants
+            .iter()
+            .position(|variant| variant.as_ref() == name)?;
+
+        Some(FieldValue::Enum {
+            index: index as u32,
+            variants: variants.clone(),
+        })
+    }
+
+    /// Returns `true` if `value` can be stored in a field of this type.
+    pub fn accepts(&self, value: &FieldValue) -> bool {
+        match (self, value) {
+            (Self::Nullable(ty), FieldValue::Null(value_ty)) => ty.as_ref() == value_ty,
+            (Self::Nullable(ty), value) => ty.accepts(value),
+            (Self::List(ty), FieldValue::List { ty: value_ty, values }) => {
+                ty == value_ty && values.iter().all(|value| ty.accepts(value))
+            }
+            (ty, value) => &value.ty() == ty,
         }
     }
 }
@@ -35,6 +137,21 @@ impl FieldTy {
 pub enum Ty {
     Field(FieldTy),
     Table(Named<Arc<TableData>>),
+    /// Any number of records of the table
+    Records(Named<Arc<TableData>>),
+}
+
+impl Ty {
+    /// The field type, or the id type of the table
+    pub fn into_field_or_id(self) -> Option<FieldTy> {
+        match self {
+            Ty::Field(ty) => Some(ty),
+            Ty::Table(table) => Some(FieldTy::RecordId {
+                table_name: table.name,
+            }),
+            Ty::Records(_) => None,
+        }
+    }
 }
 
 impl From<FieldTy> for Ty {
@@ -46,24 +163,58 @@ impl From<FieldTy> for Ty {
 type TagBytes = [u8; 4];
 
 const I32_TAG: TagBytes = *b"i32 ";
+const I64_TAG: TagBytes = *b"i64 ";
+const F64_TAG: TagBytes = *b"f64 ";
+const DECIMAL_TAG: TagBytes = *b"dcml";
 const BOOL_TAG: TagBytes = *b"bool";
 const TIMESTAMP_TAG: TagBytes = *b"tstp";
+const DURATION_TAG: TagBytes = *b"dura";
 const TEXT_TAG: TagBytes = *b"text";
 const RECORD_TAG: TagBytes = *b"rcrd";
+const NULLABLE_TAG: TagBytes = *b"null";
+const ENUM_TAG: TagBytes = *b"enum";
+const LIST_TAG: TagBytes = *b"list";
+const JSON_TAG: TagBytes = *b"json";
+const BLOB_TAG: TagBytes = *b"blob";
 
 impl Pack for FieldTy {
     const PACK_BYTES: u32 = InlinePointerPack::<FieldTy>::PACK_BYTES;
 
     fn pack(&self, offset: u32, packer: &mut bytepack::BytePacker) {
+        let variant_names;
+
         let pointer: InlinePointerPack<'_, Self> = match self {
             FieldTy::IntI32 => InlinePointerPack::Inline { tag: I32_TAG },
+            FieldTy::IntI64 => InlinePointerPack::Inline { tag: I64_TAG },
+            FieldTy::F64 => InlinePointerPack::Inline { tag: F64_TAG },
+            FieldTy::Decimal => InlinePointerPack::Inline { tag: DECIMAL_TAG },
             FieldTy::Bool => InlinePointerPack::Inline { tag: BOOL_TAG },
             FieldTy::Timestamp => InlinePointerPack::Inline { tag: TIMESTAMP_TAG },
+            FieldTy::Duration => InlinePointerPack::Inline { tag: DURATION_TAG },
             FieldTy::Text => InlinePointerPack::Inline { tag: TEXT_TAG },
+            FieldTy::Json => InlinePointerPack::Inline { tag: JSON_TAG },
+            FieldTy::Blob => InlinePointerPack::Inline { tag: BLOB_TAG },
             FieldTy::RecordId { table_name } => InlinePointerPack::Indirect {
                 tag: RECORD_TAG,
                 value: table_name.as_bytes(),
             },
+            FieldTy::Nullable(ty) => InlinePointerPack::Nested {
+                tag: NULLABLE_TAG,
+                value: ty.as_ref(),
+            },
+            FieldTy::List(ty) => InlinePointerPack::Nested {
+                tag: LIST_TAG,
+                value: ty.as_ref(),
+            },
+            // Variant names are separated by `\0`, `Db::register_table` rejects names containing it
+            FieldTy::Enum { variants } => {
+                variant_names = variants.join("\0");
+
+                InlinePointerPack::Indirect {
+                    tag: ENUM_TAG,
+                    value: variant_names.as_bytes(),
+                }
+            }
         };
 
         pointer.pack(offset, packer);
@@ -77,21 +228,47 @@ impl<'b> Unpack<'b> for FieldTy {
         match pointer {
             InlinePointerUnpack::Inline { tag } => match tag {
                 I32_TAG => Some(Self::IntI32),
+                I64_TAG => Some(Self::IntI64),
+                F64_TAG => Some(Self::F64),
+                DECIMAL_TAG => Some(Self::Decimal),
                 BOOL_TAG => Some(Self::Bool),
                 TIMESTAMP_TAG => Some(Self::Timestamp),
+                DURATION_TAG => Some(Self::Duration),
                 TEXT_TAG => Some(Self::Text),
+                JSON_TAG => Some(Self::Json),
+                BLOB_TAG => Some(Self::Blob),
                 _ => None,
             },
             InlinePointerUnpack::Indirect {
                 tag,
                 value,
-                value_offset: _,
+                value_offset,
             } => match tag {
                 RECORD_TAG => {
                     let table_name = str::from_utf8(value).ok()?.into();
 
                     Some(Self::RecordId { table_name })
                 }
+                NULLABLE_TAG => {
+                    let ty = Self::unpack(value_offset, unpacker)?;
+
+                    Some(Self::Nullable(Box::new(ty)))
+                }
+                LIST_TAG => {
+                    let ty = Self::unpack(value_offset, unpacker)?;
+
+                    Some(Self::List(Box::new(ty)))
+                }
+                ENUM_TAG => {
+                    let variant_names = str::from_utf8(value).ok()?;
+
+                    let variants = match variant_names {
+                        "" => Arc::from([]),
+                        names => names.split('\0').map(Arc::from).collect(),
+                    };
+
+                    Some(Self::Enum { variants })
+                }
                 _ => None,
             },
         }
@@ -1,11 +1,16 @@
-use std::sync::Arc;
+use std::{cmp::Ordering, fmt::Display, sync::Arc};
 
-use bytepack::Pack;
-use chrono::{DateTime, Utc};
+use bytepack::{Pack, PackPointer};
+use chrono::{DateTime, TimeDelta, Utc};
 use ulid::Ulid;
 
 use crate::{
+    blob::Blob,
+    decimal::Decimal,
     defs::table::TableData,
+    duration,
+    float::Float,
+    json::Json,
     named::Named,
     record::RecordBytes,
     ty::{FieldTy, Ty},
@@ -14,10 +19,28 @@ use crate::{
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
 pub enum FieldValue {
     Int(i32),
+    I64(i64),
+    F64(Float),
+    Decimal(Decimal),
     Bool(bool),
     Timestamp(DateTime<Utc>),
+    Duration(TimeDelta),
     Text(String),
     RecordId { id: Ulid, table_name: Arc<str> },
+    /// The value of an empty nullable field, holding the type of the field without nullability
+    Null(FieldTy),
+    /// The variant `index` of `variants`, see `FieldTy::Enum`
+    Enum {
+        index: u32,
+        variants: Arc<[Arc<str>]>,
+    },
+    /// The elements of a list, holding the element type for empty lists
+    List {
+        ty: Box<FieldTy>,
+        values: Vec<FieldValue>,
+    },
+    Json(Json),
+    Blob(Blob),
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
@@ -27,40 +50,241 @@ pub enum Value {
         table: Named<Arc<TableData>>,
         record: Arc<RecordBytes>,
     },
+    /// Records of a table, see `Expr::ReferencedBy`
+    Records {
+        table: Named<Arc<TableData>>,
+        records: Vec<Arc<RecordBytes>>,
+    },
 }
 
 impl FieldValue {
     pub fn ty(&self) -> FieldTy {
         match self {
             FieldValue::Int(_) => FieldTy::IntI32,
+            FieldValue::I64(_) => FieldTy::IntI64,
+            FieldValue::F64(_) => FieldTy::F64,
+            FieldValue::Decimal(_) => FieldTy::Decimal,
             FieldValue::Bool(_) => FieldTy::Bool,
             FieldValue::Timestamp(_) => FieldTy::Timestamp,
+            FieldValue::Duration(_) => FieldTy::Duration,
             FieldValue::Text(_) => FieldTy::Text,
             Self::RecordId { table_name, .. } => FieldTy::RecordId {
                 table_name: table_name.clone(),
             },
+            Self::Null(ty) => FieldTy::Nullable(Box::new(ty.clone())),
+            Self::Enum { variants, .. } => FieldTy::Enum {
+                variants: variants.clone(),
+            },
+            Self::List { ty, .. } => FieldTy::List(ty.clone()),
+            Self::Json(_) => FieldTy::Json,
+            Self::Blob(_) => FieldTy::Blob,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Self::Null(_))
+    }
+
+    /// The name of the variant of an enum value
+    pub fn variant(&self) -> Option<&str> {
+        match self {
+            Self::Enum { index, variants } => variants.get(*index as usize).map(AsRef::as_ref),
+            _ => None,
+        }
+    }
+
+    /// Orders values of the same type like their index keys, null values come first.
+    ///
+    /// Values of different types are ordered by their type.
+    pub fn total_cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (FieldValue::Int(a), FieldValue::Int(b)) => a.cmp(b),
+            (FieldValue::I64(a), FieldValue::I64(b)) => a.cmp(b),
+            (FieldValue::F64(a), FieldValue::F64(b)) => a.cmp(b),
+            (FieldValue::Decimal(a), FieldValue::Decimal(b)) => a.cmp(b),
+            (FieldValue::Bool(a), FieldValue::Bool(b)) => a.cmp(b),
+            (FieldValue::Timestamp(a), FieldValue::Timestamp(b)) => a.cmp(b),
+            (FieldValue::Duration(a), FieldValue::Duration(b)) => a.cmp(b),
+            (FieldValue::Text(a), FieldValue::Text(b)) => a.cmp(b),
+            (
+                FieldValue::RecordId { id: a, table_name: a_table },
+                FieldValue::RecordId { id: b, table_name: b_table },
+            ) => a.cmp(b).then_with(|| a_table.cmp(b_table)),
+            (
+                FieldValue::Enum { index: a, variants: a_variants },
+                FieldValue::Enum { index: b, variants: b_variants },
+            ) => a.cmp(b).then_with(|| a_variants.cmp(b_variants)),
+            // Lists are ordered by their first differing element
+            (FieldValue::List { values: a, .. }, FieldValue::List { values: b, .. }) => a
+                .iter()
+                .zip(b)
+                .map(|(a, b)| a.total_cmp(b))
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or_else(|| a.len().cmp(&b.len())),
+            // JSON is ordered by its text, like its index keys
+            (FieldValue::Json(a), FieldValue::Json(b)) => a.to_string().cmp(&b.to_string()),
+            (FieldValue::Blob(a), FieldValue::Blob(b)) => a.cmp(b),
+            (a, b) => a.type_rank().cmp(&b.type_rank()),
+        }
+    }
+
+    fn type_rank(&self) -> u8 {
+        match self {
+            FieldValue::Null(_) => 0,
+            FieldValue::Int(_) => 1,
+            FieldValue::I64(_) => 2,
+            FieldValue::F64(_) => 3,
+            FieldValue::Decimal(_) => 4,
+            FieldValue::Bool(_) => 5,
+            FieldValue::Timestamp(_) => 6,
+            FieldValue::Duration(_) => 7,
+            FieldValue::Text(_) => 8,
+            FieldValue::RecordId { .. } => 9,
+            FieldValue::Enum { .. } => 10,
+            FieldValue::List { .. } => 11,
+            FieldValue::Json(_) => 12,
+            FieldValue::Blob(_) => 13,
+        }
+    }
+
+    /// Converts this value to a value of type `ty`.
+    ///
+    /// Returns `None` if the value can not be represented as `ty`.
+    pub fn cast(&self, ty: &FieldTy) -> Option<FieldValue> {
+        if ty.accepts(self) {
+            return Some(self.clone());
+        }
+
+        match (self, ty) {
+            (FieldValue::Null(_), FieldTy::Nullable(ty)) => Some(FieldValue::Null(ty.as_ref().clone())),
+            (value, FieldTy::Nullable(ty)) => value.cast(ty),
+            (FieldValue::Int(value), FieldTy::IntI64) => Some(FieldValue::I64(*value as i64)),
+            (FieldValue::Int(value), FieldTy::F64) => Some(FieldValue::F64(Float(*value as f64))),
+            (FieldValue::Int(value), FieldTy::Decimal) => {
+                Decimal::from_int(*value as i64).map(FieldValue::Decimal)
+            }
+            (FieldValue::Int(value), FieldTy::Bool) => Some(FieldValue::Bool(*value != 0)),
+            (FieldValue::Int(value), FieldTy::Text) => Some(FieldValue::Text(value.to_string())),
+            (FieldValue::I64(value), FieldTy::IntI32) => (*value).try_into().ok().map(FieldValue::Int),
+            (FieldValue::I64(value), FieldTy::F64) => Some(FieldValue::F64(Float(*value as f64))),
+            (FieldValue::I64(value), FieldTy::Decimal) => Decimal::from_int(*value).map(FieldValue::Decimal),
+            (FieldValue::I64(value), FieldTy::Text) => Some(FieldValue::Text(value.to_string())),
+            // Floats are rounded towards zero and must be in range of the integer type
+            (FieldValue::F64(value), FieldTy::IntI32) => {
+                float_to_int(value.0).and_then(|value| value.try_into().ok()).map(FieldValue::Int)
+            }
+            (FieldValue::F64(value), FieldTy::IntI64) => float_to_int(value.0).map(FieldValue::I64),
+            (FieldValue::F64(value), FieldTy::Decimal) => Decimal::from_f64(value.0).map(FieldValue::Decimal),
+            (FieldValue::F64(value), FieldTy::Text) => Some(FieldValue::Text(value.to_string())),
+            (FieldValue::Decimal(value), FieldTy::IntI32) => value.trunc().try_into().ok().map(FieldValue::Int),
+            (FieldValue::Decimal(value), FieldTy::IntI64) => Some(FieldValue::I64(value.trunc())),
+            (FieldValue::Decimal(value), FieldTy::F64) => Some(FieldValue::F64(Float(value.to_f64()))),
+            (FieldValue::Decimal(value), FieldTy::Text) => Some(FieldValue::Text(value.to_string())),
+            (FieldValue::Bool(value), FieldTy::IntI32) => Some(FieldValue::Int(*value as i32)),
+            (FieldValue::Bool(value), FieldTy::Text) => Some(FieldValue::Text(value.to_string())),
+            (FieldValue::Timestamp(value), FieldTy::Text) => {
+                Some(FieldValue::Text(value.to_rfc3339()))
+            }
+            (FieldValue::Duration(value), FieldTy::Text) => {
+                Some(FieldValue::Text(duration::format(value)))
+            }
+            (FieldValue::Text(value), FieldTy::IntI32) => value.trim().parse().ok().map(FieldValue::Int),
+            (FieldValue::Text(value), FieldTy::IntI64) => value.trim().parse().ok().map(FieldValue::I64),
+            (FieldValue::Text(value), FieldTy::F64) => value.trim().parse().ok().map(|value| FieldValue::F64(Float(value))),
+            (FieldValue::Text(value), FieldTy::Decimal) => value.trim().parse().ok().map(FieldValue::Decimal),
+            (FieldValue::Text(value), FieldTy::Bool) => value.trim().parse().ok().map(FieldValue::Bool),
+            (FieldValue::Text(value), FieldTy::Timestamp) => DateTime::parse_from_rfc3339(value.trim())
+                .ok()
+                .map(|value| FieldValue::Timestamp(value.to_utc())),
+            (FieldValue::Text(value), FieldTy::Duration) => duration::parse(value.trim()).map(FieldValue::Duration),
+            (FieldValue::RecordId { id, .. }, FieldTy::Text) => Some(FieldValue::Text(id.to_string())),
+            (FieldValue::Text(value), FieldTy::Enum { .. }) => ty.variant(value),
+            // Enums are converted by the name of their variant
+            (value @ FieldValue::Enum { .. }, FieldTy::Text) => value.variant().map(|name| FieldValue::Text(name.to_owned())),
+            (value @ FieldValue::Enum { .. }, FieldTy::Enum { .. }) => ty.variant(value.variant()?),
+            (FieldValue::Text(value), FieldTy::RecordId { table_name }) => Ulid::from_string(value.trim()).ok().map(|id| FieldValue::RecordId { id, table_name: table_name.clone() }),
+            // Lists are converted element by element
+            (FieldValue::List { values, .. }, FieldTy::List(ty)) => values.iter().map(|value| value.cast(ty)).collect::<Option<_>>().map(|values| FieldValue::List { ty: ty.clone(), values }),
+            // JSON is converted from and to its text
+            (FieldValue::Text(value), FieldTy::Json) => Json::parse(value).map(FieldValue::Json),
+            (FieldValue::Json(value), FieldTy::Text) => Some(FieldValue::Text(value.to_string())),
+            _ => None,
         }
     }
 
     pub fn pack(&self, offset: u32, packer: &mut bytepack::BytePacker) {
         match self {
             FieldValue::Int(value) => value.pack(offset, packer),
+            FieldValue::I64(value) => value.pack(offset, packer),
+            FieldValue::F64(value) => value.pack(offset, packer),
+            FieldValue::Decimal(value) => value.pack(offset, packer),
             FieldValue::Bool(value) => value.pack(offset, packer),
             FieldValue::Timestamp(value) => value.pack(offset, packer),
+            FieldValue::Duration(value) => value.pack(offset, packer),
             FieldValue::Text(value) => value.pack(offset, packer),
             FieldValue::RecordId {
                 id: value,
                 table_name: _,
             } => value.pack(offset, packer),
+            FieldValue::Null(_) => PackPointer::NULL.pack(offset, packer),
+            FieldValue::Enum { index, .. } => index.pack(offset, packer),
+            // The elements are stored one after another, each taking the bytes of the element type
+            FieldValue::List { ty, values } => {
+                let pointer = packer.reserve_dynamic_bytes(ty.byte_count() * values.len() as u32);
+                pointer.pack(offset, packer);
+
+                for (idx, value) in values.iter().enumerate() {
+                    value.pack_as(ty, pointer.offset + idx as u32 * ty.byte_count(), packer);
+                }
+            }
+            FieldValue::Json(value) => value.pack(offset, packer),
+            FieldValue::Blob(value) => value.pack(offset, packer),
+        }
+    }
+
+    /// Packs this value into a field of type `ty`.
+    ///
+    /// Unlike [`FieldValue::pack`] this respects the indirection of nullable fields.
+    pub fn pack_as(&self, ty: &FieldTy, offset: u32, packer: &mut bytepack::BytePacker) {
+        match (ty, self) {
+            (FieldTy::Nullable(_), FieldValue::Null(_)) => PackPointer::NULL.pack(offset, packer),
+            (FieldTy::Nullable(ty), value) => {
+                let pointer = packer.reserve_dynamic_bytes(ty.byte_count());
+                pointer.pack(offset, packer);
+
+                value.pack_as(ty, pointer.offset, packer);
+            }
+            (_, value) => value.pack(offset, packer),
         }
     }
 }
 
+/// `value` rounded towards zero, `None` if it is not finite or out of range
+fn float_to_int(value: f64) -> Option<i64> {
+    let value = value.trunc();
+
+    // `i64::MAX as f64` is rounded up to 2^63, which is out of range
+    (value >= i64::MIN as f64 && value < i64::MAX as f64).then_some(value as i64)
+}
+
 impl Value {
     pub fn ty(&self) -> Ty {
         match self {
             Value::Field(field_value) => Ty::Field(field_value.ty()),
             Value::Record { table, record: _ } => Ty::Table(table.clone()),
+            Value::Records { table, records: _ } => Ty::Records(table.clone()),
+        }
+    }
+
+    /// The field value, or the id of the record
+    pub fn into_field_or_id(self) -> Option<FieldValue> {
+        match self {
+            Value::Field(value) => Some(value),
+            Value::Record { table, record } => Some(FieldValue::RecordId {
+                id: record.id(),
+                table_name: table.name,
+            }),
+            Value::Records { .. } => None,
         }
     }
 }
@@ -70,3 +294,39 @@ impl From<FieldValue> for Value {
         Self::Field(value)
     }
 }
+
+impl Display for FieldValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FieldValue::Int(value) => write!(f, "{value}"),
+            FieldValue::I64(value) => write!(f, "{value}"),
+            FieldValue::F64(value) => write!(f, "{value}"),
+            FieldValue::Decimal(value) => write!(f, "{value}"),
+            FieldValue::Bool(value) => write!(f, "{value}"),
+            FieldValue::Timestamp(value) => write!(f, "{}", value.to_rfc3339()),
+            FieldValue::Duration(value) => f.write_str(&duration::format(value)),
+            FieldValue::Text(value) => write!(f, "{value:?}"),
+            FieldValue::RecordId { id, table_name } => write!(f, "{table_name}:{id}"),
+            FieldValue::Null(_) => f.write_str("null"),
+            FieldValue::Enum { index, .. } => match self.variant() {
+                Some(name) => write!(f, "{name:?}"),
+                None => write!(f, "<variant {index}>"),
+            },
+            FieldValue::List { values, .. } => {
+                f.write_str("[")?;
+
+                for (idx, value) in values.iter().enumerate() {
+                    if idx > 0 {
+                        f.write_str(", ")?;
+                    }
+
+                    write!(f, "{value}")?;
+                }
+
+                f.write_str("]")
+            }
+            FieldValue::Json(value) => write!(f, "{value}"),
+            FieldValue::Blob(value) => write!(f, "{value}"),
+        }
+    }
+}
@@ -34,7 +34,11 @@ fn main() {
 fn App() -> Element {
     // Build cool things ✌️
 
-    use_context_provider(|| db::Db::new("data/main.db").unwrap());
+    use_context_provider(|| {
+        let db = db::Db::new("data/main.db").unwrap();
+        views::migrate_db(&db);
+        db
+    });
 
     rsx! {
         // Global app resources
@@ -9,7 +9,7 @@ use db_core::{
 };
 use dioxus::prelude::*;
 use query_parse::parse_expr;
-use ui::{DataTable, value_to_string};
+use ui::{DataTable, RowTable, value_to_string};
 
 #[component]
 pub fn ExprPage() -> Element {
@@ -39,6 +39,18 @@ pub fn ExprPage() -> Element {
         }
     });
 
+    let plan = use_memo({
+        let db = db.clone();
+        move || {
+            let query = query()?;
+
+            Some(match db.explain(&query) {
+                Ok(plan) => plan.to_string(),
+                Err(err) => format!("ERROR: {err}"),
+            })
+        }
+    });
+
     use_effect(move || {
         if let Some(query) = query() {
             println!("updating store");
@@ -59,6 +71,10 @@ pub fn ExprPage() -> Element {
             onclick: move |_| text_value.set("query project group_by project.group".to_owned()),
             "query project group_by project.group"
         }
+        button {
+            onclick: move |_| text_value.set("query work_time group_by work_time.project select count()".to_owned()),
+            "query work_time group_by work_time.project select count()"
+        }
         div {
             "Expr: {expr:?}",
         }
@@ -68,6 +84,11 @@ pub fn ExprPage() -> Element {
         div {
             "Query: {query:?}",
         }
+        if let Some(plan) = plan() {
+            div {
+                "Plan: {plan}",
+            }
+        }
         if let Some(result) = query_result.transpose()
             && let Ok(result) = result.result().transpose()
         {
@@ -80,18 +101,32 @@ pub fn ExprPage() -> Element {
 
 #[component]
 pub fn QueryResultView(result: Store<QueryResult>) -> Element {
+    let db = use_context::<Db>();
+
     match result.transpose() {
         QueryResultStoreTransposed::Records(records) => {
+            let aggregates = records.read().aggregates.clone();
+
             rsx! {
+                if !aggregates.is_empty() {
+                    div {
+                        for aggregate in aggregates {
+                            span { "{aggregate.name}: {value_to_string(aggregate.value, &db)} " }
+                        }
+                    }
+                }
                 DataTable {
                     records: records,
                     table_name: "Foobar",
                 }
             }
         }
+        QueryResultStoreTransposed::Rows(rows) => {
+            rsx! {
+                RowTable { rows }
+            }
+        }
         QueryResultStoreTransposed::Grouped { groups } => {
-            let db = use_context::<Db>();
-
             rsx! {
                 for group in groups.iter() {
                     div {
@@ -1,10 +1,15 @@
 use std::sync::Arc;
 
+use bytepack::PackFormat;
 use db::Db;
 use db_core::{
-    defs::table::{TableDef, TableFieldDef},
+    defs::{
+        index::IndexOnDelete,
+        table::{AlterTableOp, TableDef, TableFieldDef},
+    },
     named::Named,
     ty::FieldTy,
+    value::FieldValue,
 };
 use dioxus::prelude::*;
 use ui::{
@@ -32,7 +37,9 @@ pub fn Home() -> Element {
     let on_submit = {
         let db = db.clone();
         move |table| {
-            db.register_table(table);
+            if let Err(err) = db.register_table(table) {
+                println!("ERROR: {err}");
+            }
             reload_idx.with_mut(|i| *i += 1);
         }
     };
@@ -44,7 +51,13 @@ pub fn Home() -> Element {
             let project_group_name = Arc::<str>::from("project_group");
             let project_name = Arc::<str>::from("project");
 
-            db.register_table(Named {
+            let register_table = |table| {
+                if let Err(err) = db.register_table(table) {
+                    println!("ERROR: {err}");
+                }
+            };
+
+            register_table(Named {
                 name: project_group_name.clone(),
                 value: TableDef {
                     fields: [Named::new(
@@ -52,14 +65,18 @@ pub fn Home() -> Element {
                         TableFieldDef {
                             ty: FieldTy::Text,
                             has_index: false,
+                            on_delete: IndexOnDelete::None,
+                            unique: false,
                         },
                     )]
                     .into(),
                     main_display_field: Some(0),
+                    unique: Vec::new(),
+                    indices: Vec::new(),
                 },
             });
 
-            db.register_table(Named {
+            register_table(Named {
                 name: project_name.clone(),
                 value: TableDef {
                     fields: [
@@ -68,24 +85,30 @@ pub fn Home() -> Element {
                             TableFieldDef {
                                 ty: FieldTy::Text,
                                 has_index: false,
+                                on_delete: IndexOnDelete::None,
+                                unique: true,
                             },
                         ),
                         Named::new(
                             "group",
                             TableFieldDef {
-                                ty: FieldTy::RecordId {
+                                ty: FieldTy::Nullable(Box::new(FieldTy::RecordId {
                                     table_name: project_group_name,
-                                },
+                                })),
                                 has_index: false,
+                                on_delete: IndexOnDelete::SetNone,
+                                unique: false,
                             },
                         ),
                     ]
                     .into(),
                     main_display_field: Some(0),
+                    unique: Vec::new(),
+                    indices: Vec::new(),
                 },
             });
 
-            db.register_table(Named {
+            register_table(Named {
                 name: "work_time".into(),
                 value: TableDef {
                     fields: [
@@ -96,6 +119,8 @@ pub fn Home() -> Element {
                                     table_name: project_name,
                                 },
                                 has_index: false,
+                                on_delete: IndexOnDelete::Cascase,
+                                unique: false,
                             },
                         ),
                         Named::new(
@@ -103,13 +128,18 @@ pub fn Home() -> Element {
                             TableFieldDef {
                                 ty: FieldTy::Timestamp,
                                 has_index: false,
+                                on_delete: IndexOnDelete::None,
+                                unique: false,
                             },
                         ),
                         Named::new(
                             "end_time",
                             TableFieldDef {
-                                ty: FieldTy::Timestamp,
+                                // Null while the timer is running
+                                ty: FieldTy::Nullable(Box::new(FieldTy::Timestamp)),
                                 has_index: false,
+                                on_delete: IndexOnDelete::None,
+                                unique: false,
                             },
                         ),
                         Named::new(
@@ -117,11 +147,15 @@ pub fn Home() -> Element {
                             TableFieldDef {
                                 ty: FieldTy::Text,
                                 has_index: false,
+                                on_delete: IndexOnDelete::None,
+                                unique: false,
                             },
                         ),
                     ]
                     .into(),
                     main_display_field: None,
+                    unique: Vec::new(),
+                    indices: vec![vec!["project".into(), "start_time".into()]],
                 },
             });
 
@@ -155,3 +189,25 @@ pub fn Home() -> Element {
         }
     }
 }
+
+/// Brings tables created by older versions of `Home`'s init up to date, runs once at startup
+pub fn migrate_db(db: &Db) {
+    // Databases created before running timers existed store `end_time` as a plain timestamp
+    let has_plain_end_time = db.table("work_time").is_some_and(|table| {
+        table
+            .field("end_time")
+            .is_some_and(|field| field.ty == FieldTy::Timestamp)
+    });
+
+    if has_plain_end_time {
+        let retype = AlterTableOp::RetypeField {
+            name: "end_time".into(),
+            ty: FieldTy::Nullable(Box::new(FieldTy::Timestamp)),
+            default: FieldValue::Null(FieldTy::Timestamp),
+        };
+
+        if let Err(err) = db.alter_table("work_time", [retype]) {
+            println!("ERROR: {err}");
+        }
+    }
+}
@@ -1,5 +1,5 @@
 mod home;
-pub use home::Home;
+pub use home::{Home, migrate_db};
 
 mod info;
 pub use info::Info;
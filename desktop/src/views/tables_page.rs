@@ -30,8 +30,9 @@ pub fn TablePage(name: String) -> Element {
 
     fn query_all_records(db: &Db, name: &str) -> Option<QueryResultRecords> {
         Some(QueryResultRecords {
-            records: db.get_all(name)?,
+            records: db.get_all(name).ok()?,
             format: Arc::new(db.table(name)?),
+            aggregates: Vec::new(),
         })
     }
 
@@ -82,7 +83,12 @@ pub fn TablePage(name: String) -> Element {
 
         move || {
             let name = table_name.peek();
-            db.delete_table(&name);
+
+            if let Err(err) = db.delete_table(&name) {
+                println!("ERROR: {err}");
+                return;
+            }
+
             nav.replace(Route::Home {});
         }
     };
@@ -93,7 +99,10 @@ pub fn TablePage(name: String) -> Element {
         let mut update_records = update_records.clone();
 
         move |record: RecordBytes| {
-            db.insert_record(&name(), &record).unwrap();
+            if let Err(err) = db.insert_record(&name(), &record) {
+                println!("ERROR: {err}");
+            }
+
             update_records();
         }
     };
@@ -104,7 +113,9 @@ pub fn TablePage(name: String) -> Element {
         let mut update_records = update_records.clone();
 
         move |id: Ulid| {
-            db.delete_record(&name(), id).unwrap();
+            if let Err(err) = db.delete_record(&name(), id) {
+                println!("ERROR: {err}");
+            }
 
             update_records()
         }
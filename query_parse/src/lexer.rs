@@ -4,10 +4,10 @@ use chumsky::{
     IterParser, Parser,
     error::Rich,
     extra,
-    prelude::{choice, just, none_of},
+    prelude::{any, choice, just, none_of, one_of},
     select,
     span::SimpleSpan,
-    text::{ident, whitespace},
+    text::{digits, ident, whitespace},
 };
 use db_core::expr::{CompareOp, EqOp, LogicOp};
 
@@ -33,16 +33,22 @@ pub fn lexer<'src>()
 
     let op = op.map(Token::Op);
 
-    // Num with float is currently not supported, because db currently does not have f32/f64 data types
-    // let num = chumsky::text::digits(10)
-    //     .then(just('.').then(digits(10)).or_not())
-    //     .to_slice()
-    //     .map(|slice| Token::Number(slice));
-
     let num = chumsky::text::int(10)
+        .then(just('.').then(digits(10)).or_not())
         .to_slice()
         .map(|slice| Token::Number(slice));
 
+    // A duration must not be followed by an identifier, so `2max` is no duration
+    let ident_char = any().filter(|c: &char| c.is_alphanumeric() || *c == '_');
+
+    let duration = chumsky::text::int(10)
+        .then(one_of("dhms"))
+        .repeated()
+        .at_least(1)
+        .to_slice()
+        .then_ignore(ident_char.not())
+        .map(Token::Duration);
+
     let string_escape = just('\\').then(choice([
         just('\\'),
         just('"'),
@@ -69,7 +75,12 @@ pub fn lexer<'src>()
         }
     });
 
-    let token = ident.or(op).or(num).or(string_literal).or(separator);
+    let token = ident
+        .or(op)
+        .or(duration)
+        .or(num)
+        .or(string_literal)
+        .or(separator);
 
     token.padded_by(whitespace()).repeated().collect()
 }
@@ -25,14 +25,55 @@ pub fn parse_expr(input: &str) -> Option<Expr> {
 
 #[cfg(test)]
 mod tests {
+    use chrono::{DateTime, TimeDelta};
     use db_core::{
-        expr::{BinaryOp, CompareOp, EqOp, EvalCtx, Expr, MathOp, TyCtx},
+        decimal::Decimal,
+        expr::{
+            AggregateFn, BinaryOp, CompareOp, EqOp, EvalCtx, EvalErr, Expr, LogicOp, MathOp, TyCtx,
+            UnaryOp,
+        },
+        float::Float,
+        named::Named,
+        query::{Join, OnError, OrderBy, SortDirection},
         ty::{FieldTy, Ty},
         value::{FieldValue, Value},
     };
 
     use super::*;
 
+    /// Parses `input` and checks that it has the type of `expected` and evaluates to it
+    #[track_caller]
+    fn assert_eval(input: &str, expected: FieldValue) {
+        let expr = parse_expr(input).unwrap();
+
+        assert_eval_expr(&EvalCtx::default(), &expr, expected);
+    }
+
+    /// Parses `input` and checks that it type checks but fails to evaluate
+    #[track_caller]
+    fn assert_eval_err(input: &str) -> EvalErr {
+        let expr = parse_expr(input).unwrap();
+
+        assert!(expr.ty(&TyCtx::default()).is_ok(), "{input}");
+
+        expr.eval(&EvalCtx::default()).unwrap_err()
+    }
+
+    /// Like `assert_eval`, for an expression evaluated with `eval_ctx`
+    #[track_caller]
+    fn assert_eval_expr(eval_ctx: &EvalCtx, expr: &Expr, expected: FieldValue) {
+        assert_eq!(
+            expr.ty(&TyCtx::default()).ok(),
+            Some(Ty::Field(expected.ty())),
+            "{expr}"
+        );
+        assert_eq!(
+            expr.eval(eval_ctx).ok(),
+            Some(Value::Field(expected)),
+            "{expr}"
+        );
+    }
+
     #[test]
     fn test_parse() {
         let input = "query user where 5 < 10 * 4 + 2 == true";
@@ -59,17 +100,23 @@ mod tests {
 
         let value = Query {
             table_name: "user".into(),
+            joins: Vec::new(),
             filter: Some(expr),
-            group_by: None
+            group_by: None,
+            select: Vec::new(),
+            order_by: Vec::new(),
+            limit: None,
+            offset: None,
+            on_error: OnError::Skip,
         };
 
         assert_eq!(query, value);
 
-        let ty_ctx = TyCtx { tables: Default::default() };
+        let ty_ctx = TyCtx::default();
         let eval_ctx = EvalCtx::default();
 
         assert_eq!(
-            query.filter.as_ref().and_then(|filter| filter.ty(&ty_ctx)),
+            query.filter.as_ref().and_then(|filter| filter.ty(&ty_ctx).ok()),
             Some(Ty::Field(FieldTy::Bool))
         );
         assert_eq!(
@@ -78,6 +125,674 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_order_limit_offset() {
+        let input = "query user order_by user.age desc, user.name limit 10 offset 20 on_error fail";
+
+        let query = parse(input).unwrap();
+
+        let field = |name: &str| Expr::FieldAccess {
+            value: Box::new(Expr::TableAccess { name: "user".into() }),
+            field: name.into(),
+        };
+
+        let value = Query {
+            table_name: "user".into(),
+            joins: Vec::new(),
+            filter: None,
+            group_by: None,
+            select: Vec::new(),
+            order_by: vec![
+                OrderBy {
+                    expr: field("age"),
+                    direction: SortDirection::Desc,
+                },
+                OrderBy {
+                    expr: field("name"),
+                    direction: SortDirection::Asc,
+                },
+            ],
+            limit: Some(10),
+            offset: Some(20),
+            on_error: OnError::Fail,
+        };
+
+        assert_eq!(query, value);
+
+        // The words of the clauses are only special after an expression, fields can use them
+        let query =
+            parse("query user order_by user.limit desc, user.desc limit 1 offset 2").unwrap();
+
+        assert_eq!(
+            query.order_by,
+            [
+                OrderBy {
+                    expr: field("limit"),
+                    direction: SortDirection::Desc,
+                },
+                OrderBy {
+                    expr: field("desc"),
+                    direction: SortDirection::Asc,
+                },
+            ]
+        );
+        assert_eq!((query.limit, query.offset), (Some(1), Some(2)));
+    }
+
+    #[test]
+    fn test_parse_select() {
+        let query =
+            parse("query task select task.as as as, task.hours, count() as limit limit 5").unwrap();
+
+        let field = |name: &str| Expr::FieldAccess {
+            value: Box::new(Expr::TableAccess { name: "task".into() }),
+            field: name.into(),
+        };
+
+        // Without `as` the expression is the name
+        assert_eq!(
+            query.select,
+            [
+                Named::new("as", field("as")),
+                Named::new("task.hours", field("hours")),
+                Named::new(
+                    "limit",
+                    Expr::Aggregate {
+                        func: AggregateFn::Count,
+                        arg: None,
+                    },
+                ),
+            ]
+        );
+        assert_eq!(query.limit, Some(5));
+    }
+
+    #[test]
+    fn test_parse_join() {
+        let input = "query work_time join project on work_time.project == project where project.group.name == \"a\"";
+
+        let query = parse(input).unwrap();
+
+        let access = |table: &str| Expr::TableAccess { name: table.into() };
+        let field = |value: Expr, name: &str| Expr::FieldAccess {
+            value: Box::new(value),
+            field: name.into(),
+        };
+
+        let value = Query {
+            table_name: "work_time".into(),
+            joins: vec![Join {
+                table_name: "project".into(),
+                on: Expr::BinaryOp {
+                    a: Box::new(field(access("work_time"), "project")),
+                    op: BinaryOp::Eq(EqOp::Eq),
+                    b: Box::new(access("project")),
+                },
+            }],
+            filter: Some(Expr::BinaryOp {
+                a: Box::new(field(field(access("project"), "group"), "name")),
+                op: BinaryOp::Eq(EqOp::Eq),
+                b: Box::new(Expr::Literal(FieldValue::Text("a".into()))),
+            }),
+            group_by: None,
+            select: Vec::new(),
+            order_by: Vec::new(),
+            limit: None,
+            offset: None,
+            on_error: OnError::Skip,
+        };
+
+        assert_eq!(query, value);
+
+        // `on` is only special after the joined table
+        let query = parse("query task join on on task.on == on").unwrap();
+
+        assert_eq!(
+            query.joins,
+            [Join {
+                table_name: "on".into(),
+                on: Expr::BinaryOp {
+                    a: Box::new(field(access("task"), "on")),
+                    op: BinaryOp::Eq(EqOp::Eq),
+                    b: Box::new(access("on")),
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_referenced_by() {
+        let expr = parse_expr("count(project_group.referenced_by(project.group).name)").unwrap();
+
+        let value = Expr::Aggregate {
+            func: AggregateFn::Count,
+            arg: Some(Box::new(Expr::FieldAccess {
+                value: Box::new(Expr::ReferencedBy {
+                    value: Box::new(Expr::TableAccess {
+                        name: "project_group".into(),
+                    }),
+                    table_name: "project".into(),
+                    field_name: "group".into(),
+                }),
+                field: "name".into(),
+            })),
+        };
+
+        assert_eq!(expr, value);
+        assert_eq!(
+            expr.to_string(),
+            "count(project_group.referenced_by(project.group).name)"
+        );
+    }
+
+    #[test]
+    fn test_fn_call() {
+        let ty_ctx = TyCtx::default();
+        let eval_ctx = EvalCtx::default();
+
+        let expr = parse_expr("str_len(\"abc\") + 1").unwrap();
+
+        assert_eq!(expr.ty(&ty_ctx).ok(), Some(Ty::Field(FieldTy::IntI32)));
+        assert_eq!(
+            expr.eval(&eval_ctx).ok(),
+            Some(Value::Field(FieldValue::Int(4)))
+        );
+
+        let expr = parse_expr("str_len(1)").unwrap();
+
+        assert!(matches!(
+            expr.ty(&ty_ctx),
+            Err(EvalErr::MissmatchedTypes { .. })
+        ));
+        assert!(matches!(
+            expr.eval(&eval_ctx),
+            Err(EvalErr::MissmatchedTypes { .. })
+        ));
+
+        let expr = parse_expr("str_len(\"a\", \"b\")").unwrap();
+
+        assert!(matches!(
+            expr.ty(&ty_ctx),
+            Err(EvalErr::InvalidFunctionArgCount {
+                found: 2,
+                expected: 1,
+                ..
+            })
+        ));
+
+        let expr = parse_expr("strlen(\"a\")").unwrap();
+
+        assert!(matches!(
+            expr.eval(&eval_ctx),
+            Err(EvalErr::UnknownFunction { .. })
+        ));
+    }
+
+    #[test]
+    fn test_text_functions() {
+        let ty_ctx = TyCtx::default();
+        let eval_ctx = EvalCtx::default();
+
+        let text = |value: &str| FieldValue::Text(value.into());
+
+        assert_eval("str_len(\"äöü\")", FieldValue::Int(3));
+        assert_eval("lower(\"AbC\")", text("abc"));
+        assert_eval("upper(\"AbC\")", text("ABC"));
+        assert_eval("trim(\"  a b  \")", text("a b"));
+        assert_eval("contains(\"project\", \"roj\")", FieldValue::Bool(true));
+        assert_eval("contains(\"project\", \"Roj\")", FieldValue::Bool(false));
+        assert_eval("starts_with(\"project\", \"pro\")", FieldValue::Bool(true));
+        assert_eval("ends_with(\"project\", \"pro\")", FieldValue::Bool(false));
+        assert_eval("substr(\"project\", 2, 3)", text("roj"));
+        assert_eval("substr(\"äöü\", 2, 10)", text("öü"));
+        assert_eval("substr(\"project\", 10, 3)", text(""));
+        assert_eval("replace(\"a-b-c\", \"-\", \", \")", text("a, b, c"));
+        assert_eval("concat(\"a\", concat(\"b\", \"c\"))", text("abc"));
+        assert_eval("split_part(\"a,b,c\", \",\", 2)", text("b"));
+        assert_eval("split_part(\"a,b,c\", \",\", 4)", text(""));
+        assert_eval("like(\"Project\", \"pro%\")", FieldValue::Bool(true));
+        assert_eval("like(\"Project\", \"%J_CT\")", FieldValue::Bool(true));
+        assert_eval("like(\"Project\", \"%j_t\")", FieldValue::Bool(false));
+        assert_eval("like(\"aXbXc\", \"a%b%c\")", FieldValue::Bool(true));
+        assert_eval("like(\"\", \"%\")", FieldValue::Bool(true));
+
+        let expr = parse_expr("substr(\"project\", \"2\", 3)").unwrap();
+
+        assert!(matches!(
+            expr.ty(&ty_ctx),
+            Err(EvalErr::MissmatchedTypes { .. })
+        ));
+
+        let expr = parse_expr("split_part(\"a,b\", \",\")").unwrap();
+
+        assert!(matches!(
+            expr.eval(&eval_ctx),
+            Err(EvalErr::InvalidFunctionArgCount {
+                found: 2,
+                expected: 3,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_date_time() {
+        let now = DateTime::parse_from_rfc3339("2024-03-14T15:09:26Z")
+            .unwrap()
+            .to_utc();
+
+        let ty_ctx = TyCtx::default();
+        let eval_ctx = EvalCtx {
+            now,
+            ..Default::default()
+        };
+
+        let at = |value: &str| {
+            FieldValue::Timestamp(DateTime::parse_from_rfc3339(value).unwrap().to_utc())
+        };
+        let duration = |seconds| FieldValue::Duration(TimeDelta::seconds(seconds));
+
+        let expr = parse_expr("1h30m").unwrap();
+
+        assert_eq!(expr, Expr::Literal(duration(5400)));
+        assert_eq!(expr.to_string(), "1h30m");
+
+        // Evaluated at the fixed `now`
+        let assert_eval = |input: &str, expected: FieldValue| {
+            assert_eval_expr(&eval_ctx, &parse_expr(input).unwrap(), expected);
+        };
+
+        assert_eval("now() - (now() - 90m)", duration(5400));
+        assert_eval("2h + 30m - 15s", duration(8985));
+        assert_eval("2 * 1d / 4", duration(43200));
+        assert_eval("-2h", duration(-7200));
+        assert_eval("now() + 1d", at("2024-03-15T15:09:26Z"));
+        assert_eval("30m + now()", at("2024-03-14T15:39:26Z"));
+        assert_eval("now() - 1h > now()", FieldValue::Bool(false));
+        assert_eval("hours(now() - (now() - 150m))", FieldValue::Int(2));
+        assert_eval("minutes(1d)", FieldValue::Int(1440));
+        assert_eval("year(now())", FieldValue::Int(2024));
+        assert_eval("month(now())", FieldValue::Int(3));
+        assert_eval("day(now())", FieldValue::Int(14));
+        assert_eval("weekday(now())", FieldValue::Int(4));
+        assert_eval("date_trunc(\"year\", now())", at("2024-01-01T00:00:00Z"));
+        assert_eval("date_trunc(\"month\", now())", at("2024-03-01T00:00:00Z"));
+        assert_eval("date_trunc(\"week\", now())", at("2024-03-11T00:00:00Z"));
+        assert_eval("date_trunc(\"day\", now())", at("2024-03-14T00:00:00Z"));
+        assert_eval("date_trunc(\"hour\", now())", at("2024-03-14T15:00:00Z"));
+        assert_eval("date_trunc(\"minute\", now())", at("2024-03-14T15:09:00Z"));
+
+        let expr = parse_expr("now() + now()").unwrap();
+
+        assert!(matches!(
+            expr.ty(&ty_ctx),
+            Err(EvalErr::InvalidTypeForBinaryOp { .. })
+        ));
+
+        let expr = parse_expr("date_trunc(\"decade\", now())").unwrap();
+
+        assert!(matches!(
+            expr.eval(&eval_ctx),
+            Err(EvalErr::UnknownDateUnit { .. })
+        ));
+
+        // The number of whole units must fit into an `IntI32`
+        assert_eval("hours(-150m)", FieldValue::Int(-2));
+        assert!(matches!(
+            assert_eval_err("minutes(1d * 2000000)"),
+            EvalErr::InvalidCast { .. }
+        ));
+        assert!(matches!(
+            assert_eval_err("hours(1d * 100000000)"),
+            EvalErr::InvalidCast { .. }
+        ));
+    }
+
+    #[test]
+    fn test_numbers() {
+        let eval_ctx = EvalCtx::default();
+
+        let float = |value| FieldValue::F64(Float(value));
+        let decimal = |value: &str| FieldValue::Decimal(value.parse::<Decimal>().unwrap());
+
+        let expr = parse_expr("2.5").unwrap();
+
+        assert_eq!(expr, Expr::Literal(float(2.5)));
+        assert_eq!(expr.to_string(), "2.5");
+
+        assert_eval("3000000000", FieldValue::I64(3_000_000_000));
+        assert_eval("3000000000 - 1", FieldValue::I64(2_999_999_999));
+        assert_eval("7 / 2", FieldValue::Int(3));
+        assert_eval("1 + 2.5", float(3.5));
+        assert_eval("-0.5 * 3", float(-1.5));
+        assert_eval("1.5 > 1", FieldValue::Bool(true));
+        assert_eval("2 == 2.0", FieldValue::Bool(true));
+        assert_eval("to_decimal(1) / 3", decimal("0.3333"));
+        assert_eval("to_decimal(\"2.50\") * 2", decimal("5"));
+        assert_eval("to_decimal(\"0.1\") + 0.2", float(0.30000000000000004));
+        assert_eval("to_i32(-2.9)", FieldValue::Int(-2));
+        assert_eval("to_i64(\" 42 \")", FieldValue::I64(42));
+        assert_eval("to_f64(to_decimal(\"1.25\"))", float(1.25));
+        assert_eval("to_text(1.25)", FieldValue::Text("1.25".into()));
+        assert_eval("to_text(1h30m)", FieldValue::Text("1h30m".into()));
+
+        let expr = parse_expr("to_i32(3000000000)").unwrap();
+
+        assert!(matches!(
+            expr.eval(&eval_ctx),
+            Err(EvalErr::InvalidCast { .. })
+        ));
+
+        let expr = parse_expr("to_f64(\"abc\")").unwrap();
+
+        assert!(matches!(
+            expr.eval(&eval_ctx),
+            Err(EvalErr::InvalidCast { .. })
+        ));
+    }
+
+    #[test]
+    fn test_checked_math() {
+        let division_by_zero =
+            |input| matches!(assert_eval_err(input), EvalErr::DivisionByZero { .. });
+        let overflow = |input| matches!(assert_eval_err(input), EvalErr::Overflow { .. });
+
+        assert!(division_by_zero("1 / 0"));
+        assert!(division_by_zero("5 / (2 - 2)"));
+        assert!(division_by_zero("to_decimal(1) / 0"));
+        assert!(division_by_zero("1.5 / 0"));
+        assert!(overflow("2147483647 + 1"));
+        assert!(overflow("-2147483647 - 2"));
+        assert!(overflow("65536 * 65536"));
+        assert!(overflow("-(-2147483647 - 1)"));
+        assert!(overflow("9223372036854775807 + 1"));
+        assert!(overflow("1d * 2147483647 * 2147483647"));
+
+        let EvalErr::DivisionByZero { a, b } = assert_eval_err("7 / (1 - 1)") else {
+            panic!("expected division by zero");
+        };
+
+        assert_eq!((*a, *b), (FieldValue::Int(7), FieldValue::Int(0)));
+        assert_eq!(
+            assert_eval_err("2147483647 + 1").to_string(),
+            "2147483647 + 1 overflows"
+        );
+
+        // The narrower operand is converted before the operation
+        assert_eval("2147483647 + 3000000000", FieldValue::I64(5_147_483_647));
+    }
+
+    #[test]
+    fn test_null() {
+        let ty_ctx = TyCtx::default();
+        let eval_ctx = EvalCtx::default();
+
+        let expr = parse_expr("1 + 2 is not null && coalesce(3, 4) is null").unwrap();
+
+        assert_eq!(
+            expr,
+            Expr::BinaryOp {
+                a: Box::new(Expr::UnaryOp {
+                    op: UnaryOp::IsNotNull,
+                    value: Box::new(Expr::BinaryOp {
+                        a: Box::new(Expr::Literal(FieldValue::Int(1))),
+                        op: BinaryOp::Math(MathOp::Add),
+                        b: Box::new(Expr::Literal(FieldValue::Int(2))),
+                    }),
+                }),
+                op: BinaryOp::Logic(LogicOp::And),
+                b: Box::new(Expr::UnaryOp {
+                    op: UnaryOp::IsNull,
+                    value: Box::new(Expr::Coalesce {
+                        args: vec![
+                            Expr::Literal(FieldValue::Int(3)),
+                            Expr::Literal(FieldValue::Int(4)),
+                        ],
+                    }),
+                }),
+            }
+        );
+        assert_eq!(parse_expr(&expr.to_string()), Some(expr));
+        assert!(parse_expr("coalesce()").unwrap().ty(&ty_ctx).is_err());
+
+        // `null` is only special after `is`, elsewhere it is a name
+        assert_eq!(
+            parse_expr("task.null is null"),
+            Some(Expr::UnaryOp {
+                op: UnaryOp::IsNull,
+                value: Box::new(Expr::FieldAccess {
+                    value: Box::new(Expr::TableAccess { name: "task".into() }),
+                    field: "null".into(),
+                }),
+            })
+        );
+
+        // There is no null literal, a null only comes from a field
+        let null = |ty| Expr::Literal(FieldValue::Null(ty));
+        let literal = Expr::Literal;
+        let binary = |a, op, b| Expr::BinaryOp {
+            a: Box::new(a),
+            op,
+            b: Box::new(b),
+        };
+        let unary = |op, value| Expr::UnaryOp {
+            op,
+            value: Box::new(value),
+        };
+        let null_int = || null(FieldTy::IntI32);
+        let null_bool = || null(FieldTy::Bool);
+
+        let assert_eval = |expr: Expr, expected: FieldValue| {
+            assert_eval_expr(&eval_ctx, &expr, expected);
+        };
+
+        assert_eval(
+            binary(
+                null_int(),
+                BinaryOp::Math(MathOp::Add),
+                literal(FieldValue::Int(1)),
+            ),
+            FieldValue::Null(FieldTy::IntI32),
+        );
+        assert_eval(
+            binary(null_int(), BinaryOp::Eq(EqOp::Eq), null_int()),
+            FieldValue::Null(FieldTy::Bool),
+        );
+        assert_eval(
+            binary(
+                literal(FieldValue::Int(1)),
+                BinaryOp::Compare(CompareOp::Less),
+                null_int(),
+            ),
+            FieldValue::Null(FieldTy::Bool),
+        );
+        assert_eval(
+            unary(UnaryOp::Negate, null_int()),
+            FieldValue::Null(FieldTy::IntI32),
+        );
+        assert_eval(
+            unary(UnaryOp::LogicNot, null_bool()),
+            FieldValue::Null(FieldTy::Bool),
+        );
+        assert_eval(
+            binary(
+                null_bool(),
+                BinaryOp::Logic(LogicOp::And),
+                literal(FieldValue::Bool(true)),
+            ),
+            FieldValue::Null(FieldTy::Bool),
+        );
+        assert_eval(
+            binary(
+                literal(FieldValue::Bool(false)),
+                BinaryOp::Logic(LogicOp::Or),
+                null_bool(),
+            ),
+            FieldValue::Null(FieldTy::Bool),
+        );
+        assert_eval(unary(UnaryOp::IsNull, null_int()), FieldValue::Bool(true));
+        assert_eval(
+            unary(UnaryOp::IsNotNull, null_int()),
+            FieldValue::Bool(false),
+        );
+        assert_eval(
+            unary(UnaryOp::IsNull, literal(FieldValue::Int(0))),
+            FieldValue::Bool(false),
+        );
+        assert_eval(
+            Expr::Coalesce {
+                args: vec![null_int(), null_int(), literal(FieldValue::Int(3))],
+            },
+            FieldValue::Int(3),
+        );
+        assert_eval(
+            Expr::Coalesce {
+                args: vec![null_int(), null_int()],
+            },
+            FieldValue::Null(FieldTy::IntI32),
+        );
+
+        // A nullable operand makes the type nullable, even if the value is known anyway
+        let nullable_bool = Some(Ty::Field(FieldTy::Nullable(Box::new(FieldTy::Bool))));
+        let and_false = binary(
+            null_bool(),
+            BinaryOp::Logic(LogicOp::And),
+            literal(FieldValue::Bool(false)),
+        );
+        let or_true = binary(
+            literal(FieldValue::Bool(true)),
+            BinaryOp::Logic(LogicOp::Or),
+            null_bool(),
+        );
+
+        assert_eq!(and_false.ty(&ty_ctx).ok(), nullable_bool);
+        assert_eq!(
+            and_false.eval(&eval_ctx).ok(),
+            Some(Value::Field(FieldValue::Bool(false)))
+        );
+        assert_eq!(or_true.ty(&ty_ctx).ok(), nullable_bool);
+        assert_eq!(
+            or_true.eval(&eval_ctx).ok(),
+            Some(Value::Field(FieldValue::Bool(true)))
+        );
+
+        // The result is only nullable if every argument is
+        let expr = Expr::Coalesce {
+            args: vec![null_int(), literal(FieldValue::Int(3))],
+        };
+
+        assert_eq!(expr.ty(&ty_ctx).ok(), Some(Ty::Field(FieldTy::IntI32)));
+
+        let expr = Expr::Coalesce {
+            args: vec![null_int(), literal(FieldValue::Text("3".into()))],
+        };
+
+        assert!(expr.ty(&ty_ctx).is_err());
+    }
+
+    #[test]
+    fn test_list() {
+        let text = |value: &str| FieldValue::Text(value.into());
+        let tags = FieldValue::List {
+            ty: Box::new(FieldTy::Text),
+            values: vec![text("bug"), text("ui")],
+        };
+
+        // The list is a variable, which is bound to its value for evaluation
+        let ty_ctx = TyCtx {
+            vars: [("tags".into(), tags.ty())].into(),
+            ..Default::default()
+        };
+        let eval_ctx = EvalCtx::default();
+
+        let expr = parse_expr("any(tag in tags, tag == \"bug\")").unwrap();
+
+        assert_eq!(
+            expr,
+            Expr::Any {
+                var: "tag".into(),
+                list: Box::new(Expr::TableAccess {
+                    name: "tags".into()
+                }),
+                predicate: Box::new(Expr::BinaryOp {
+                    a: Box::new(Expr::TableAccess { name: "tag".into() }),
+                    op: BinaryOp::Eq(EqOp::Eq),
+                    b: Box::new(Expr::Literal(text("bug"))),
+                }),
+            }
+        );
+        assert_eq!(parse_expr(&expr.to_string()), Some(expr));
+
+        // `in` is only special after the variable, which may be called `in` as well
+        let expr = parse_expr("any(in in tags, in == \"bug\")").unwrap();
+
+        assert!(matches!(&expr, Expr::Any { var, .. } if var.as_ref() == "in"));
+        assert_eq!(
+            expr.bind("tags", &tags).eval(&eval_ctx).ok(),
+            Some(Value::Field(FieldValue::Bool(true)))
+        );
+
+        assert_eq!(
+            parse_expr("len(tags)").unwrap().ty(&ty_ctx).ok(),
+            Some(Ty::Field(FieldTy::IntI32))
+        );
+
+        let assert_eval = |input: &str, expected: FieldValue| {
+            let expr = parse_expr(input).unwrap().bind("tags", &tags);
+
+            assert_eval_expr(&eval_ctx, &expr, expected);
+        };
+
+        assert_eval("any(tag in tags, tag == \"bug\")", FieldValue::Bool(true));
+        assert_eval(
+            "any(tag in tags, starts_with(tag, \"x\"))",
+            FieldValue::Bool(false),
+        );
+        assert_eval("contains(tags, \"ui\")", FieldValue::Bool(true));
+        assert_eval("contains(tags, \"x\")", FieldValue::Bool(false));
+        assert_eval("contains(\"tags\", \"a\")", FieldValue::Bool(true));
+        assert_eval("len(tags)", FieldValue::Int(2));
+
+        // A null predicate result is unknown, so no match is only false without one
+        let flags = |values: [FieldValue; 2]| FieldValue::List {
+            ty: Box::new(FieldTy::Nullable(Box::new(FieldTy::Bool))),
+            values: values.to_vec(),
+        };
+        let expr = parse_expr("any(flag in flags, flag)").unwrap();
+
+        for (values, expected) in [
+            (
+                [FieldValue::Null(FieldTy::Bool), FieldValue::Bool(false)],
+                None,
+            ),
+            (
+                [FieldValue::Null(FieldTy::Bool), FieldValue::Bool(true)],
+                Some(true),
+            ),
+        ] {
+            let expr = expr.bind("flags", &flags(values));
+
+            assert_eq!(
+                expr.ty(&TyCtx::default()).ok(),
+                Some(Ty::Field(FieldTy::Nullable(Box::new(FieldTy::Bool))))
+            );
+            assert_eq!(
+                expr.eval(&eval_ctx).ok(),
+                Some(Value::Field(
+                    expected.map_or(FieldValue::Null(FieldTy::Bool), FieldValue::Bool)
+                ))
+            );
+        }
+
+        // Elements are compared with the value and the predicate must be a condition
+        for input in ["contains(tags, 1)", "any(tag in tags, tag)"] {
+            assert!(matches!(
+                parse_expr(input).unwrap().ty(&ty_ctx),
+                Err(EvalErr::MissmatchedTypes { .. })
+            ));
+        }
+    }
+
     #[test]
     fn dbg_parse() {
         let input = "query user where user.age > 10";
@@ -1,12 +1,15 @@
 use chumsky::{
-    IterParser, Parser, error::Rich, extra, pratt::{infix, left, prefix}, prelude::{choice, just, recursive}, select, span::SimpleSpan
+    IterParser, Parser, error::Rich, extra, pratt::{infix, left, postfix, prefix}, prelude::{choice, just, recursive}, select, span::SimpleSpan
 };
 use db_core::{
-    expr::{BinaryOp, Expr, MathOp, UnaryOp},
+    duration,
+    float::Float,
+    expr::{AggregateFn, BinaryOp, Expr, MathOp, UnaryOp},
+    named::Named,
     value::FieldValue,
 };
 
-use db_core::query::Query;
+use db_core::query::{Join, OnError, OrderBy, Query, SortDirection};
 
 use crate::token::{Keyword, Op, Separator, Token};
 
@@ -19,18 +22,93 @@ pub fn parser<'token, 'src: 'token>()
 
     let expr = parse_expr();
 
+    let join = just(Token::Keyword(Keyword::Join))
+        .ignore_then(ident)
+        .then_ignore(just(Token::Ident("on")))
+        .then(expr.clone())
+        .map(|(table_name, on)| Join {
+            table_name: table_name.into(),
+            on,
+        });
+
     let filter = just(Token::Keyword(Keyword::Where)).ignore_then(expr.clone());
-    let group = just(Token::Keyword(Keyword::GroupBy)).ignore_then(expr);
+    let group = just(Token::Keyword(Keyword::GroupBy)).ignore_then(expr.clone());
+    // Without a name the expression itself is used as name
+    let select_item = expr
+        .clone()
+        .then(just(Token::Ident("as")).ignore_then(ident).or_not())
+        .map(|(expr, name)| match name {
+            Some(name) => Named::new(name, expr),
+            None => Named::new(expr.to_string(), expr),
+        });
+
+    let select = just(Token::Keyword(Keyword::Select)).ignore_then(
+        select_item
+            .separated_by(just(Token::Separator(Separator::Comma)))
+            .at_least(1)
+            .collect::<Vec<_>>(),
+    );
+
+    let direction = select! {
+        Token::Ident("asc") => SortDirection::Asc,
+        Token::Ident("desc") => SortDirection::Desc,
+    };
+
+    let order_key = expr
+        .then(direction.or_not())
+        .map(|(expr, direction)| OrderBy {
+            expr,
+            direction: direction.unwrap_or_default(),
+        });
+
+    let order = just(Token::Keyword(Keyword::OrderBy)).ignore_then(
+        order_key
+            .separated_by(just(Token::Separator(Separator::Comma)))
+            .at_least(1)
+            .collect::<Vec<_>>(),
+    );
+
+    let count = select! {
+        Token::Number(num) => num,
+    }
+    .try_map(|num, span| {
+        num.parse::<usize>()
+            .map_err(|_| Rich::custom(span, "Invalid count"))
+    });
+
+    let limit = just(Token::Ident("limit")).ignore_then(count);
+    let offset = just(Token::Ident("offset")).ignore_then(count);
+
+    let on_error = just(Token::Keyword(Keyword::OnError)).ignore_then(select! {
+        Token::Ident("skip") => OnError::Skip,
+        Token::Ident("fail") => OnError::Fail,
+    });
 
     just(Token::Keyword(Keyword::Query))
         .ignore_then(ident)
+        .then(join.repeated().collect::<Vec<_>>())
         .then(filter.or_not())
         .then(group.or_not())
-        .map(|((name, filter), group)| Query {
-            table_name: name.into(),
-            filter,
-            group_by: group
-        })
+        .then(select.or_not())
+        .then(order.or_not())
+        .then(limit.or_not())
+        .then(offset.or_not())
+        .then(on_error.or_not())
+        .map(
+            |((((((((name, joins), filter), group), select), order), limit), offset), on_error)| {
+                Query {
+                    table_name: name.into(),
+                    joins,
+                    filter,
+                    group_by: group,
+                    select: select.unwrap_or_default(),
+                    order_by: order.unwrap_or_default(),
+                    limit,
+                    offset,
+                    on_error: on_error.unwrap_or_default(),
+                }
+            },
+        )
 }
 
 pub fn parse_expr<'token, 'src: 'token>()
@@ -45,18 +123,66 @@ pub fn parse_expr<'token, 'src: 'token>()
                     just(Token::Separator(Separator::ParenOpen)),
                     just(Token::Separator(Separator::ParenClose)),
                 ),
-        ).map(|(name, args)| {
-            Expr::FnCall { name: name.into(), args }
+        ).try_map(|(name, mut args): (&str, Vec<Expr>), span| {
+            if name == "coalesce" && !args.is_empty() {
+                return Ok(Expr::Coalesce { args });
+            }
+
+            let Some(func) = AggregateFn::from_name(name) else {
+                return Ok(Expr::FnCall { name: name.into(), args });
+            };
+
+            // `count` is the only aggregate function that can be called without argument
+            match (func, args.len()) {
+                (AggregateFn::Count, 0) => Ok(Expr::Aggregate { func, arg: None }),
+                (_, 1) => Ok(Expr::Aggregate { func, arg: args.pop().map(Box::new) }),
+                _ => Err(Rich::custom(span, format!("Invalid argument count for '{name}'"))),
+            }
+        });
+
+        // `any(var in list, predicate)`
+        let any = just(Token::Ident("any")).ignore_then(
+            select! { Token::Ident(ident) => ident }
+                .then_ignore(just(Token::Ident("in")))
+                .then(expr.clone())
+                .then_ignore(just(Token::Separator(Separator::Comma)))
+                .then(expr.clone())
+                .delimited_by(
+                    just(Token::Separator(Separator::ParenOpen)),
+                    just(Token::Separator(Separator::ParenClose)),
+                ),
+        )
+        .map(|((var, list), predicate)| Expr::Any {
+            var: var.into(),
+            list: Box::new(list),
+            predicate: Box::new(predicate),
         });
 
         let num = select! {
             Token::Number(num) => num,
         }
-        .try_map(|num, span| {
-            if let Ok(value) = num.parse() {
-                Ok(FieldValue::Int(value))
+        .try_map(|num: &str, span| {
+            // Integers that do not fit into `i32` are `i64`
+            let value = if num.contains('.') {
+                num.parse().ok().map(|value| FieldValue::F64(Float(value)))
+            } else {
+                num.parse()
+                    .map(FieldValue::Int)
+                    .or_else(|_| num.parse().map(FieldValue::I64))
+                    .ok()
+            };
+
+            value.ok_or_else(|| Rich::custom(span, "Invalid number"))
+        });
+
+        let duration = select! {
+            Token::Duration(duration) => duration,
+        }
+        .try_map(|duration, span| {
+            if let Some(value) = duration::parse(duration) {
+                Ok(FieldValue::Duration(value))
             } else {
-                Err(Rich::custom(span, "Invalid integer"))
+                Err(Rich::custom(span, "Invalid duration"))
             }
         });
 
@@ -73,7 +199,12 @@ pub fn parse_expr<'token, 'src: 'token>()
             Token::Ident(ident) => Expr::TableAccess { name: ident.into() }
         };
 
-        let literal = num.or(boolean).or(string).map(Expr::Literal).or(table_ident);
+        let literal = duration
+            .or(num)
+            .or(boolean)
+            .or(string)
+            .map(Expr::Literal)
+            .or(table_ident);
 
         let paren_expr = expr.delimited_by(
             just(Token::Separator(Separator::ParenOpen)),
@@ -81,20 +212,52 @@ pub fn parse_expr<'token, 'src: 'token>()
         );
 
         let atom = choice((
+            any,
             fn_call,
             literal,
             paren_expr
         ));
 
+        let ident = select! {
+            Token::Ident(ident) => ident
+        };
+
+        // `value.referenced_by(table.field)`
+        let referenced_by = just(Token::Keyword(Keyword::ReferencedBy)).ignore_then(
+            ident
+                .then_ignore(just(Token::Separator(Separator::Dot)))
+                .then(ident)
+                .delimited_by(
+                    just(Token::Separator(Separator::ParenOpen)),
+                    just(Token::Separator(Separator::ParenClose)),
+                ),
+        );
+
+        let access = choice((
+            referenced_by.map(|(table_name, field_name)| Access::ReferencedBy {
+                table_name,
+                field_name,
+            }),
+            ident.map(Access::Field),
+        ));
+
         let field_access = atom.foldl(
             just(Token::Separator(Separator::Dot))
-                .ignore_then(select! {
-                    Token::Ident(ident) => ident
-                })
+                .ignore_then(access)
                 .repeated(),
-            |value, field| Expr::FieldAccess {
-                value: Box::new(value),
-                field: field.into(),
+            |value, access| match access {
+                Access::Field(field) => Expr::FieldAccess {
+                    value: Box::new(value),
+                    field: field.into(),
+                },
+                Access::ReferencedBy {
+                    table_name,
+                    field_name,
+                } => Expr::ReferencedBy {
+                    value: Box::new(value),
+                    table_name: table_name.into(),
+                    field_name: field_name.into(),
+                },
             },
         );
 
@@ -103,6 +266,15 @@ pub fn parse_expr<'token, 'src: 'token>()
             Token::Op(Op::LogicNot) => UnaryOp::LogicNot,
         };
 
+        // `is null` and `is not null`
+        let null_check = just(Token::Keyword(Keyword::Is))
+            .ignore_then(just(Token::Keyword(Keyword::Not)).or_not())
+            .then_ignore(just(Token::Ident("null")))
+            .map(|not| match not {
+                Some(_) => UnaryOp::IsNotNull,
+                None => UnaryOp::IsNull,
+            });
+
         let product_op = select! {
             Token::Op(Op::Mul) => BinaryOp::Math(MathOp::Mul),
             Token::Op(Op::Div) => BinaryOp::Math(MathOp::Div),
@@ -137,12 +309,16 @@ pub fn parse_expr<'token, 'src: 'token>()
         }
 
         let ops = field_access.pratt((
-            prefix(10, unary_op, |op, value, _extra| Expr::UnaryOp {
+            prefix(11, unary_op, |op, value, _extra| Expr::UnaryOp {
+                op,
+                value: Box::new(value),
+            }),
+            infix(left(10), product_op, binary_fold!()),
+            infix(left(9), sum_op, binary_fold!()),
+            postfix(8, null_check, |value, op, _extra| Expr::UnaryOp {
                 op,
                 value: Box::new(value),
             }),
-            infix(left(9), product_op, binary_fold!()),
-            infix(left(8), sum_op, binary_fold!()),
             infix(left(7), compare_op, binary_fold!()),
             infix(left(6), eq_op, binary_fold!()),
             infix(left(5), logic_op, binary_fold!()),
@@ -151,3 +327,12 @@ pub fn parse_expr<'token, 'src: 'token>()
         ops
     })
 }
+
+/// What follows a `.` after an expression
+enum Access<'src> {
+    Field(&'src str),
+    ReferencedBy {
+        table_name: &'src str,
+        field_name: &'src str,
+    },
+}
@@ -8,17 +8,30 @@ pub enum Token<'src> {
     Ident(&'src str),
     Op(Op),
     Number(&'src str),
+    /// Like `2h` or `1h30m`, see `db_core::duration`
+    Duration(&'src str),
     StringLiteral(&'src str),
     Separator(Separator),
 }
 
+/// Reserved words, which can not be used as names of tables or fields.
+///
+/// Words that only have a meaning at certain positions, like `desc` or `limit`,
+/// stay identifiers and are matched by the parser instead.
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Keyword {
     Query,
+    Join,
     Where,
     GroupBy,
+    Select,
+    OrderBy,
+    OnError,
+    ReferencedBy,
     True,
     False,
+    Is,
+    Not,
 }
 
 impl FromStr for Keyword {
@@ -27,10 +40,17 @@ impl FromStr for Keyword {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "query" => Ok(Self::Query),
+            "join" => Ok(Self::Join),
             "where" => Ok(Self::Where),
             "group_by" => Ok(Self::GroupBy),
+            "select" => Ok(Self::Select),
+            "order_by" => Ok(Self::OrderBy),
+            "on_error" => Ok(Self::OnError),
+            "referenced_by" => Ok(Self::ReferencedBy),
             "true" => Ok(Self::True),
             "false" => Ok(Self::False),
+            "is" => Ok(Self::Is),
+            "not" => Ok(Self::Not),
             _ => Err(()),
         }
     }
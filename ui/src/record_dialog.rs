@@ -4,7 +4,16 @@ use bytepack::{BytePacker, PackFormat};
 use chrono::{DateTime, Timelike, Utc};
 use db::{Db, Ulid};
 use db_core::{
-    defs::table::TableData, named::Named, record::RecordBytes, ty::FieldTy, value::FieldValue,
+    blob::{Blob, BlobContent},
+    decimal::Decimal,
+    defs::table::TableData,
+    duration,
+    float::Float,
+    json::Json,
+    named::Named,
+    record::RecordBytes,
+    ty::FieldTy,
+    value::FieldValue,
 };
 use dioxus::prelude::*;
 
@@ -55,7 +64,7 @@ pub fn RecordDialogButton(
         for field in values.iter() {
             if let Some(value) = &field.value() {
                 if let Some(field) = table.field(&field.name) {
-                    value.pack(field.offset, &mut packer);
+                    value.pack_as(&field.ty, field.offset, &mut packer);
                 }
             } else {
                 warn!("field {} has no value", field.name);
@@ -108,7 +117,7 @@ fn RecordFieldInput(field: Store<RecordField>) -> Element {
         });
     };
 
-    field.value().with(|value| {
+    let input = field.value().with(|value| {
         match value {
             RecordFieldValue::Timestamp(date) => {
                 let date = *date;
@@ -133,14 +142,14 @@ fn RecordFieldInput(field: Store<RecordField>) -> Element {
             RecordFieldValue::Bool(value) => rsx! {
                 Input { key: "{name()}-input", id: "{name()}", type: "checkbox", checked: *value, oninput: move |ev: Event<FormData>| { field.with_mut(|field| field.value = RecordFieldValue::Bool(ev.checked())) } }
             },
-            RecordFieldValue::Record { table_name, id} => {
+            RecordFieldValue::Record { table_name, id, .. } => {
                 let db = use_context::<Db>();
 
                 let table = db.table(&table_name).unwrap();
 
                 let display_field = table.main_display_field();
 
-                let records = db.get_all(&table_name).unwrap();
+                let records = db.get_all(&table_name).unwrap_or_default();
 
 
                 let options = records.iter().enumerate().map(|(idx, value)| {
@@ -186,14 +195,68 @@ fn RecordFieldInput(field: Store<RecordField>) -> Element {
                 // Input { class: if string_field.value.is_err() {"input invalid"} else {"input"}, key: "{name()}-input", id: "{name()}", placeholder: "{name()}", value: "{string_field.string}", oninput}
                 }
             },
+            RecordFieldValue::Enum { variants, index } => {
+                let options = variants.iter().enumerate().map(|(idx, variant)| {
+                    rsx! {
+                        SelectOption::<u32> {
+                            index: idx,
+                            value: idx as u32,
+                            text_value: "{variant}",
+                            "{variant}"
+                            SelectItemIndicator {}
+                        }
+                    }
+                });
+
+                rsx! {
+                    Label { key: "{name()}-label", html_for: "{name()}", "{name()}" }
+                    Select::<u32> { placeholder: "Select variant",
+                        value: *index,
+                        on_value_change: move |v| {
+                            field.value().with_mut(|value| {
+                                match value {
+                                    RecordFieldValue::Enum { index, .. } => *index = v,
+                                    _ => ()
+                                }
+                            })
+                        },
+
+                        SelectTrigger { aria_label: "Select Trigger", width: "12rem", SelectValue {} }
+                        SelectList { aria_label: "Select Variant",
+                            SelectGroup {
+                                SelectGroupLabel { "Variants" }
+                                {options}
+                            }
+                        }
+                    }
+                }
+            },
+        }
+    });
+
+    if field.null_ty().with(|ty| ty.is_none()) {
+        return input;
+    }
+
+    let is_null = *field.is_null().read();
+
+    rsx! {
+        Label { key: "{name()}-null-label", html_for: "{name()}-null", "{name()} empty" }
+        Input { key: "{name()}-null", id: "{name()}-null", type: "checkbox", checked: is_null, oninput: move |ev: Event<FormData>| { field.with_mut(|field| field.is_null = ev.checked()) } }
+
+        if !is_null {
+            {input}
         }
-    })
+    }
 }
 
 #[derive(Store)]
 struct RecordField {
     name: Arc<str>,
     value: RecordFieldValue,
+    /// The type of the null value of a nullable field, records handle their own nullability
+    null_ty: Option<FieldTy>,
+    is_null: bool,
 }
 
 #[derive(Store)]
@@ -205,6 +268,11 @@ enum RecordFieldValue {
     Record {
         table_name: Arc<str>,
         id: Option<Ulid>,
+        nullable: bool,
+    },
+    Enum {
+        variants: Arc<[Arc<str>]>,
+        index: Option<u32>,
     },
 }
 
@@ -216,43 +284,126 @@ struct RecordStringField {
 
 enum StringFieldType {
     IntI32,
+    IntI64,
+    F64,
+    Decimal,
+    Duration,
+    /// Comma separated elements of the type
+    List(FieldTy),
+    Json,
+    /// Path of the file to attach
+    Blob,
 }
 
 impl RecordField {
     pub fn new(name: Arc<str>, ty: FieldTy) -> Self {
+        let null_ty = match &ty {
+            FieldTy::Nullable(ty) if !matches!(**ty, FieldTy::RecordId { .. }) => {
+                Some(ty.as_ref().clone())
+            }
+            _ => None,
+        };
+
         Self {
             name,
-            value: match ty {
-                FieldTy::IntI32 => RecordFieldValue::StringField(RecordStringField::new(
-                    String::new(),
-                    StringFieldType::IntI32,
-                )),
-                FieldTy::Bool => RecordFieldValue::Bool(false),
-                FieldTy::Timestamp => RecordFieldValue::Timestamp(
-                    Utc::now()
-                        .with_second(0)
-                        .unwrap()
-                        .with_nanosecond(0)
-                        .unwrap(),
-                ),
-                FieldTy::Text => RecordFieldValue::Text(String::new()),
+            value: Self::initial_value(ty),
+            is_null: null_ty.is_some(),
+            null_ty,
+        }
+    }
+
+    fn initial_value(ty: FieldTy) -> RecordFieldValue {
+        match ty {
+            FieldTy::IntI32 => RecordFieldValue::StringField(RecordStringField::new(
+                String::new(),
+                StringFieldType::IntI32,
+            )),
+            FieldTy::IntI64 => RecordFieldValue::StringField(RecordStringField::new(
+                String::new(),
+                StringFieldType::IntI64,
+            )),
+            FieldTy::F64 => RecordFieldValue::StringField(RecordStringField::new(
+                String::new(),
+                StringFieldType::F64,
+            )),
+            FieldTy::Decimal => RecordFieldValue::StringField(RecordStringField::new(
+                String::new(),
+                StringFieldType::Decimal,
+            )),
+            FieldTy::Bool => RecordFieldValue::Bool(false),
+            FieldTy::Timestamp => RecordFieldValue::Timestamp(
+                Utc::now()
+                    .with_second(0)
+                    .unwrap()
+                    .with_nanosecond(0)
+                    .unwrap(),
+            ),
+            FieldTy::Duration => RecordFieldValue::StringField(RecordStringField::new(
+                String::new(),
+                StringFieldType::Duration,
+            )),
+            FieldTy::Text => RecordFieldValue::Text(String::new()),
+            FieldTy::RecordId { table_name } => RecordFieldValue::Record {
+                table_name,
+                id: None,
+                nullable: false,
+            },
+            FieldTy::Nullable(ty) => match *ty {
                 FieldTy::RecordId { table_name } => RecordFieldValue::Record {
                     table_name,
                     id: None,
+                    nullable: true,
                 },
+                ty => Self::initial_value(ty),
+            },
+            FieldTy::Enum { variants } => RecordFieldValue::Enum {
+                index: (!variants.is_empty()).then_some(0),
+                variants,
             },
+            FieldTy::List(ty) => RecordFieldValue::StringField(RecordStringField::new(
+                String::new(),
+                StringFieldType::List(*ty),
+            )),
+            FieldTy::Json => RecordFieldValue::StringField(RecordStringField::new(
+                String::new(),
+                StringFieldType::Json,
+            )),
+            FieldTy::Blob => RecordFieldValue::StringField(RecordStringField::new(
+                String::new(),
+                StringFieldType::Blob,
+            )),
         }
     }
 
     pub fn value(&self) -> Option<FieldValue> {
+        if let Some(ty) = &self.null_ty
+            && self.is_null
+        {
+            return Some(FieldValue::Null(ty.clone()));
+        }
+
         match &self.value {
             RecordFieldValue::Timestamp(date_time) => Some(FieldValue::Timestamp(*date_time)),
             RecordFieldValue::Text(text) => Some(FieldValue::Text(text.clone())),
             RecordFieldValue::StringField(field) => field.value.as_ref().ok().cloned(),
             RecordFieldValue::Bool(value) => Some(FieldValue::Bool(*value)),
-            RecordFieldValue::Record { id, table_name } => id.map(|id| FieldValue::RecordId {
+            RecordFieldValue::Record {
                 id,
-                table_name: table_name.clone(),
+                table_name,
+                nullable,
+            } => match id {
+                Some(id) => Some(FieldValue::RecordId {
+                    id: *id,
+                    table_name: table_name.clone(),
+                }),
+                None if *nullable => Some(FieldValue::Null(FieldTy::RecordId {
+                    table_name: table_name.clone(),
+                })),
+                None => None,
+            },
+            RecordFieldValue::Enum { variants, index } => index.map(|index| FieldValue::Enum {
+                index,
+                variants: variants.clone(),
             }),
         }
     }
@@ -275,7 +426,47 @@ impl RecordStringField {
         self.string = string;
 
         let value = match &self.ty {
-            StringFieldType::IntI32 => i32::from_str(&self.string).map(FieldValue::Int),
+            StringFieldType::IntI32 => i32::from_str(&self.string)
+                .map(FieldValue::Int)
+                .map_err(|err| format!("{:?}", err)),
+            StringFieldType::IntI64 => i64::from_str(&self.string)
+                .map(FieldValue::I64)
+                .map_err(|err| format!("{:?}", err)),
+            StringFieldType::F64 => f64::from_str(&self.string)
+                .map(|value| FieldValue::F64(Float(value)))
+                .map_err(|err| format!("{:?}", err)),
+            StringFieldType::Decimal => Decimal::from_str(self.string.trim())
+                .map(FieldValue::Decimal)
+                .map_err(|()| "Expected a number with at most 4 decimal places".to_string()),
+            StringFieldType::Duration => duration::parse(self.string.trim())
+                .map(FieldValue::Duration)
+                .ok_or_else(|| "Expected a duration like 1h30m".to_string()),
+            StringFieldType::List(ty) => self
+                .string
+                .split(',')
+                .map(str::trim)
+                .filter(|element| !element.is_empty())
+                .map(|element| {
+                    FieldValue::Text(element.into())
+                        .cast(ty)
+                        .ok_or_else(|| format!("Expected a list of {ty:?}"))
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map(|values| FieldValue::List {
+                    ty: Box::new(ty.clone()),
+                    values,
+                }),
+            StringFieldType::Json => Json::parse(&self.string)
+                .map(FieldValue::Json)
+                .ok_or_else(|| "Expected JSON like {\"key\": 1}".to_string()),
+            StringFieldType::Blob => std::fs::read(self.string.trim())
+                .map(|bytes| {
+                    FieldValue::Blob(Blob {
+                        mime: guess_mime(self.string.trim()).into(),
+                        content: BlobContent::Inline(bytes),
+                    })
+                })
+                .map_err(|err| err.to_string()),
             // StringFieldType::Number(num) => match num {
             //     NumberFieldType::U8 => u8::from_str(&self.string).map(FieldValue::U8),
             //     NumberFieldType::U16 => u16::from_str(&self.string).map(FieldValue::U16),
@@ -288,9 +479,25 @@ impl RecordStringField {
             //     NumberFieldType::I64 => i64::from_str(&self.string).map(FieldValue::I64),
             //     NumberFieldType::I128 => i128::from_str(&self.string).map(FieldValue::I128),
             // }
-        }
-        .map_err(|err| format!("{:?}", err));
+        };
 
         self.value = value;
     }
 }
+
+/// The MIME type of a file from its extension
+fn guess_mime(path: &str) -> &'static str {
+    let extension = path.rsplit_once('.').map(|(_, extension)| extension.to_ascii_lowercase());
+
+    match extension.as_deref() {
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("svg") => "image/svg+xml",
+        Some("pdf") => "application/pdf",
+        Some("txt") => "text/plain",
+        Some("json") => "application/json",
+        _ => "application/octet-stream",
+    }
+}
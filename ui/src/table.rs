@@ -1,7 +1,7 @@
 use chrono::{DateTime, Local, Utc};
 use db::{Db, Ulid};
 use db_core::{
-    defs::table::TableFieldData, named::Named, query::QueryResultRecords, record::RecordBytes, value::{FieldValue, Value}
+    defs::table::TableFieldData, duration, json::Json, named::Named, query::{QueryResultRecords, QueryResultRows}, record::RecordBytes, value::{FieldValue, Value}
 };
 use dioxus::prelude::*;
 
@@ -130,6 +130,42 @@ pub fn DataTable(
     )
 }
 
+/// Renders the rows of a query with a `select` clause, one column per selected expression.
+#[component]
+pub fn RowTable(rows: ReadSignal<QueryResultRows>) -> Element {
+    let db = use_context::<Db>();
+
+    rsx!(
+        table {
+            thead {
+                tr {
+                    th {"Id"}
+                    for column in rows.read().columns.iter() {
+                        th { "{column.name}" }
+                    }
+                }
+            }
+            tbody {
+                // Joined queries can return several rows for the same record
+                for (idx, row) in rows.read().rows.iter().enumerate() {
+                    tr { key: "{idx}",
+                        td {
+                            IdCard {
+                                id: row.id
+                            }
+                        }
+                        for value in row.values.iter() {
+                            td {
+                                "{value_to_string(value.clone(), &db)}"
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    )
+}
+
 pub fn extract_value(record: &RecordBytes, field: &TableFieldData, db: &Db) -> String {
     match record.get_field(field) {
         Some(value) => field_value_to_string(value, db),
@@ -141,16 +177,21 @@ pub fn value_to_string(value: Value, db: &Db) -> String {
     match value {
         Value::Field(value) => field_value_to_string(value, db),
         Value::Record { table, record } => format!("record data {}:{}", table.name, record.id()),
+        Value::Records { table, records } => format!("{} records of {}", records.len(), table.name),
     }
 }
 
 pub fn field_value_to_string(value: FieldValue, db: &Db) -> String {
     match value {
         FieldValue::Int(value) => value.to_string(),
+        FieldValue::I64(value) => value.to_string(),
+        FieldValue::F64(value) => value.to_string(),
+        FieldValue::Decimal(value) => value.to_string(),
         FieldValue::Bool(value) => value.to_string(),
         FieldValue::Timestamp(date_time) => DateTime::<Local>::from(date_time)
             .format("%d.%m.%Y %H:%M:%S")
             .to_string(),
+        FieldValue::Duration(value) => duration::format(&value),
         FieldValue::Text(value) => value,
         FieldValue::RecordId { id, table_name } => {
             let Some(table) = db.table(&table_name) else {
@@ -160,7 +201,7 @@ pub fn field_value_to_string(value: FieldValue, db: &Db) -> String {
             let display_field = table.main_display_field();
 
             if let Some(field) = display_field {
-                let Some(value) = db.get(&table_name, id) else {
+                let Ok(Some(value)) = db.get(&table_name, id) else {
                     return format!("<ERROR: record '{table_name}:{id}' does not exist>");
                 };
 
@@ -173,5 +214,39 @@ pub fn field_value_to_string(value: FieldValue, db: &Db) -> String {
                 id_text(id)
             }
         }
+        FieldValue::Null(_) => String::new(),
+        FieldValue::Enum { .. } => value.variant().unwrap_or_default().to_owned(),
+        FieldValue::List { values, .. } => values
+            .into_iter()
+            .map(|value| field_value_to_string(value, db))
+            .collect::<Vec<_>>()
+            .join(", "),
+        FieldValue::Json(value) => json_to_string(&value, false),
+        FieldValue::Blob(blob) => format!("{}, {} bytes", blob.mime, blob.len()),
+    }
+}
+
+/// JSON with spaces after separators, strings outside of arrays and objects are shown without quotes
+fn json_to_string(value: &Json, nested: bool) -> String {
+    match value {
+        Json::Null if !nested => String::new(),
+        Json::String(value) if !nested => value.clone(),
+        Json::Array(values) => {
+            let values = values
+                .iter()
+                .map(|value| json_to_string(value, true))
+                .collect::<Vec<_>>();
+
+            format!("[{}]", values.join(", "))
+        }
+        Json::Object(entries) => {
+            let entries = entries
+                .iter()
+                .map(|(key, value)| format!("{key}: {}", json_to_string(value, true)))
+                .collect::<Vec<_>>();
+
+            format!("{{{}}}", entries.join(", "))
+        }
+        value => value.to_string(),
     }
 }
@@ -1,11 +1,16 @@
+use std::sync::Arc;
+
 use db_core::{
-    defs::table::{TableDef, TableFieldDef},
+    defs::{
+        index::IndexOnDelete,
+        table::{TableDef, TableFieldDef},
+    },
     named::Named,
     ty::FieldTy,
 };
 use dioxus::prelude::*;
 use dioxus_free_icons::{
-    icons::fa_solid_icons::{FaEye, FaEyeSlash, FaHashtag},
+    icons::fa_solid_icons::{FaEye, FaEyeSlash, FaHashtag, FaKey, FaList, FaQuestion},
     Icon,
 };
 
@@ -35,10 +40,50 @@ pub fn TableDialogButton(on_submit: Callback<Named<TableDef>, ()>) -> Element {
 
         let fields = fields
             .iter()
-            .map(|field| Named::new(field.name.clone(), TableFieldDef { ty: field.ty.clone(), has_index: field.has_index }))
-            .collect();
+            .map(|field| {
+                let ty = match &field.ty {
+                    // Variants are edited as a comma separated list, empty and repeated names are dropped
+                    FieldTy::Enum { variants } => {
+                        let mut names = Vec::<Arc<str>>::new();
+
+                        for variant in variants.iter().map(|variant| variant.trim()) {
+                            if !variant.is_empty() && !names.iter().any(|name| name.as_ref() == variant) {
+                                names.push(variant.into());
+                            }
+                        }
 
-        let table = TableDef { fields, main_display_field: main_display_field_idx() };
+                        FieldTy::Enum { variants: names.into() }
+                    }
+                    ty => ty.clone(),
+                };
+
+                let ty = if field.list {
+                    FieldTy::List(Box::new(ty))
+                } else {
+                    ty
+                };
+
+                // Setting a reference to none on delete requires a nullable field, lists remove the reference instead
+                let ty = if field.nullable || !field.list && matches!(field.on_delete, IndexOnDelete::SetNone) {
+                    FieldTy::Nullable(Box::new(ty))
+                } else {
+                    ty
+                };
+
+                Named::new(field.name.clone(), TableFieldDef { ty, has_index: field.has_index, on_delete: field.on_delete, unique: field.unique })
+            })
+            .collect::<Vec<_>>();
+
+        // An enum without variants could never hold a value, the dialog stays open until some are entered
+        let has_empty_enum = fields.iter().any(|field| {
+            matches!(field.value.ty.indexed().non_null(), FieldTy::Enum { variants } if variants.is_empty())
+        });
+
+        if has_empty_enum {
+            return;
+        }
+
+        let table = TableDef { fields, main_display_field: main_display_field_idx(), unique: Vec::new(), indices: Vec::new() };
 
         on_submit(Named {
             name: name.clone().into(),
@@ -81,7 +126,7 @@ pub fn TableDialogButton(on_submit: Callback<Named<TableDef>, ()>) -> Element {
                             variant: ButtonVariant::Outline,
                             onclick: move |ev: Event<MouseData>| {
                                 ev.prevent_default();
-                                fields.push(FieldStore { name: String::new(), ty: FieldTy::Text, has_index: false });
+                                fields.push(FieldStore { name: String::new(), ty: FieldTy::Text, has_index: false, on_delete: IndexOnDelete::Cascase, unique: false, nullable: false, list: false });
                             },
                             "New Field"
                         }
@@ -122,8 +167,38 @@ pub fn TableDialogButton(on_submit: Callback<Named<TableDef>, ()>) -> Element {
                                     icon: FaHashtag,
                                 }
                             }
+                            Button {
+                                onclick: move |_| field.unique().toggle(),
+                                variant: if field.unique()() { ButtonVariant::Primary } else { ButtonVariant::Secondary },
+                                Icon {
+                                    width: 12,
+                                    height: 12,
+                                    icon: FaKey,
+                                }
+                            }
+                            Button {
+                                onclick: move |_| field.nullable().toggle(),
+                                variant: if field.nullable()() { ButtonVariant::Primary } else { ButtonVariant::Secondary },
+                                Icon {
+                                    width: 12,
+                                    height: 12,
+                                    icon: FaQuestion,
+                                }
+                            }
+                            Button {
+                                onclick: move |_| field.list().toggle(),
+                                variant: if field.list()() { ButtonVariant::Primary } else { ButtonVariant::Secondary },
+                                Icon {
+                                    width: 12,
+                                    height: 12,
+                                    icon: FaList,
+                                }
+                            }
                             Input { placeholder: "Name", value: "{field.name()}", autocorrect: "off", flex: "1", oninput: {let mut name = field.name(); move |ev: Event<FormData>| {name.set(ev.value())}} }
                             FieldTypeSelect { value: field.ty() }
+                            if let FieldTy::RecordId { .. } = field.ty()() {
+                                OnDeleteSelect { value: field.on_delete() }
+                            }
                         }
                     }
 
@@ -144,6 +219,12 @@ struct FieldStore {
     name: String,
     ty: FieldTy,
     has_index: bool,
+    on_delete: IndexOnDelete,
+    unique: bool,
+    /// The field may be left empty
+    nullable: bool,
+    /// The field holds any number of values of `ty`
+    list: bool,
 }
 
 #[component]
@@ -160,6 +241,9 @@ pub fn FieldTypeSelect(value: Store<FieldTy>) -> Element {
 
     let numbers = [
         FieldTy::IntI32,
+        FieldTy::IntI64,
+        FieldTy::F64,
+        FieldTy::Decimal,
         // FieldType::Number(NumberFieldType::U16),
         // FieldType::Number(NumberFieldType::U32),
         // FieldType::Number(NumberFieldType::U64),
@@ -190,6 +274,17 @@ pub fn FieldTypeSelect(value: Store<FieldTy>) -> Element {
                 oninput: move |ev: Event<FormData>| value.set(FieldTy::RecordId {table_name: ev.value().into()})
             }
         }
+    } else if let FieldTy::Enum { variants } = value() {
+        let variants = variants.join(",");
+
+        rsx! {
+            Input {
+                value: "{variants}",
+                placeholder: "open,in progress,done",
+                autocorrect: "off",
+                oninput: move |ev: Event<FormData>| value.set(FieldTy::Enum { variants: ev.value().split(',').map(Into::into).collect() })
+            }
+        }
     } else {
         rsx! {
             Select::<FieldTy> { placeholder: "Select a type",
@@ -217,11 +312,39 @@ pub fn FieldTypeSelect(value: Store<FieldTy>) -> Element {
                             "Timestamp"
                             SelectItemIndicator {}
                         }
+                        SelectOption::<FieldTy> {
+                            index: numbers.len() + 2,
+                            value: FieldTy::Duration,
+                            text_value: "Duration",
+                            "Duration"
+                            SelectItemIndicator {}
+                        }
+                        SelectOption::<FieldTy> {
+                            index: numbers.len() + 3,
+                            value: FieldTy::Enum { variants: Vec::new().into() },
+                            text_value: "Enum",
+                            "Enum"
+                            SelectItemIndicator {}
+                        }
+                        SelectOption::<FieldTy> {
+                            index: numbers.len() + 4,
+                            value: FieldTy::Json,
+                            text_value: "JSON",
+                            "JSON"
+                            SelectItemIndicator {}
+                        }
+                        SelectOption::<FieldTy> {
+                            index: numbers.len() + 5,
+                            value: FieldTy::Blob,
+                            text_value: "Blob",
+                            "Blob"
+                            SelectItemIndicator {}
+                        }
                     }
                     SelectGroup {
                         SelectGroupLabel { "Record" }
                         SelectOption::<FieldTy> {
-                            index: numbers.len() + 2,
+                            index: numbers.len() + 6,
                             value: FieldTy::RecordId { table_name: String::new().into() },
                             text_value: "Record",
                             "Record"
@@ -233,3 +356,46 @@ pub fn FieldTypeSelect(value: Store<FieldTy>) -> Element {
         }
     }
 }
+
+#[component]
+pub fn OnDeleteSelect(value: Store<IndexOnDelete>) -> Element {
+    let mut picker_value = use_signal(|| Some(Some(value())));
+
+    use_effect(move || {
+        if let Some(Some(on_delete)) = *picker_value.read() {
+            value.set(on_delete);
+        }
+    });
+
+    let options = [
+        (IndexOnDelete::Cascase, "Cascade"),
+        (IndexOnDelete::SetNone, "Set None"),
+        (IndexOnDelete::Restrict, "Restrict"),
+    ];
+
+    let options = options.iter().enumerate().map(|(idx, (value, text))| {
+        rsx! {
+            SelectOption::<IndexOnDelete> {
+                index: idx,
+                value: *value,
+                text_value: "{text}",
+                "{text}"
+                SelectItemIndicator {}
+            }
+        }
+    });
+
+    rsx! {
+        Select::<IndexOnDelete> { placeholder: "On delete",
+            value: picker_value,
+            on_value_change: move |v| picker_value.set(Some(v)),
+            SelectTrigger { aria_label: "Select Trigger", width: "8rem", SelectValue {} }
+            SelectList { aria_label: "Select On Delete",
+                SelectGroup {
+                    SelectGroupLabel { "On Delete" }
+                    {options}
+                }
+            }
+        }
+    }
+}